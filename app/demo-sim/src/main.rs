@@ -0,0 +1,16 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel for the hosted simulator. This is an ordinary Linux program; see the
+//! kernel's `arch::sim` module for how it works.
+
+fn main() {
+    // The simulator's tick divisor is in microseconds.
+    const MICROS_PER_TICK: u32 = 1000;
+
+    unsafe {
+        kern::arch::load_image();
+        kern::startup::start_kernel(MICROS_PER_TICK)
+    }
+}
@@ -7,6 +7,7 @@ use convert_case::{Case, Casing};
 use indexmap::IndexMap;
 use multimap::MultiMap;
 use serde::Deserialize;
+use std::cmp::Ordering::Less;
 use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fmt::Write;
@@ -162,6 +163,83 @@ struct I2cSensors {
     speed: usize,
 
     names: Option<Vec<String>>,
+
+    /// alarm thresholds, if any, by kind of sensor
+    thresholds: Option<I2cThresholds>,
+}
+
+//
+// Thresholds for a kind of sensor apply to every sensor of that kind on the
+// device.
+//
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct I2cThresholds {
+    temperature: Option<I2cThreshold>,
+    power: Option<I2cThreshold>,
+    current: Option<I2cThreshold>,
+    voltage: Option<I2cThreshold>,
+    speed: Option<I2cThreshold>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct I2cThreshold {
+    critical_low: Option<f32>,
+    warning_low: Option<f32>,
+    warning_high: Option<f32>,
+    critical_high: Option<f32>,
+}
+
+impl I2cThreshold {
+    fn validate(&self, d: &I2cDevice, kind: Sensor) -> Result<()> {
+        let levels = [
+            self.critical_low,
+            self.warning_low,
+            self.warning_high,
+            self.critical_high,
+        ];
+        let present = levels.iter().flatten().collect::<Vec<_>>();
+
+        if present.is_empty() {
+            bail!(
+                "{} {} thresholds for device at {:#x} are empty",
+                d.device,
+                kind,
+                d.address
+            );
+        }
+
+        let ascending = |w: &[&f32]| w[0].partial_cmp(w[1]) == Some(Less);
+        if !present.windows(2).all(ascending) {
+            bail!(
+                "{} {} thresholds for device at {:#x} are out of order \
+                (must be critical-low < warning-low < warning-high < \
+                critical-high)",
+                d.device,
+                kind,
+                d.address
+            );
+        }
+
+        Ok(())
+    }
+
+    fn generate(&self) -> String {
+        let level = |l: Option<f32>| match l {
+            Some(v) => format!("Some({:?})", v),
+            None => "None".to_string(),
+        };
+
+        format!(
+            "task_sensor_api::Thresholds {{ critical_low: {}, \
+            warning_low: {}, warning_high: {}, critical_high: {} }}",
+            level(self.critical_low),
+            level(self.warning_low),
+            level(self.warning_high),
+            level(self.critical_high),
+        )
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -980,11 +1058,26 @@ impl ConfigGenerator {
         let mut bykind = MultiMap::new();
 
         let mut sensors = vec![];
+        let mut thresholds = vec![];
+        let mut info = vec![];
 
         let mut add_sensor = |kind, d: &I2cDevice, idx: usize| {
             let id = sensors.len();
             sensors.push(kind);
 
+            let t = d.sensors.as_ref().unwrap().thresholds.as_ref();
+            let threshold = t.and_then(|t| match kind {
+                Sensor::Temperature => t.temperature.as_ref(),
+                Sensor::Power => t.power.as_ref(),
+                Sensor::Current => t.current.as_ref(),
+                Sensor::Voltage => t.voltage.as_ref(),
+                Sensor::Speed => t.speed.as_ref(),
+            });
+
+            if let Some(threshold) = threshold {
+                thresholds.push((id, threshold.clone()));
+            }
+
             let name: Option<String> = if let Some(pmbus) = &d.pmbus {
                 if let Some(rails) = &pmbus.rails {
                     if idx < rails.len() {
@@ -1011,6 +1104,8 @@ impl ConfigGenerator {
                 }
             };
 
+            info.push((kind, name.clone(), d.device.clone(), d.refdes.clone()));
+
             if let Some(bus) = &d.bus {
                 bybus.insert((d.device.clone(), bus.clone(), kind), id);
 
@@ -1032,6 +1127,32 @@ impl ConfigGenerator {
 
         for d in &self.devices {
             if let Some(s) = &d.sensors {
+                if let Some(t) = &s.thresholds {
+                    let kinds = [
+                        (Sensor::Temperature, &t.temperature, s.temperature),
+                        (Sensor::Power, &t.power, s.power),
+                        (Sensor::Current, &t.current, s.current),
+                        (Sensor::Voltage, &t.voltage, s.voltage),
+                        (Sensor::Speed, &t.speed, s.speed),
+                    ];
+
+                    for (kind, threshold, count) in kinds {
+                        if let Some(threshold) = threshold {
+                            if count == 0 {
+                                bail!(
+                                    "{} at {:#x} has {} thresholds, \
+                                    but no {} sensors",
+                                    d.device,
+                                    d.address,
+                                    kind,
+                                    kind
+                                );
+                            }
+                            threshold.validate(d, kind)?;
+                        }
+                    }
+                }
+
                 for i in 0..s.temperature {
                     add_sensor(Sensor::Temperature, d, i);
                 }
@@ -1066,6 +1187,57 @@ impl ConfigGenerator {
             sensors.len()
         )?;
 
+        write!(
+            &mut self.output,
+            r##"
+        #[allow(dead_code)]
+        pub const THRESHOLDS: [(SensorId, task_sensor_api::Thresholds); {}] = [
+"##,
+            thresholds.len()
+        )?;
+
+        for (id, threshold) in &thresholds {
+            writeln!(
+                &mut self.output,
+                "            (SensorId({}), {}),",
+                id,
+                threshold.generate()
+            )?;
+        }
+
+        writeln!(&mut self.output, "        ];")?;
+
+        let string = |s: &Option<String>| match s {
+            Some(s) => format!("Some({:?})", s),
+            None => "None".to_string(),
+        };
+
+        write!(
+            &mut self.output,
+            r##"
+        #[allow(dead_code)]
+        pub const SENSOR_INFO: [task_sensor_api::SensorInfo; NUM_SENSORS] = [
+"##
+        )?;
+
+        for (kind, name, device, refdes) in &info {
+            writeln!(
+                &mut self.output,
+                r##"            task_sensor_api::SensorInfo {{
+                kind: task_sensor_api::SensorKind::{:?},
+                name: {},
+                device: {:?},
+                refdes: {},
+            }},"##,
+                kind,
+                string(name),
+                device,
+                string(refdes),
+            )?;
+        }
+
+        writeln!(&mut self.output, "        ];")?;
+
         for ((device, kind), ids) in bydevice.iter_all() {
             self.emit_sensor(device, &format!("{}", kind), ids)?;
         }
@@ -7,11 +7,17 @@ use serde::de::DeserializeOwned;
 use std::collections::BTreeMap;
 use std::env;
 
+/// Target triple used to build images for the hosted simulator.
+pub const SIM_TARGET: &str = "i686-unknown-linux-gnu";
+
 /// Exposes the CPU's M-profile architecture version. This isn't available in
 /// rustc's standard environment.
 ///
 /// This will set one of `cfg(armv6m`), `cfg(armv7m)`, or `cfg(armv8m)`
 /// depending on the value of the `TARGET` environment variable.
+///
+/// The hosted simulator target (`i686-unknown-linux-gnu`) has no M-profile
+/// version at all; for it, this sets `cfg(hubris_sim)` instead.
 pub fn expose_m_profile() {
     let target = env::var("TARGET").unwrap();
 
@@ -22,6 +28,8 @@ pub fn expose_m_profile() {
         println!("cargo:rustc-cfg=armv7m");
     } else if target.starts_with("thumbv8m") {
         println!("cargo:rustc-cfg=armv8m");
+    } else if target == SIM_TARGET {
+        println!("cargo:rustc-cfg=hubris_sim");
     } else {
         println!("Don't know the target {}", target);
         std::process::exit(1);
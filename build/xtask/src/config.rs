@@ -11,6 +11,9 @@ use anyhow::{anyhow, bail, Context, Result};
 use indexmap::IndexMap;
 use serde::Deserialize;
 
+/// Target triple for the hosted (Linux, i686) simulator.
+pub const SIM_TARGET: &str = "i686-unknown-linux-gnu";
+
 /// A `RawConfig` represents an `app.toml` file that has been deserialized,
 /// but may not be ready for use.  In particular, we use the `chip` field
 /// to load a second file containing peripheral register addresses.
@@ -33,6 +36,8 @@ struct RawConfig {
     kernel: Kernel,
     tasks: IndexMap<String, Task>,
     #[serde(default)]
+    shared_regions: IndexMap<String, SharedRegion>,
+    #[serde(default)]
     extratext: IndexMap<String, Peripheral>,
     #[serde(default)]
     config: Option<ordered_toml::Value>,
@@ -53,6 +58,7 @@ pub struct Config {
     pub stacksize: Option<u32>,
     pub kernel: Kernel,
     pub outputs: IndexMap<String, Vec<Output>>,
+    pub shared_regions: IndexMap<String, SharedRegion>,
     pub tasks: IndexMap<String, Task>,
     pub peripherals: IndexMap<String, Peripheral>,
     pub extratext: IndexMap<String, Peripheral>,
@@ -69,6 +75,17 @@ impl Config {
         if toml.tasks.contains_key("kernel") {
             bail!("'kernel' is reserved and cannot be used as a task name");
         }
+        for (name, shared) in &toml.shared_regions {
+            for task in shared.tasks.keys() {
+                if !toml.tasks.contains_key(task) {
+                    bail!(
+                        "shared region {} is mapped into unknown task {}",
+                        name,
+                        task
+                    );
+                }
+            }
+        }
 
         let mut hasher = DefaultHasher::new();
         hasher.write(&cfg_contents);
@@ -119,6 +136,7 @@ impl Config {
             stacksize: toml.stacksize,
             kernel: toml.kernel,
             outputs,
+            shared_regions: toml.shared_regions,
             tasks: toml.tasks,
             peripherals,
             extratext: toml.extratext,
@@ -364,6 +382,9 @@ impl Config {
         // ARMv8-M does not.
         match self.target.as_str() {
             "thumbv8m.main-none-eabihf" => MpuAlignment::Chunk(32),
+            // The simulator doesn't have an MPU, but mimicking ARMv8-M keeps
+            // images small while preserving word alignment.
+            SIM_TARGET => MpuAlignment::Chunk(32),
             "thumbv7em-none-eabihf" | "thumbv6m-none-eabi" => {
                 MpuAlignment::PowerOfTwo
             }
@@ -371,6 +392,13 @@ impl Config {
         }
     }
 
+    /// Checks whether this app is built for the hosted simulator rather than
+    /// real hardware. The simulator kernel is a host executable, so several
+    /// packaging steps are skipped or altered for it.
+    pub fn is_simulated(&self) -> bool {
+        self.target == SIM_TARGET
+    }
+
     /// Checks whether the given chip's MPU requires power-of-two sized regions
     pub fn mpu_power_of_two_required(&self) -> bool {
         self.mpu_alignment() == MpuAlignment::PowerOfTwo
@@ -476,6 +504,13 @@ pub struct Kernel {
     pub stacksize: Option<u32>,
     #[serde(default)]
     pub features: Vec<String>,
+    /// Number of timers each task gets.
+    #[serde(default = "default_timers_per_task")]
+    pub timers_per_task: usize,
+}
+
+fn default_timers_per_task() -> usize {
+    1
 }
 
 fn default_name() -> String {
@@ -512,6 +547,11 @@ pub struct Task {
     #[serde(default)]
     pub start: bool,
     #[serde(default)]
+    pub sched_class: SchedClass,
+    /// Tasks that may send to this one. If absent, any task may.
+    #[serde(default)]
+    pub allowed_callers: Option<Vec<String>>,
+    #[serde(default)]
     pub features: Vec<String>,
     #[serde(default)]
     pub interrupts: IndexMap<String, u32>,
@@ -525,6 +565,49 @@ pub struct Task {
     pub uses_secure_entry: bool,
 }
 
+/// A region of memory mapped into several tasks, so that they can share data
+/// without copying it through the kernel.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SharedRegion {
+    /// Size of the region in bytes. This is rounded up to suit the MPU.
+    pub size: u32,
+    /// Memory to allocate the region from.
+    #[serde(default = "default_shared_memory")]
+    pub memory: String,
+    /// Tasks the region is mapped into, and their access to it.
+    pub tasks: IndexMap<String, SharedAccess>,
+}
+
+fn default_shared_memory() -> String {
+    "ram".to_string()
+}
+
+/// A task's access to a shared region.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SharedAccess {
+    Read,
+    ReadWrite,
+}
+
+/// Scheduling class of a task, which decides how it is ordered against other
+/// runnable tasks at the same priority.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedClass {
+    /// Round-robin with the other tasks at this priority.
+    Priority,
+    /// Earliest armed timer deadline first, ahead of round-robin tasks.
+    Deadline,
+}
+
+impl Default for SchedClass {
+    fn default() -> Self {
+        Self::Priority
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Peripheral {
@@ -18,7 +18,7 @@ use path_slash::PathBufExt;
 use serde::Serialize;
 
 use crate::{
-    config::{BuildConfig, Config},
+    config::{BuildConfig, Config, SchedClass, SharedAccess},
     elf,
     sizes::load_task_size,
     task_slot,
@@ -761,6 +761,13 @@ fn link_task(
 ) -> Result<()> {
     println!("linking task '{}'", name);
     let task_toml = &cfg.toml.tasks[name];
+    let shared = cfg
+        .toml
+        .shared_regions
+        .iter()
+        .filter(|(_, s)| s.tasks.contains_key(name))
+        .map(|(region, _)| (region.as_str(), allocs.shared[region].clone()))
+        .collect();
     generate_task_linker_script(
         "memory.x",
         &allocs.tasks[name],
@@ -770,6 +777,7 @@ fn link_task(
         })?,
         &cfg.toml.all_regions("flash".to_string())?,
         image_name,
+        &shared,
     )
     .context(format!("failed to generate linker script for {}", name))?;
     fs::copy("build/task-link.x", "target/link.x")?;
@@ -797,6 +805,15 @@ fn link_dummy_task(cfg: &PackageConfig, name: &str) -> Result<()> {
         .into_iter()
         .collect();
 
+    // Shared regions haven't been allocated yet, so point them at nothing.
+    let shared = cfg
+        .toml
+        .shared_regions
+        .iter()
+        .filter(|(_, s)| s.tasks.contains_key(name))
+        .map(|(region, _)| (region.as_str(), 0..0))
+        .collect();
+
     generate_task_linker_script(
         "memory.x",
         &memories, // ALL THE SPACE
@@ -806,6 +823,7 @@ fn link_dummy_task(cfg: &PackageConfig, name: &str) -> Result<()> {
         })?,
         &cfg.toml.all_regions("flash".to_string())?,
         &cfg.toml.image_names[0],
+        &shared,
     )
     .context(format!("failed to generate linker script for {}", name))?;
     fs::copy("build/task-tlink.x", "target/link.x")?;
@@ -872,13 +890,8 @@ fn build_kernel(
     all_output_sections.hash(&mut image_id);
 
     // Format the descriptors for the kernel build.
-    let kconfig = make_kconfig(
-        &cfg.toml,
-        &allocs.tasks,
-        entry_points,
-        image_name,
-        secure,
-    )?;
+    let kconfig =
+        make_kconfig(&cfg.toml, allocs, entry_points, image_name, secure)?;
     let kconfig = ron::ser::to_string(&kconfig)?;
 
     kconfig.hash(&mut image_id);
@@ -905,6 +918,19 @@ fn build_kernel(
         Some(&cfg.sysroot),
     );
     build(cfg, "kernel", build_config, false)?;
+
+    if cfg.toml.is_simulated() {
+        // The simulator kernel is a host executable: it doesn't live in the
+        // image, and instead loads the task image at startup (see the `sim`
+        // module in the kernel). It has no image header and no entry point
+        // within the image.
+        std::fs::copy(
+            &cfg.dist_file("kernel"),
+            cfg.img_file("kernel", image_name),
+        )?;
+        return Ok((0, BTreeMap::default()));
+    }
+
     if update_image_header(
         &cfg.dist_file("kernel"),
         &cfg.img_file("kernel.modified", image_name),
@@ -955,8 +981,10 @@ fn update_image_header(
     if elf.header.container()? != Container::Little {
         bail!("where did you get a big-endian image?");
     }
-    if elf.header.e_machine != goblin::elf::header::EM_ARM {
-        bail!("this is not an ARM file");
+    match elf.header.e_machine {
+        // i386 ELF files are produced by simulator builds.
+        goblin::elf::header::EM_ARM | goblin::elf::header::EM_386 => (),
+        _ => bail!("this is not an ARM file"),
     }
 
     // Good enough.
@@ -1130,6 +1158,7 @@ fn generate_task_linker_script(
     stacksize: u32,
     images: &IndexMap<String, Range<u32>>,
     image_name: &str,
+    shared: &BTreeMap<&str, Range<u32>>,
 ) -> Result<()> {
     // Put the linker script somewhere the linker can find it
     let mut linkscr = File::create(Path::new(&format!("target/{}", name)))?;
@@ -1186,6 +1215,20 @@ fn generate_task_linker_script(
             out.end
         )?;
     }
+    for (name, range) in shared {
+        writeln!(
+            linkscr,
+            "__SHARED_{}_BASE = {:#010x};",
+            name.to_ascii_uppercase(),
+            range.start
+        )?;
+        writeln!(
+            linkscr,
+            "__SHARED_{}_END = {:#010x};",
+            name.to_ascii_uppercase(),
+            range.end
+        )?;
+    }
 
     // The task may have defined additional section-to-memory mappings.
     if let Some(map) = sections {
@@ -1308,24 +1351,42 @@ fn build(
         .iter()
         .map(|r| format!(" --remap-path-prefix={}={}", r.0.display(), r.1))
         .collect();
+    // The simulator kernel is an ordinary host executable, linked and loaded
+    // by the host toolchain; everything else is laid out by our own scripts.
+    let hosted = cfg.toml.is_simulated() && !reloc;
+    let link_flags = if hosted {
+        ""
+    } else {
+        "-C link-arg=-z -C link-arg=common-page-size=0x20 \
+         -C link-arg=-z -C link-arg=max-page-size=0x20"
+    };
+    // Simulated tasks are still freestanding images: keep the host's C
+    // runtime and unwinder out of them.
+    let sim_task_flags = if cfg.toml.is_simulated() && reloc {
+        "-C panic=abort -C relocation-model=static \
+         -C link-arg=-nostartfiles -C link-arg=-nostdlib"
+    } else {
+        ""
+    };
     cmd.env(
         "RUSTFLAGS",
         &format!(
-            "-C link-arg=-z -C link-arg=common-page-size=0x20 \
-             -C link-arg=-z -C link-arg=max-page-size=0x20 \
+            "{} {} \
              -C llvm-args=--enable-machine-outliner=never \
              -C overflow-checks=y \
              -C metadata={} \
              {}
              ",
-            cfg.link_script_hash, remap_path_prefix,
+            link_flags, sim_task_flags, cfg.link_script_hash, remap_path_prefix,
         ),
     );
     cmd.arg("--");
-    cmd.arg("-C")
-        .arg("link-arg=-Tlink.x")
-        .arg("-L")
-        .arg(format!("{}", cargo_out.display()));
+    if !hosted {
+        cmd.arg("-C")
+            .arg("link-arg=-Tlink.x")
+            .arg("-L")
+            .arg(format!("{}", cargo_out.display()));
+    }
     if reloc {
         cmd.arg("-C").arg("link-arg=-r");
     }
@@ -1431,6 +1492,7 @@ fn link(
         "thumbv6m-none-eabi"
         | "thumbv7em-none-eabihf"
         | "thumbv8m.main-none-eabihf" => "armelf",
+        crate::config::SIM_TARGET => "elf_i386",
         _ => bail!("No target emulation for '{}'", cfg.toml.target),
     };
     cmd.arg(src_file);
@@ -1461,6 +1523,32 @@ pub struct Allocations {
     pub kernel: BTreeMap<String, Range<u32>>,
     /// Map from task-name to memory-name to address-range
     pub tasks: BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    /// Map from shared-region-name to address-range
+    pub shared: BTreeMap<String, Range<u32>>,
+}
+
+impl Allocations {
+    fn record(&mut self, claimant: Claimant<'_>, mem: &str, range: Range<u32>) {
+        match claimant {
+            Claimant::Task(task) => {
+                self.tasks
+                    .entry(task.to_string())
+                    .or_default()
+                    .insert(mem.to_string(), range);
+            }
+            Claimant::Shared(name) => {
+                self.shared.insert(name.to_string(), range);
+            }
+        }
+    }
+}
+
+/// Something other than the kernel that needs address space: either a task, or
+/// a region shared between tasks.
+#[derive(Copy, Clone, Debug)]
+enum Claimant<'a> {
+    Task(&'a str),
+    Shared(&'a str),
 }
 
 /// Allocates address space from all regions for the kernel and all tasks.
@@ -1508,7 +1596,8 @@ pub fn allocate_all(
     // We keep kernel and task requests separate so we can always service the
     // kernel first.
     //
-    // The task map is: memory name -> allocation size -> queue of task name.
+    // The task map is: memory name -> allocation size -> queue of task (or
+    // shared region) name.
     // The kernel map is: memory name -> allocation size
     let kernel = &toml.kernel;
     let tasks = &toml.tasks;
@@ -1522,8 +1611,10 @@ pub fn allocate_all(
         let mut free = toml.memories(&image_name)?;
         let kernel_requests = &kernel.requires;
 
-        let mut task_requests: BTreeMap<&str, BTreeMap<u32, VecDeque<&str>>> =
-            BTreeMap::new();
+        let mut task_requests: BTreeMap<
+            &str,
+            BTreeMap<u32, VecDeque<Claimant<'_>>>,
+        > = BTreeMap::new();
 
         for name in tasks.keys() {
             for (mem, amt) in task_sizes[name.as_str()].iter() {
@@ -1540,8 +1631,27 @@ pub fn allocate_all(
                     .or_default()
                     .entry(bytes.try_into().unwrap())
                     .or_default()
-                    .push_back(name.as_str());
+                    .push_back(Claimant::Task(name.as_str()));
+            }
+        }
+
+        // Shared regions are packed in alongside the tasks.
+        for (name, shared) in &toml.shared_regions {
+            if !free.contains_key(&shared.memory) {
+                bail!(
+                    "shared region {}: no memory named {}",
+                    name,
+                    shared.memory
+                );
             }
+            let bytes =
+                toml.suggest_memory_region_size(name, u64::from(shared.size));
+            task_requests
+                .entry(shared.memory.as_str())
+                .or_default()
+                .entry(bytes.try_into().unwrap())
+                .or_default()
+                .push_back(Claimant::Shared(name.as_str()));
         }
 
         // Okay! Do memory types one by one, fitting kernel first.
@@ -1550,7 +1660,7 @@ pub fn allocate_all(
             let mut t_reqs = task_requests.get_mut(region.as_str());
 
             fn reqs_map_not_empty(
-                om: &Option<&mut BTreeMap<u32, VecDeque<&str>>>,
+                om: &Option<&mut BTreeMap<u32, VecDeque<Claimant<'_>>>>,
             ) -> bool {
                 om.iter()
                     .flat_map(|map| map.values())
@@ -1584,33 +1694,27 @@ pub fn allocate_all(
 
                 if let Some(t_reqs) = t_reqs.as_mut() {
                     for (&sz, q) in t_reqs.range_mut(..=align).rev() {
-                        if let Some(task) = q.pop_front() {
+                        if let Some(claimant) = q.pop_front() {
                             // We can pack an equal or smaller one in.
                             let align = toml.task_memory_alignment(sz);
-                            allocs
-                                .tasks
-                                .entry(task.to_string())
-                                .or_default()
-                                .insert(
-                                    region.to_string(),
-                                    allocate_one(region, sz, align, avail)?,
-                                );
+                            allocs.record(
+                                claimant,
+                                region,
+                                allocate_one(region, sz, align, avail)?,
+                            );
                             continue 'fitloop;
                         }
                     }
 
                     for (&sz, q) in t_reqs.range_mut(align + 1..) {
-                        if let Some(task) = q.pop_front() {
+                        if let Some(claimant) = q.pop_front() {
                             // We've gotta use a larger one.
                             let align = toml.task_memory_alignment(sz);
-                            allocs
-                                .tasks
-                                .entry(task.to_string())
-                                .or_default()
-                                .insert(
-                                    region.to_string(),
-                                    allocate_one(region, sz, align, avail)?,
-                                );
+                            allocs.record(
+                                claimant,
+                                region,
+                                allocate_one(region, sz, align, avail)?,
+                            );
                             continue 'fitloop;
                         }
                     }
@@ -1688,8 +1792,13 @@ pub struct KernelConfig {
     tasks: Vec<abi::TaskDesc>,
     regions: Vec<abi::RegionDesc>,
     irqs: Vec<abi::Interrupt>,
+    timers_per_task: usize,
 }
 
+/// Most timers we'll give each task. The `SET_TIMER` syscall has room for
+/// many more, but each costs kernel RAM for every task.
+const MAX_TIMERS_PER_TASK: usize = 8;
+
 /// Generate the application descriptor table that the kernel uses to find and
 /// start tasks.
 ///
@@ -1701,7 +1810,7 @@ pub struct KernelConfig {
 /// - Some number of `Interrupt` records routing interrupts to tasks.
 pub fn make_kconfig(
     toml: &Config,
-    task_allocations: &BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    allocations: &Allocations,
     entry_points: &HashMap<String, u32>,
     image_name: &str,
     secure: &Option<SecureData>,
@@ -1789,21 +1898,60 @@ pub fn make_kconfig(
         });
     }
 
+    // Shared regions get one entry for each kind of access that some task has
+    // to them, so that a reader can't write.
+    let mut shared_index = HashMap::new();
+    for (name, shared) in &toml.shared_regions {
+        let range = &allocations.shared[name];
+        let dma = toml.outputs[&shared.memory]
+            .iter()
+            .any(|o| o.name == *image_name && o.dma);
+        for &access in shared.tasks.values() {
+            if shared_index.contains_key(&(name, access)) {
+                continue;
+            }
+            shared_index.insert((name, access), regions.len());
+
+            let mut attributes = abi::RegionAttributes::READ;
+            if access == SharedAccess::ReadWrite {
+                attributes |= abi::RegionAttributes::WRITE;
+            }
+            if dma {
+                attributes |= abi::RegionAttributes::DMA;
+            }
+            regions.push(abi::RegionDesc {
+                base: range.start,
+                size: range.end - range.start,
+                attributes,
+            });
+        }
+    }
+
     // The remaining regions are allocated to tasks on a first-come first-serve
     // basis. We don't check power-of-two requirements in task_allocations
     // because it's the result of autosizing, which already takes the MPU into
     // account.
+    let task_allocations = &allocations.tasks;
     for (i, (name, task)) in toml.tasks.iter().enumerate() {
         // Regions are referenced by index into the table we just generated.
-        // Each task has up to 8, chosen from its 'requires' and 'uses' keys.
+        // Each task has up to 8, chosen from its 'requires' and 'uses' keys,
+        // and any shared regions mapped into it.
         let mut task_regions = [0; 8];
 
-        if task.uses.len() + task_allocations[name].len() > 8 {
+        let shared = toml
+            .shared_regions
+            .iter()
+            .filter_map(|(region, s)| Some((region, *s.tasks.get(name)?)))
+            .collect::<Vec<_>>();
+
+        if task.uses.len() + task_allocations[name].len() + shared.len() > 8 {
             panic!(
-                "task {} uses {} peripherals and {} memories (too many)",
+                "task {} uses {} peripherals, {} memories, and {} shared \
+                 regions (too many)",
                 name,
                 task.uses.len(),
-                task_allocations[name].len()
+                task_allocations[name].len(),
+                shared.len()
             );
         }
 
@@ -1862,10 +2010,44 @@ pub fn make_kconfig(
             }
         }
 
+        // Likewise for shared regions, which follow the peripherals.
+        for (j, key) in shared.iter().enumerate() {
+            task_regions[allocs.len() + task.uses.len() + j] =
+                shared_index[key] as u8;
+        }
+
         let mut flags = abi::TaskFlags::empty();
         if task.start {
             flags |= abi::TaskFlags::START_AT_BOOT;
         }
+        if task.sched_class == SchedClass::Deadline {
+            flags |= abi::TaskFlags::DEADLINE;
+        }
+
+        let mut allowed_callers = 0;
+        if let Some(callers) = &task.allowed_callers {
+            flags |= abi::TaskFlags::RESTRICT_CALLERS;
+            for caller in callers {
+                let index =
+                    toml.tasks.get_index_of(caller).ok_or_else(|| {
+                        anyhow!(
+                            "task `{}` allows calls from unknown task `{}`",
+                            name,
+                            caller
+                        )
+                    })?;
+                if index >= abi::MAX_ALLOWED_CALLERS {
+                    bail!(
+                        "task `{}` allows calls from `{}`, but only the first \
+                         {} tasks can be allowed callers",
+                        name,
+                        caller,
+                        abi::MAX_ALLOWED_CALLERS
+                    );
+                }
+                allowed_callers |= 1 << index;
+            }
+        }
 
         task_descs.push(abi::TaskDesc {
             regions: task_regions,
@@ -1875,6 +2057,7 @@ pub fn make_kconfig(
             priority: task.priority,
             flags,
             index: u16::try_from(i).expect("more than 2**16 tasks?"),
+            allowed_callers,
         });
 
         // Interrupts.
@@ -1964,10 +2147,20 @@ pub fn make_kconfig(
         }
     }
 
+    let timers_per_task = toml.kernel.timers_per_task;
+    if !(1..=MAX_TIMERS_PER_TASK).contains(&timers_per_task) {
+        bail!(
+            "kernel timers-per-task must be between 1 and {}, not {}",
+            MAX_TIMERS_PER_TASK,
+            timers_per_task
+        );
+    }
+
     Ok(KernelConfig {
         irqs,
         tasks: task_descs,
         regions,
+        timers_per_task,
     })
 }
 
@@ -2025,8 +2218,10 @@ fn load_elf(
     if elf.header.container()? != Container::Little {
         bail!("where did you get a big-endian image?");
     }
-    if elf.header.e_machine != goblin::elf::header::EM_ARM {
-        bail!("this is not an ARM file");
+    match elf.header.e_machine {
+        // i386 ELF files are produced by simulator builds.
+        goblin::elf::header::EM_ARM | goblin::elf::header::EM_386 => (),
+        _ => bail!("this is not an ARM file"),
     }
 
     let mut flash = 0;
@@ -20,21 +20,7 @@ pub fn run(
     if interactive {
         ctrlc::set_handler(|| {}).expect("Error setting Ctrl-C handler");
     }
-    let toml = Config::from_file(&args.cfg)?;
-
-    let archive = Path::new("target")
-        .join(&toml.name)
-        .join("dist")
-        .join(image_name)
-        .join(format!("build-{}.zip", &toml.name));
-
-    let humility_path = match env::var("HUBRIS_HUMILITY_PATH") {
-        Ok(path) => path,
-        _ => "humility".to_string(),
-    };
-
-    let mut humility = Command::new(humility_path);
-    humility.arg("-a").arg(archive);
+    let mut humility = command(args, image_name)?;
     for c in precmd {
         humility.arg(c);
     }
@@ -57,3 +43,49 @@ pub fn run(
 
     Ok(())
 }
+
+/// Runs `humility` with the given arguments, returning its standard output.
+pub fn output(
+    args: &HumilityArgs,
+    cmd: &[&str],
+    image_name: &String,
+) -> anyhow::Result<String> {
+    let mut humility = command(args, image_name)?;
+    humility.args(cmd);
+
+    let output = humility
+        .output()
+        .with_context(|| format!("failed to run humility ({:?})", humility))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "humility failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Builds a `humility` command pointed at the archive for `image_name`.
+fn command(
+    args: &HumilityArgs,
+    image_name: &String,
+) -> anyhow::Result<Command> {
+    let toml = Config::from_file(&args.cfg)?;
+
+    let archive = Path::new("target")
+        .join(&toml.name)
+        .join("dist")
+        .join(image_name)
+        .join(format!("build-{}.zip", &toml.name));
+
+    let humility_path = match env::var("HUBRIS_HUMILITY_PATH") {
+        Ok(path) => path,
+        _ => "humility".to_string(),
+    };
+
+    let mut humility = Command::new(humility_path);
+    humility.arg("-a").arg(archive);
+    Ok(humility)
+}
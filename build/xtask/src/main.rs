@@ -16,6 +16,7 @@ mod elf;
 mod flash;
 mod humility;
 mod sizes;
+mod stacks;
 mod task_slot;
 
 #[derive(Debug, Parser)]
@@ -106,6 +107,13 @@ enum Xtask {
         dirty: bool,
     },
 
+    /// Reads each task's deepest stack use from an attached target (using
+    /// `humility`), and recommends `stacksize` values
+    Stacks {
+        #[clap(flatten)]
+        args: HumilityArgs,
+    },
+
     /// Runs `humility`, passing any arguments
     Humility {
         #[clap(flatten)]
@@ -251,6 +259,18 @@ fn run(xtask: Xtask) -> Result<()> {
             };
             humility::run(&args, &[], None, true, &image_name)?;
         }
+        Xtask::Stacks { args } => {
+            let toml = Config::from_file(&args.cfg)?;
+            let image_name = if let Some(ref name) = args.image_name {
+                if !toml.check_image_name(&name) {
+                    bail!("Image name {} not declared in TOML", name);
+                }
+                &name
+            } else {
+                &toml.image_names[0]
+            };
+            stacks::run(&args, &image_name)?;
+        }
         Xtask::Gdb { noflash, mut args } => {
             let toml = Config::from_file(&args.cfg)?;
             let image_name = if let Some(ref name) = args.image_name {
@@ -0,0 +1,80 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{bail, Context, Result};
+
+use crate::{humility, Config, HumilityArgs};
+
+/// Extra stack we recommend on top of the deepest use we've seen, as a
+/// percentage. The measurement only catches stack that was actually written,
+/// and only along the code paths the task has run so far.
+const HEADROOM_PERCENT: u32 = 25;
+
+/// Asks the supervisor on an attached target how deep each task's stack has
+/// gone, and prints that alongside the configured and recommended
+/// `stacksize` for each task.
+pub fn run(args: &HumilityArgs, image_name: &String) -> Result<()> {
+    let toml = Config::from_file(&args.cfg)?;
+
+    println!(
+        "{:<20} {:>10} {:>10} {:>12}",
+        "TASK", "STACKSIZE", "MAX USED", "RECOMMENDED"
+    );
+    for (i, (name, task)) in toml.tasks.iter().enumerate() {
+        let stacksize = task.stacksize.or(toml.stacksize).unwrap();
+
+        let task_arg = format!("task={}", i);
+        let out = humility::output(
+            args,
+            &["hiffy", "-c", "Jefe.get_stack_usage", "-a", &task_arg],
+            image_name,
+        )?;
+        let max_used = parse_field(&out, "max_used").with_context(|| {
+            format!("couldn't read stack usage for {}: {}", name, out.trim())
+        })?;
+
+        let recommended = recommend(max_used);
+        let note = if max_used >= stacksize {
+            " (overflowed)"
+        } else if recommended < stacksize {
+            " (can shrink)"
+        } else if recommended > stacksize {
+            " (should grow)"
+        } else {
+            ""
+        };
+        println!(
+            "{:<20} {:>10} {:>10} {:>12}{}",
+            name, stacksize, max_used, recommended, note
+        );
+    }
+    Ok(())
+}
+
+/// Recommends a `stacksize` for a task whose stack has been seen to reach
+/// `max_used` bytes deep: that plus some headroom, rounded up to the 8-byte
+/// alignment the build requires.
+fn recommend(max_used: u32) -> u32 {
+    let size = max_used + max_used * HEADROOM_PERCENT / 100;
+    (size + 7) & !7
+}
+
+/// Picks the value of `field` out of Humility's rendering of a struct, e.g.
+/// `StackUsage { size: 0x800, max_used: 0x3a8 }`.
+fn parse_field(out: &str, field: &str) -> Result<u32> {
+    let pattern = format!("{}: ", field);
+    let start = match out.find(&pattern) {
+        Some(i) => i + pattern.len(),
+        None => bail!("no `{}` in output", field),
+    };
+    let value: String = out[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.with_context(|| format!("bad value for `{}`: {:?}", field, value))
+}
@@ -206,6 +206,11 @@ fn main() -> ! {
         .clock_ctrl
         .modify(|_, w| w.fro1mhz_clk_ena().set_bit());
 
+    // Clock the windowed watchdog, which jefe drives directly (from the 1Mhz
+    // clock turned on above), since jefe can't ask us to do it.
+    set_bit!(syscon.ahbclkctrl0, Peripheral::Wwdt.pmask());
+    syscon.wdtclkdiv.modify(|_, w| w.halt().clear_bit());
+
     // Just set our Flexcom0 i.e. UART0 to be 12Mhz
     syscon.fcclksel0().modify(|_, w| w.sel().enum_0x2());
     // Flexcom4 (the DAC i2c) is also set to 12Mhz
@@ -0,0 +1,309 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! PID controller
+//!
+//! This is a discrete-time proportional-integral-derivative controller, as
+//! used by the thermal loop to turn "how far above target are we?" into a
+//! fan duty cycle.  It runs at a fixed rate chosen by the caller, so gains
+//! are expressed per control period rather than per second.
+//!
+//! On top of the textbook controller, the output is clamped to a range and
+//! limited in how fast it may change, and the integral term stops
+//! accumulating whenever either limit is holding the output back (so it
+//! doesn't wind up while, say, the fans are already at full speed).
+//!
+//! It's free of any hardware dependencies so that it can be tested on the
+//! host against a simulated plant; see the tests at the bottom of this file.
+
+#![cfg_attr(not(test), no_std)]
+
+/// Tuning parameters for a [`PidControl`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PidConfig {
+    /// Proportional gain: output per unit of error
+    pub gain_p: f32,
+
+    /// Integral gain: output per unit of error, per control period
+    pub gain_i: f32,
+
+    /// Derivative gain: output per unit of change in error, per control
+    /// period
+    pub gain_d: f32,
+
+    /// Lowest output the controller will ask for
+    pub min_output: f32,
+
+    /// Highest output the controller will ask for
+    pub max_output: f32,
+
+    /// Largest change in output from one control period to the next
+    pub max_slew: f32,
+}
+
+/// A PID controller.
+///
+/// The error passed to [`PidControl::run`] should be positive when the
+/// output needs to go *up*; for a fan controller, that's when things are
+/// hotter than their target.
+#[derive(Copy, Clone, Debug)]
+pub struct PidControl {
+    cfg: PidConfig,
+
+    /// Accumulated integral term, already multiplied by `gain_i`
+    integral: f32,
+
+    /// Error from the previous call to `run`, if there was one since the
+    /// last reset
+    last_error: Option<f32>,
+
+    /// Output from the previous call to `run` (or the last reset)
+    output: f32,
+}
+
+impl PidControl {
+    /// Builds a new controller, with its output initially at `min_output`.
+    ///
+    /// Panics if the output range is empty or `max_slew` is negative.
+    pub fn new(cfg: PidConfig) -> Self {
+        assert!(cfg.min_output <= cfg.max_output);
+        assert!(cfg.max_slew >= 0.0);
+        let mut out = Self {
+            cfg,
+            integral: 0.0,
+            last_error: None,
+            output: 0.0,
+        };
+        out.reset(cfg.min_output);
+        out
+    }
+
+    /// Returns the controller's configuration.
+    pub fn config(&self) -> &PidConfig {
+        &self.cfg
+    }
+
+    /// Returns the most recent output.
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// Resets the controller as though it had settled with the given output
+    /// (clamped to the output range), so that taking over from some other
+    /// source of control (e.g. manual mode) doesn't cause a jump.
+    pub fn reset(&mut self, output: f32) {
+        let output = output.max(self.cfg.min_output).min(self.cfg.max_output);
+        self.integral = output;
+        self.last_error = None;
+        self.output = output;
+    }
+
+    /// Runs one control period with the given error, returning the new
+    /// output.
+    pub fn run(&mut self, error: f32) -> f32 {
+        let cfg = &self.cfg;
+
+        let p = cfg.gain_p * error;
+        let integral = self.integral + cfg.gain_i * error;
+        // There's no derivative term on the first run after a reset, since
+        // we've got nothing to difference against.
+        let d = match self.last_error {
+            Some(last) => cfg.gain_d * (error - last),
+            None => 0.0,
+        };
+
+        // We can't go beyond the output range, nor move further than
+        // `max_slew` from where we were.
+        let lo = cfg.min_output.max(self.output - cfg.max_slew);
+        let hi = cfg.max_output.min(self.output + cfg.max_slew);
+
+        let raw = p + integral + d;
+        let output = raw.max(lo).min(hi);
+
+        // Anti-windup: if we're pinned against a limit and the error is
+        // pushing us further into it, accumulating more would only delay
+        // our recovery once the error changes sign.
+        let winding_up = (raw > hi && error > 0.0) || (raw < lo && error < 0.0);
+        if !winding_up {
+            self.integral = integral.max(cfg.min_output).min(cfg.max_output);
+        }
+
+        self.last_error = Some(error);
+        self.output = output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The thermal task's gains, with output in percent PWM duty cycle and
+    /// error in degrees C. If you retune a board, try its gains here too.
+    const CFG: PidConfig = PidConfig {
+        gain_p: 8.0,
+        gain_i: 1.0,
+        gain_d: 10.0,
+        min_output: 0.0,
+        max_output: 100.0,
+        max_slew: 20.0,
+    };
+
+    /// Seconds per control period, matching the thermal task
+    const PERIOD: u32 = 10;
+
+    /// Target temperature, in degrees C
+    const TARGET: f32 = 50.0;
+
+    /// A lumped thermal model of a part and its heatsink: it's heated by
+    /// `power` and cooled towards `ambient` through a conductance which goes
+    /// up linearly with fan duty cycle.
+    struct Plant {
+        temp: f32,
+        ambient: f32,
+        /// Heat dissipated by the part, in W
+        power: f32,
+        /// Heat capacity, in J/C
+        capacity: f32,
+        /// Conductance to ambient with the fans off, in W/C
+        conductance: f32,
+        /// Extra conductance per percent of fan duty cycle, in W/C
+        conductance_per_pwm: f32,
+    }
+
+    impl Plant {
+        fn new() -> Self {
+            // With 100 W of heat, this settles at 50C with the fans at 70%,
+            // and at 43C with them at full speed.
+            Self {
+                temp: 25.0,
+                ambient: 25.0,
+                power: 100.0,
+                capacity: 200.0,
+                conductance: 0.5,
+                conductance_per_pwm: 0.05,
+            }
+        }
+
+        /// Steps the model through one control period with the fans at
+        /// `pwm` percent, a second at a time.
+        fn step(&mut self, pwm: f32) {
+            let g = self.conductance + self.conductance_per_pwm * pwm;
+            for _ in 0..PERIOD {
+                let flow = self.power - g * (self.temp - self.ambient);
+                self.temp += flow / self.capacity;
+            }
+        }
+    }
+
+    /// Runs the loop for `periods` control periods, returning the outputs.
+    fn run(
+        pid: &mut PidControl,
+        plant: &mut Plant,
+        periods: usize,
+    ) -> Vec<f32> {
+        (0..periods)
+            .map(|_| {
+                let pwm = pid.run(plant.temp - TARGET);
+                plant.step(pwm);
+                pwm
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settles_at_target() {
+        let mut pid = PidControl::new(CFG);
+        let mut plant = Plant::new();
+        let mut peak = plant.temp;
+        let mut pwm = 0.0;
+        for _ in 0..100 {
+            pwm = pid.run(plant.temp - TARGET);
+            plant.step(pwm);
+            peak = peak.max(plant.temp);
+        }
+
+        assert!((plant.temp - TARGET).abs() < 0.1, "temp {}", plant.temp);
+        assert!((pwm - 70.0).abs() < 1.0, "pwm {}", pwm);
+        // Starting from cold with the fans off, the part heats up faster
+        // than the fans are allowed to spin up, so there's some overshoot,
+        // but it shouldn't be much.
+        assert!(peak < TARGET + 6.0, "peak {}", peak);
+    }
+
+    #[test]
+    fn tracks_a_step_in_load() {
+        let mut pid = PidControl::new(CFG);
+        let mut plant = Plant::new();
+        run(&mut pid, &mut plant, 100);
+
+        // A 20% jump in dissipation should be soaked up without a big
+        // excursion.
+        plant.power = 120.0;
+        let mut peak = plant.temp;
+        for _ in 0..100 {
+            let pwm = pid.run(plant.temp - TARGET);
+            plant.step(pwm);
+            peak = peak.max(plant.temp);
+        }
+        assert!(peak < TARGET + 2.0, "peak {}", peak);
+        assert!((plant.temp - TARGET).abs() < 0.1, "temp {}", plant.temp);
+    }
+
+    #[test]
+    fn output_respects_limits_and_slew() {
+        let mut pid = PidControl::new(CFG);
+        let mut plant = Plant::new();
+        plant.temp = 90.0;
+
+        let mut prev = pid.output();
+        for pwm in run(&mut pid, &mut plant, 100) {
+            assert!((CFG.min_output..=CFG.max_output).contains(&pwm));
+            assert!((pwm - prev).abs() <= CFG.max_slew + 1e-3);
+            prev = pwm;
+        }
+    }
+
+    #[test]
+    fn recovers_quickly_from_saturation() {
+        let mut pid = PidControl::new(CFG);
+        let mut plant = Plant::new();
+
+        // More heat than the fans can handle: we sit at full speed for a
+        // long time, well above target.
+        plant.power = 200.0;
+        let out = run(&mut pid, &mut plant, 200);
+        assert_eq!(out[199], CFG.max_output);
+        assert!(plant.temp > TARGET);
+
+        // Once the load drops, the fans should start slowing within a
+        // couple of periods of dropping below target, rather than waiting
+        // for a wound-up integral to drain.
+        plant.power = 50.0;
+        let mut below = None;
+        for i in 0..100 {
+            let pwm = pid.run(plant.temp - TARGET);
+            plant.step(pwm);
+            if plant.temp < TARGET && below.is_none() {
+                below = Some(i);
+            }
+            if pwm < CFG.max_output {
+                assert!(i <= below.unwrap_or(i) + 2, "slowed at {}", i);
+                return;
+            }
+        }
+        panic!("fans never slowed down");
+    }
+
+    #[test]
+    fn reset_is_bumpless() {
+        let mut pid = PidControl::new(CFG);
+        pid.reset(40.0);
+        assert_eq!(pid.output(), 40.0);
+        assert_eq!(pid.run(0.0), 40.0);
+
+        pid.reset(150.0);
+        assert_eq!(pid.output(), CFG.max_output);
+    }
+}
@@ -14,6 +14,11 @@ use zerocopy::{AsBytes, FromBytes, Unaligned};
 /// performance. (Though note that changing this alters the ABI.)
 pub const REGIONS_PER_TASK: usize = 8;
 
+/// Number of tasks a `TaskDesc` caller allow-list can name. Only tasks with
+/// indices below this can be allowed to send to a task that restricts its
+/// callers.
+pub const MAX_ALLOWED_CALLERS: usize = 64;
+
 pub const TASK_ID_INDEX_BITS: usize = 10;
 
 /// Names a particular incarnation of a task.
@@ -154,6 +159,19 @@ pub struct TaskDesc {
     /// The index is a u16 to save space in the `TaskDesc` struct; in practice
     /// other factors limit us to fewer than `2**16` tasks.
     pub index: u16,
+    /// Tasks allowed to send to this one, as a bitmask of task indices, if
+    /// the `RESTRICT_CALLERS` flag is set. Otherwise, anyone may send, and
+    /// this is ignored.
+    pub allowed_callers: u64,
+}
+
+impl TaskDesc {
+    /// Checks whether the task at index `caller` may send to this task.
+    pub fn allows_caller(&self, caller: usize) -> bool {
+        !self.flags.contains(TaskFlags::RESTRICT_CALLERS)
+            || (caller < MAX_ALLOWED_CALLERS
+                && self.allowed_callers & (1 << caller) != 0)
+    }
 }
 
 bitflags::bitflags! {
@@ -161,7 +179,13 @@ bitflags::bitflags! {
     #[repr(transparent)]
     pub struct TaskFlags: u8 {
         const START_AT_BOOT = 1 << 0;
-        const RESERVED = !1;
+        /// Task is in the deadline scheduling class: among runnable tasks at
+        /// its priority, it is ordered by its armed timer deadline, earliest
+        /// first, ahead of tasks without one.
+        const DEADLINE = 1 << 1;
+        /// Only the tasks named in `allowed_callers` may send to this task.
+        const RESTRICT_CALLERS = 1 << 2;
+        const RESERVED = !((1 << 3) - 1);
     }
 }
 
@@ -304,8 +328,14 @@ pub struct ULease {
     /// Base address of leased memory. This is equivalent to the base address
     /// field in `USlice`, but isn't represented as a `USlice` because we leave
     /// the internal memory representation of `USlice` out of the ABI.
+    ///
+    /// If `attributes` contains `FORWARD`, this instead names a lease held by
+    /// one of the lender's own clients: the low 16 bits are that client's
+    /// `TaskId`, and the high 16 bits are the index of the lease in the
+    /// client's message.
     pub base_address: u32,
-    /// Length of leased memory, in bytes.
+    /// Length of leased memory, in bytes. For a forwarded lease, this is an
+    /// upper bound on the length of the client's lease.
     pub length: u32,
 }
 
@@ -317,6 +347,10 @@ bitflags::bitflags! {
         const READ = 1 << 0;
         /// Allow the borrower to write this memory.
         const WRITE = 1 << 1;
+        /// This lease forwards a lease the lender is itself borrowing from a
+        /// client, rather than lending the lender's own memory. `READ` and
+        /// `WRITE` can only narrow the access granted by the client.
+        const FORWARD = 1 << 2;
     }
 }
 
@@ -343,6 +377,114 @@ pub const fn extract_new_generation(code: u32) -> Option<Generation> {
 /// Response code returned by the kernel if a lender has defected.
 pub const DEFECT: u32 = 1;
 
+/// Response code returned by the kernel if a `SendTimeout` gave up waiting.
+///
+/// This sits just below the dead codes (see `FIRST_DEAD_CODE`), so it can't be
+/// confused with one.
+pub const TIMED_OUT: u32 = FIRST_DEAD_CODE - 0x100;
+
+/// Response code given to an asynchronous send (see `SendAsync`) if the
+/// recipient rejected the message with `REPLY_FAULT`. A synchronous sender
+/// would have been faulted instead, but an asynchronous sender is trusted more
+/// than its recipient, so the kernel leaves the decision to it.
+pub const REFUSED: u32 = TIMED_OUT - 1;
+
+/// Status of a task's asynchronous send, as reported by the
+/// `collect_async_reply` kernel IPC.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum AsyncSendStatus {
+    /// There is no asynchronous send outstanding.
+    Idle,
+    /// The message is waiting to be received, or has been received and is
+    /// waiting for a reply.
+    Pending,
+    /// The exchange is over: `code` is the response code, and `len` the number
+    /// of bytes of reply deposited in the response buffer.
+    Done { code: u32, len: u32 },
+}
+
+/// Cumulative accounting for a single task, kept by the kernel since boot.
+///
+/// These survive the task being restarted, so that a task that keeps crashing
+/// still shows up as using the CPU.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskStats {
+    /// Number of kernel ticks during which this task was the one running.
+    ///
+    /// This is sampled at each tick, so it's only accurate in aggregate. On a
+    /// kernel built with the `tickless` feature, it's only sampled when the
+    /// kernel timer actually fires, so it's a rough guide at best.
+    pub run_ticks: u64,
+    /// Number of times this task has been switched to.
+    pub times_scheduled: u32,
+    /// Number of syscalls this task has made.
+    pub syscalls: u32,
+}
+
+/// Cumulative statistics for a single hardware interrupt, kept by the kernel
+/// since boot, as reported by the `read_irq_stats` kernel IPC.
+///
+/// When an interrupt fires, the kernel disables it and notifies the task that
+/// owns it, which re-enables it with `irq_control` once it's dealt with the
+/// cause. The time between the two is a measure of how long the owner takes
+/// to handle the interrupt.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct IrqStats {
+    /// Number of times the interrupt has fired.
+    pub fires: u32,
+    /// Number of those times when the owner still hadn't taken the
+    /// notification from an earlier fire, so that it will only see one.
+    pub coalesced: u32,
+    /// Number of times the owner has re-enabled the interrupt after it fired.
+    pub handled: u32,
+    /// Total time from fire to re-enable, in CPU cycles (kernel ticks on
+    /// ARMv6-M), over all `handled` fires.
+    pub total_latency: u64,
+    /// Longest time from fire to re-enable, in CPU cycles (kernel ticks on
+    /// ARMv6-M).
+    pub max_latency: u64,
+}
+
+/// How much of a task's stack it has used, as reported by the
+/// `read_stack_usage` kernel IPC.
+///
+/// The kernel fills a task's stack with a known pattern each time the task is
+/// started, and works out how deep the stack has gone by looking for the
+/// deepest word that no longer holds the pattern. This is a high-water mark
+/// since the task was last started, not a measure of current use.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct StackUsage {
+    /// Size of the task's stack, in bytes.
+    pub size: u32,
+    /// Deepest the stack has been since the task was started, in bytes.
+    pub max_used: u32,
+}
+
+/// Register state the kernel saved for a task at its last kernel entry, as
+/// reported by the `read_task_registers` kernel IPC.
+///
+/// The rest of a task's registers (on ARM, `r0`-`r3`, `r12`, `lr`, `pc`, and
+/// `xpsr`) are stacked by the hardware, and can be found at the bottom of the
+/// task's stack, starting at `sp`.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct SavedRegisters {
+    /// On ARM, `r4`-`r11`. On the simulator, the syscall register block.
+    pub regs: [u32; 8],
+    /// The task's stack pointer.
+    pub sp: u32,
+    /// On ARM, the `EXC_RETURN` value the task entered the kernel with.
+    /// Otherwise zero.
+    pub exc_return: u32,
+}
+
 /// State used to make scheduling decisions.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum TaskState {
@@ -417,6 +559,35 @@ impl From<SchedState> for TaskState {
     }
 }
 
+/// An event recorded in the kernel's event log, along with when it happened.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct KernelEventRecord {
+    /// Kernel time, in ticks, at which the event was recorded.
+    pub timestamp: u64,
+    pub event: KernelEvent,
+}
+
+/// Noteworthy things the kernel records in its event log.
+///
+/// Tasks are identified by index, since the generation is apt to have changed
+/// by the time anyone reads the event.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum KernelEvent {
+    /// A task took a fault. This includes faults delivered by servers using
+    /// `REPLY_FAULT`, which show up as `FaultInfo::FromServer`.
+    TaskFaulted { task: u16, fault: FaultInfo },
+    /// A task was reinitialized by the `restart_task` kernel IPC, and possibly
+    /// started.
+    TaskRestarted { task: u16, started: bool },
+    /// A task's timer fired while the notification from a previous firing was
+    /// still pending, so the task missed a deadline.
+    TimerOverrun { task: u16 },
+    /// The log filled up, and this many events were discarded, starting at the
+    /// time recorded with this event. Events are lost from the end, so this
+    /// always comes after the events that were kept.
+    Dropped { count: u32 },
+}
+
 /// A record describing a fault taken by a task.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum FaultInfo {
@@ -458,7 +629,8 @@ pub enum FaultInfo {
     /// Arguments passed to a syscall were invalid. TODO: this should become
     /// more descriptive, it's a placeholder.
     SyscallUsage(UsageError),
-    /// A task has explicitly aborted itself with a panic.
+    /// A task has explicitly aborted itself with a panic. The start of its
+    /// panic message can be read with the `read_panic_message` kernel IPC.
     Panic,
     /// A fault has been injected into this task by another task
     Injected(TaskId),
@@ -466,6 +638,10 @@ pub enum FaultInfo {
     FromServer(TaskId, ReplyFaultReason),
 }
 
+/// Maximum number of bytes of a task's panic message that the kernel keeps.
+/// Longer messages are truncated, possibly in the middle of a UTF-8 sequence.
+pub const PANIC_MESSAGE_MAX: usize = 64;
+
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
 /// `FromPrimitive` because the kernel doesn't currently depend on `num-traits`
 /// and this seems okay.
@@ -513,6 +689,18 @@ pub enum UsageError {
     NoIrq,
     BadKernelMessage,
     BadReplyFaultReason,
+    /// A program named a timer that's beyond the number each task has.
+    TimerOutOfRange,
+    /// A program started an asynchronous send while it already had one
+    /// outstanding (or uncollected), or made a synchronous send to the same
+    /// task as an outstanding asynchronous one.
+    AsyncSendBusy,
+    /// A program tried to lend memory with an asynchronous send, which isn't
+    /// supported.
+    AsyncSendLeases,
+    /// A program tried to send to a task that doesn't list it as an allowed
+    /// caller.
+    CallerNotAllowed,
 }
 
 /// Origin of a fault.
@@ -570,8 +758,21 @@ pub enum Sysnum {
     RefreshTaskId = 10,
     Post = 11,
     ReplyFault = 12,
+    SendTimeout = 13,
+    SendAsync = 14,
 }
 
+/// Number of low bits of the syscall descriptor that hold the `Sysnum`.
+///
+/// The remaining high bits carry syscall-specific data. Currently only
+/// `SendTimeout` uses them, for its timeout, and `SendAsync`, for the number of
+/// the notification bit to post when the reply arrives; they must be zero for
+/// every other syscall.
+pub const SYSNUM_BITS: u32 = 8;
+
+/// Longest timeout, in kernel ticks, that can be given to `SendTimeout`.
+pub const MAX_SEND_TIMEOUT: u32 = !0 >> SYSNUM_BITS;
+
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
 /// `FromPrimitive` because the kernel doesn't currently depend on `num-traits`
 /// and this seems okay.
@@ -593,6 +794,8 @@ impl core::convert::TryFrom<u32> for Sysnum {
             10 => Ok(Self::RefreshTaskId),
             11 => Ok(Self::Post),
             12 => Ok(Self::ReplyFault),
+            13 => Ok(Self::SendTimeout),
+            14 => Ok(Self::SendAsync),
             _ => Err(()),
         }
     }
@@ -605,6 +808,15 @@ pub enum Kipcnum {
     FaultTask = 3,
     ReadImageId = 4,
     Reset = 5,
+    ReadTaskStats = 6,
+    ReadKernelEvent = 7,
+    ReadTaskRegisters = 8,
+    ReadTaskStack = 9,
+    ReadPanicMessage = 10,
+    CollectAsyncReply = 11,
+    CancelAsyncSend = 12,
+    ReadStackUsage = 13,
+    ReadIrqStats = 14,
 }
 
 impl core::convert::TryFrom<u16> for Kipcnum {
@@ -617,6 +829,15 @@ impl core::convert::TryFrom<u16> for Kipcnum {
             3 => Ok(Self::FaultTask),
             4 => Ok(Self::ReadImageId),
             5 => Ok(Self::Reset),
+            6 => Ok(Self::ReadTaskStats),
+            7 => Ok(Self::ReadKernelEvent),
+            8 => Ok(Self::ReadTaskRegisters),
+            9 => Ok(Self::ReadTaskStack),
+            10 => Ok(Self::ReadPanicMessage),
+            11 => Ok(Self::CollectAsyncReply),
+            12 => Ok(Self::CancelAsyncSend),
+            13 => Ok(Self::ReadStackUsage),
+            14 => Ok(Self::ReadIrqStats),
             _ => Err(()),
         }
     }
@@ -647,3 +868,51 @@ pub struct ImageVectors {
     pub sp: u32,
     pub entry: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Number of distinct generations a `TaskId` can represent.
+    const GENERATIONS: usize = 1 << (16 - TaskId::INDEX_BITS);
+
+    #[test]
+    fn generation_wraps_to_zero() {
+        let last = Generation((GENERATIONS - 1) as u8);
+        assert_eq!(last.next(), Generation::ZERO);
+    }
+
+    #[test]
+    fn generation_cycles_through_every_value() {
+        let mut g = Generation::ZERO;
+        for i in 1..GENERATIONS {
+            g = g.next();
+            assert_eq!(g, Generation(i as u8));
+        }
+        assert_eq!(g.next(), Generation::ZERO);
+    }
+
+    #[test]
+    fn generation_wraparound_preserves_index() {
+        let index = TaskId::INDEX_MASK as usize - 2;
+        let first = TaskId::for_index_and_gen(index, Generation::ZERO);
+        let mut id = first;
+        for _ in 0..GENERATIONS {
+            id = id.next_generation();
+            assert_eq!(id.index(), index);
+        }
+        assert_eq!(id, first);
+    }
+
+    #[test]
+    fn generation_survives_dead_response_code() {
+        let mut g = Generation::ZERO;
+        for _ in 0..GENERATIONS {
+            let code = dead_response_code(g);
+            assert!(code >= FIRST_DEAD_CODE);
+            assert_eq!(extract_new_generation(code), Some(g));
+            g = g.next();
+        }
+        assert_eq!(extract_new_generation(FIRST_DEAD_CODE - 1), None);
+    }
+}
@@ -11,10 +11,16 @@ use std::path::PathBuf;
 use serde::Deserialize;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    build_util::expose_m_profile();
+    // A build for the host, with no application to configure it, is only good
+    // for running the kernel's unit tests (see `arch::host`).
+    let host_test = env::var("TARGET")? == env::var("HOST")?
+        && env::var_os("HUBRIS_KCONFIG").is_none();
+    if !host_test {
+        build_util::expose_m_profile();
+    }
 
     generate_consts()?;
-    generate_statics()?;
+    generate_statics(host_test)?;
 
     Ok(())
 }
@@ -56,13 +62,25 @@ fn generate_consts() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn generate_statics() -> Result<(), Box<dyn std::error::Error>> {
-    let image_id: u64 = env::var("HUBRIS_IMAGE_ID")?.parse()?;
+fn generate_statics(host_test: bool) -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-env-changed=HUBRIS_IMAGE_ID");
-
-    let kconfig: KernelConfig =
-        ron::de::from_str(&env::var("HUBRIS_KCONFIG")?)?;
     println!("cargo:rerun-if-env-changed=HUBRIS_KCONFIG");
+    let (image_id, kconfig): (u64, KernelConfig) = if host_test {
+        // Unit tests build their own task tables, so all we need to provide
+        // here is enough to make `startup` compile.
+        let kconfig = KernelConfig {
+            tasks: vec![],
+            regions: vec![],
+            irqs: vec![],
+            timers_per_task: 2,
+        };
+        (0, kconfig)
+    } else {
+        (
+            env::var("HUBRIS_IMAGE_ID")?.parse()?,
+            ron::de::from_str(&env::var("HUBRIS_KCONFIG")?)?,
+        )
+    };
 
     let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let mut file = File::create(out.join("kconfig.rs")).unwrap();
@@ -76,6 +94,11 @@ fn generate_statics() -> Result<(), Box<dyn std::error::Error>> {
         "const HUBRIS_TASK_COUNT: usize = {};",
         kconfig.tasks.len()
     )?;
+    writeln!(
+        file,
+        "pub const TIMERS_PER_TASK: usize = {};",
+        kconfig.timers_per_task
+    )?;
 
     writeln!(
         file,
@@ -92,6 +115,11 @@ fn generate_statics() -> Result<(), Box<dyn std::error::Error>> {
         writeln!(file, "        initial_stack: {:#010x},", task.initial_stack)?;
         writeln!(file, "        priority: {},", task.priority)?;
         writeln!(file, "        index: {},", task.index)?;
+        writeln!(
+            file,
+            "        allowed_callers: {:#x},",
+            task.allowed_callers
+        )?;
         writeln!(
             file,
             "        flags: unsafe {{ \
@@ -136,6 +164,17 @@ fn generate_statics() -> Result<(), Box<dyn std::error::Error>> {
     }
     writeln!(file, "];")?;
 
+    // The interrupt statistics table has an entry for each interrupt, found
+    // by binary search in this sorted list of interrupt numbers.
+    let mut irq_nums = kconfig.irqs.iter().map(|i| i.irq.0).collect::<Vec<_>>();
+    irq_nums.sort_unstable();
+    writeln!(
+        file,
+        "pub const HUBRIS_IRQ_NUMS: [u32; {}] = {:?};",
+        irq_nums.len(),
+        irq_nums
+    )?;
+
     // Now, we generate two perfect hashes:
     //  irq num => abi::Interrupt
     //  (task, notifications) => abi::InterruptSet
@@ -188,7 +227,13 @@ fn generate_statics() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let target = env::var("TARGET").unwrap();
-    if target.starts_with("thumbv6m") {
+    // The simulator (and the host, when running unit tests) has no meaningful
+    // interrupt latency to optimize, so it shares the simplest representation
+    // with ARMv6-M.
+    if target.starts_with("thumbv6m")
+        || target == build_util::SIM_TARGET
+        || host_test
+    {
         let task_irq_map =
             phash_gen::OwnedSortedList::build(task_irq_map).unwrap();
         let irq_task_map =
@@ -341,4 +386,5 @@ struct KernelConfig {
     tasks: Vec<abi::TaskDesc>,
     regions: Vec<abi::RegionDesc>,
     irqs: Vec<abi::Interrupt>,
+    timers_per_task: usize,
 }
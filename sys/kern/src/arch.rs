@@ -19,12 +19,20 @@ cfg_if::cfg_if! {
     // Note: cfg_if! is slightly touchy about ordering and expression
     // complexity; this chain seems to be the best compromise.
 
-    if #[cfg(not(target_pointer_width = "32"))] {
+    if #[cfg(test)] {
+        #[macro_use]
+        pub mod host;
+        pub use host::*;
+    } else if #[cfg(not(target_pointer_width = "32"))] {
         compile_error!("non-32-bit targets not supported (even for simulation)");
     } else if #[cfg(target_arch = "arm")] {
         #[macro_use]
         pub mod arm_m;
         pub use arm_m::*;
+    } else if #[cfg(hubris_sim)] {
+        #[macro_use]
+        pub mod sim;
+        pub use sim::*;
     } else {
         compile_error!("support for this architecture not implemented");
     }
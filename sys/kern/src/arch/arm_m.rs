@@ -13,6 +13,17 @@
 //! interrupts to maintain `TICKS`, but has the upside that we don't need
 //! special SoC support for timing.
 //!
+//! With the `tickless` feature, we instead stretch each SysTick period to end
+//! at the next time the kernel has something to do -- the earliest armed timer
+//! or send deadline, see `task::next_deadline` -- or as far out as the 24-bit
+//! counter allows, if that's sooner. In between, the CPU sits in the idle
+//! task's `WFI`. `TICKS` then records the tick at which the current period
+//! started, and `now` works out how far into the period we are from the
+//! counter. Periods always end on a tick boundary, so the kernel's timestamp
+//! keeps the same rate (and stays monotonic) in either mode. The catch is that
+//! the SysTick doesn't run in the deeper sleep modes of some parts, so those
+//! must not be entered while tasks are waiting on timers.
+//!
 //! # Notes on ARM-M interrupts
 //!
 //! For performance and (believe it or not) simplicity, this implementation uses
@@ -163,6 +174,17 @@ impl task::ArchState for SavedState {
         self.psp
     }
 
+    fn saved_registers(&self) -> abi::SavedRegisters {
+        abi::SavedRegisters {
+            regs: [
+                self.r4, self.r5, self.r6, self.r7, self.r8, self.r9, self.r10,
+                self.r11,
+            ],
+            sp: self.psp,
+            exc_return: self.exc_return,
+        }
+    }
+
     /// Reads syscall argument register 0.
     fn arg0(&self) -> u32 {
         self.r4
@@ -302,7 +324,7 @@ pub fn reinitialize(task: &mut task::Task) {
 
         let zap = task.try_write(&mut uslice).unwrap_lite();
         for word in zap.iter_mut() {
-            *word = 0xbaddcafe;
+            *word = task::STACK_PAINT;
         }
     }
 
@@ -649,6 +671,22 @@ pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
         // Enable counter and interrupt.
         syst.csr.modify(|v| v | 0b111);
     }
+
+    // Start the cycle counter, which we use to time interrupt handling. The
+    // debugger may have done this already, but we can't count on it.
+    #[cfg(any(armv7m, armv8m))]
+    // Safety: turning on tracing and counting cycles has no effect on memory.
+    unsafe {
+        const TRCENA: u32 = 1 << 24;
+        const CYCCNTENA: u32 = 1 << 0;
+        let dcb = &*cortex_m::peripheral::DCB::PTR;
+        dcb.demcr.modify(|v| v | TRCENA);
+        let dwt = &*cortex_m::peripheral::DWT::PTR;
+        // The Cortex-M7 ignores writes to the DWT from software until it's
+        // unlocked; elsewhere, this write is ignored.
+        dwt.lar.write(0xC5AC_CE55);
+        dwt.ctrl.modify(|v| v | CYCCNTENA);
+    }
     // We are manufacturing authority to interact with the MPU here, because we
     // can't thread a cortex-specific peripheral through an
     // architecture-independent API. This approach might bear revisiting later.
@@ -915,11 +953,17 @@ pub unsafe extern "C" fn SVCall() {
 /// pointer while you have access to `task`, and as long as the `task` being
 /// stored is actually in the task table, you'll be okay.
 pub unsafe fn set_current_task(task: &mut task::Task) {
+    // We get called after every kernel entry, and most of the time the task
+    // carries on running; only count actual switches.
+    if !core::ptr::eq(CURRENT_TASK_PTR.load(Ordering::Relaxed), task) {
+        task.account_scheduled();
+    }
     CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
     crate::profiling::event_context_switch(task as *mut _ as usize);
 }
 
 /// Reads the tick counter.
+#[cfg(not(feature = "tickless"))]
 pub fn now() -> Timestamp {
     // Recall that we expect the systick interrupt cannot preempt kernel code,
     // so we're safe to read this in two nonatomic parts here.
@@ -929,6 +973,49 @@ pub fn now() -> Timestamp {
     ])
 }
 
+/// Reads the tick counter.
+#[cfg(feature = "tickless")]
+pub fn now() -> Timestamp {
+    Timestamp::from(tickless::position().0)
+}
+
+/// Reads the DWT cycle counter, for timing things that take much less than a
+/// tick. It wraps after 2^32 cycles.
+#[cfg(any(armv7m, armv8m))]
+pub fn cycles() -> u32 {
+    // Safety: reading the cycle counter has no side effects.
+    unsafe { (*cortex_m::peripheral::DWT::PTR).cyccnt.read() }
+}
+
+/// ARMv6-M has no cycle counter, so this reads the bottom half of the tick
+/// counter instead.
+#[cfg(armv6m)]
+pub fn cycles() -> u32 {
+    u64::from(now()) as u32
+}
+
+/// Makes sure that the SysTick fires by `deadline`, so that timers get
+/// processed in time. Without the `tickless` feature, it fires every tick
+/// anyway.
+#[cfg(not(feature = "tickless"))]
+pub fn request_wakeup(_deadline: Timestamp) {}
+
+/// Makes sure that the SysTick fires by `deadline`, so that timers get
+/// processed in time.
+#[cfg(feature = "tickless")]
+pub fn request_wakeup(deadline: Timestamp) {
+    // If the period has already ended, the SysTick handler will run as soon
+    // as we leave the kernel, and work out the next deadline for itself.
+    if cortex_m::peripheral::SCB::is_pendst_pending() {
+        return;
+    }
+    let end = u64::from(tickless::base())
+        + u64::from(tickless::PERIOD_TICKS.load(Ordering::Relaxed));
+    if u64::from(deadline) < end {
+        tickless::start_period(Some(deadline));
+    }
+}
+
 /// Kernel global for tracking the current timestamp, measured in ticks.
 ///
 /// This is a pair of `AtomicU32` because (1) we want the interior mutability of
@@ -949,31 +1036,33 @@ static TICKS: [AtomicU32; 2] = {
 #[no_mangle]
 pub unsafe extern "C" fn SysTick() {
     crate::profiling::event_timer_isr_enter();
+
+    // The timer is started just before the first task is recorded as current,
+    // so this can (rarely) be null on the very first tick.
+    let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    // Safety: we're dereferencing the current task pointer, which we're
+    // trusting the rest of this module to maintain correctly.
+    let current =
+        unsafe { current.as_ref() }.map(|t| usize::from(t.descriptor().index));
+
     with_task_table(|tasks| {
-        // Load the time before this tick event.
-        let t0 = TICKS[0].load(Ordering::Relaxed);
-        let t1 = TICKS[1].load(Ordering::Relaxed);
-
-        // Advance the kernel's notion of time by adding 1. Laboriously.
-        let (t0, t1) = if let Some(t0p) = t0.checked_add(1) {
-            // Incrementing t0 did not roll over, no need to update t1.
-            TICKS[0].store(t0p, Ordering::Relaxed);
-            (t0p, t1)
-        } else {
-            // Incrementing t0 overflowed. We need to also increment t1. We use
-            // normal checked addition for this, not wrapping, because this
-            // should not be able to overflow under normal operation, and would
-            // almost certainly indicate state corruption that we'd like to
-            // discover.
-            TICKS[0].store(0, Ordering::Relaxed);
-            TICKS[1].store(t1 + 1, Ordering::Relaxed);
-            (0, t1 + 1)
-        };
+        let then = now();
+        let now = advance_ticks();
+
+        // Charge the period that's just ended to whoever it interrupted.
+        // Without the `tickless` feature that's always one tick, but with it,
+        // the period may have been stretched over many.
+        if let Some(current) = current {
+            tasks[current].account_ticks(u64::from(now) - u64::from(then));
+        }
 
         // Process any timers.
-        let now = Timestamp::from([t0, t1]);
         let switch = task::process_timers(tasks, now);
 
+        // Sleep until there's more for us to do.
+        #[cfg(feature = "tickless")]
+        tickless::start_period(task::next_deadline(tasks));
+
         // If any timers fired, we need to defer a context switch, because the entry
         // sequence to this ISR doesn't save state correctly for efficiency.
         if switch != task::NextTask::Same {
@@ -983,6 +1072,142 @@ pub unsafe extern "C" fn SysTick() {
     crate::profiling::event_timer_isr_exit();
 }
 
+/// Advances `TICKS` at the end of a SysTick period, which is always one tick
+/// long without the `tickless` feature, and returns the new time.
+#[cfg(not(feature = "tickless"))]
+fn advance_ticks() -> Timestamp {
+    // Load the time before this tick event.
+    let t0 = TICKS[0].load(Ordering::Relaxed);
+    let t1 = TICKS[1].load(Ordering::Relaxed);
+
+    // Advance the kernel's notion of time by adding 1. Laboriously.
+    let (t0, t1) = if let Some(t0p) = t0.checked_add(1) {
+        // Incrementing t0 did not roll over, no need to update t1.
+        TICKS[0].store(t0p, Ordering::Relaxed);
+        (t0p, t1)
+    } else {
+        // Incrementing t0 overflowed. We need to also increment t1. We use
+        // normal checked addition for this, not wrapping, because this
+        // should not be able to overflow under normal operation, and would
+        // almost certainly indicate state corruption that we'd like to
+        // discover.
+        TICKS[0].store(0, Ordering::Relaxed);
+        TICKS[1].store(t1 + 1, Ordering::Relaxed);
+        (0, t1 + 1)
+    };
+
+    Timestamp::from([t0, t1])
+}
+
+/// Advances `TICKS` at the end of a SysTick period, and returns the new time.
+///
+/// This leaves the timer in between periods -- `PERIOD_TICKS` is zero -- so
+/// the caller must start the next period with `tickless::start_period` before
+/// leaving the kernel.
+#[cfg(feature = "tickless")]
+fn advance_ticks() -> Timestamp {
+    let end = u64::from(tickless::base())
+        + u64::from(tickless::PERIOD_TICKS.load(Ordering::Relaxed));
+    tickless::set_base(end);
+    tickless::PERIOD_TICKS.store(0, Ordering::Relaxed);
+    now()
+}
+
+/// Bookkeeping for the `tickless` feature; see the module docs.
+#[cfg(feature = "tickless")]
+mod tickless {
+    use super::{CLOCK_FREQ_KHZ, TICKS};
+    use crate::time::Timestamp;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use cortex_m::peripheral::{SCB, SYST};
+
+    /// Number of ticks in the SysTick period that's under way, at the end of
+    /// which the counter reaches zero. This is zero between the end of one
+    /// period and the start of the next, which only happens within the
+    /// SysTick handler.
+    ///
+    /// We start the timer with one-tick periods, so this starts out as 1.
+    pub static PERIOD_TICKS: AtomicU32 = AtomicU32::new(1);
+
+    /// The SysTick counter is 24 bits wide.
+    const MAX_CYCLES: u32 = 1 << 24;
+
+    fn syst() -> &'static cortex_m::peripheral::syst::RegisterBlock {
+        // Safety: we only use this to access the SysTick, which we own.
+        unsafe { &*SYST::PTR }
+    }
+
+    /// Returns the tick at which the current period started.
+    pub fn base() -> Timestamp {
+        // As with the non-tickless `now`, we can't be preempted by the
+        // SysTick handler, so it's fine to read this in two parts.
+        Timestamp::from([
+            TICKS[0].load(Ordering::Relaxed),
+            TICKS[1].load(Ordering::Relaxed),
+        ])
+    }
+
+    pub fn set_base(ticks: u64) {
+        TICKS[0].store(ticks as u32, Ordering::Relaxed);
+        TICKS[1].store((ticks >> 32) as u32, Ordering::Relaxed);
+    }
+
+    /// Works out where we are in time, from the current period and the
+    /// counter. Returns the current tick, and how many CPU cycles into that
+    /// tick we are.
+    pub fn position() -> (u64, u32) {
+        let divisor = CLOCK_FREQ_KHZ.load(Ordering::Relaxed);
+        let base = u64::from(base());
+        let period = PERIOD_TICKS.load(Ordering::Relaxed);
+        let remaining = syst().cvr.read();
+        // Check for the counter having reached zero *after* reading it, so
+        // that we know whether the value we read is from this period.
+        if period != 0 && !SCB::is_pendst_pending() {
+            // The period ends on a tick boundary, `remaining` cycles from
+            // now, so we can count ticks back from there.
+            let left = (remaining + divisor - 1) / divisor;
+            let tick = base + u64::from(period.saturating_sub(left));
+            (tick, left * divisor - remaining)
+        } else {
+            // The period is over, and the counter has since been reloaded.
+            // It's been counting down from the reload value ever since the
+            // end of the period, which was a tick boundary.
+            let since = syst().rvr.read() - syst().cvr.read();
+            let tick = base + u64::from(period) + u64::from(since / divisor);
+            (tick, since % divisor)
+        }
+    }
+
+    /// Starts a new period, which ends at `deadline` or as far out as the
+    /// counter allows, whichever comes first. Periods are at least one tick
+    /// long, so a deadline that has already passed is handled at the next
+    /// tick.
+    pub fn start_period(deadline: Option<Timestamp>) {
+        let divisor = CLOCK_FREQ_KHZ.load(Ordering::Relaxed);
+        let (tick, into) = position();
+        let ticks = deadline
+            .map_or(u64::MAX, |d| u64::from(d).saturating_sub(tick))
+            .clamp(1, u64::from(MAX_CYCLES / divisor));
+        // This fits in a u32, and is at least 1, thanks to the clamp above.
+        let ticks = ticks as u32;
+        let cycles = ticks * divisor - into;
+
+        set_base(tick);
+        PERIOD_TICKS.store(ticks, Ordering::Relaxed);
+        // Safety: this has no memory safety implications, though the register
+        // API doesn't know that.
+        unsafe {
+            syst().rvr.write(cycles - 1);
+            // Writing the counter clears it, so it reloads from `rvr` on the
+            // next cycle without raising an interrupt.
+            syst().cvr.write(0);
+        }
+        // If the old period ended while we were working this out, we've
+        // accounted for it above, and mustn't do so again in the handler.
+        SCB::clear_pendst();
+    }
+}
+
 fn pend_context_switch_from_isr() {
     // This sets the bit to pend a PendSV interrupt. PendSV will happen after
     // the current ISR (and any chained ISRs) returns, and perform the context
@@ -1152,7 +1377,10 @@ pub unsafe extern "C" fn DefaultHandler() {
                 // Now, post the notification and return the
                 // scheduling hint.
                 let n = task::NotificationSet(owner.notification);
-                tasks[owner.task as usize].post(n)
+                let owner = &mut tasks[owner.task as usize];
+                let coalesced = owner.has_pending_notifications(n);
+                crate::irq_stats::record_fire(irq_num, coalesced);
+                owner.post(n)
             });
             if switch {
                 pend_context_switch_from_isr()
@@ -1448,6 +1676,16 @@ pub fn reset() -> ! {
     cortex_m::peripheral::SCB::sys_reset()
 }
 
+/// Marks one of the kernel's statics as in use, by setting its `in_use` flag.
+///
+/// The kernel never reenters itself, so finding the flag already set means
+/// the static is being used recursively, which is a bug: we panic.
+pub fn claim_static(in_use: &AtomicBool) {
+    if in_use.swap_polyfill(true, Ordering::Acquire) {
+        panic!(); // recursive use
+    }
+}
+
 /// Common implementation of fault handling.
 ///
 /// # Safety
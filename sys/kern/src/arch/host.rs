@@ -0,0 +1,205 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for running the kernel's unit tests on the host.
+//!
+//! This is only built for `cargo test`, on a Linux host. It provides just
+//! enough of the architecture interface for the portable parts of the kernel
+//! to run against task tables built by the tests (see `crate::testing`). Tasks
+//! never actually run: tests play the part of a task by filling in its saved
+//! registers and calling into the kernel directly.
+//!
+//! Time doesn't pass on its own here, either. Code that takes the current time
+//! as a parameter, like `task::process_timers`, should be tested that way;
+//! `now` is always zero.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::atomic::AtomicExt;
+use crate::task;
+use crate::time::Timestamp;
+use crate::umem::USlice;
+use unwrap_lite::UnwrapLite;
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// Simulated register state: the syscall argument/return block, in the same
+/// order as `r4`-`r11` on ARM, and a stack pointer.
+#[derive(Debug, Default)]
+pub struct SavedState {
+    pub regs: [u32; 8],
+    pub sp: u32,
+}
+
+/// Map the simulated registers to syscall argument and return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    fn saved_registers(&self) -> abi::SavedRegisters {
+        abi::SavedRegisters {
+            regs: self.regs,
+            sp: self.sp,
+            exc_return: 0,
+        }
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.regs[7]
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+}
+
+pub unsafe fn set_clock_freq(_tick_divisor: u32) {}
+
+pub fn reinitialize(task: &mut task::Task) {
+    *task.save_mut() = SavedState::default();
+    let initial_stack = task.descriptor().initial_stack;
+    uassert!(initial_stack & 0x7 == 0);
+
+    // Zap the stack with the same pattern as on hardware, so that tests of
+    // stack accounting see the same thing.
+    for region in task.region_table().iter() {
+        if initial_stack < region.base {
+            continue;
+        }
+
+        if initial_stack > region.base + region.size {
+            continue;
+        }
+
+        let mut uslice: USlice<u32> = USlice::from_raw(
+            region.base as usize,
+            (initial_stack as usize - region.base as usize) >> 2,
+        )
+        .unwrap_lite();
+
+        let zap = task.try_write(&mut uslice).unwrap_lite();
+        for word in zap.iter_mut() {
+            *word = task::STACK_PAINT;
+        }
+    }
+
+    task.save_mut().sp = initial_stack;
+}
+
+/// There's no memory protection on the host.
+pub fn apply_memory_protection(_task: &task::Task) {}
+
+pub fn start_first_task(_tick_divisor: u32, _task: &mut task::Task) -> ! {
+    panic!("tasks can't run on the host");
+}
+
+std::thread_local! {
+    /// Task most recently passed to `set_current_task`. Tests run in parallel
+    /// on separate threads, each with its own task table, so this is per
+    /// thread.
+    static CURRENT_TASK_PTR: core::cell::Cell<*const task::Task> =
+        core::cell::Cell::new(core::ptr::null());
+}
+
+/// Records `task` as the current task. On the host, the only trace of this is
+/// in the task's statistics, which count it if it's a switch.
+///
+/// # Safety
+///
+/// This is always safe on the host, but is `unsafe` for consistency with the
+/// real architectures.
+pub unsafe fn set_current_task(task: &mut task::Task) {
+    let previous = CURRENT_TASK_PTR.with(|p| p.replace(task));
+    if !core::ptr::eq(previous, task) {
+        task.account_scheduled();
+    }
+}
+
+/// Reads the tick counter, which never moves.
+pub fn now() -> Timestamp {
+    Timestamp::from(0)
+}
+
+pub fn cycles() -> u32 {
+    0
+}
+
+pub fn request_wakeup(_deadline: Timestamp) {}
+
+pub fn disable_irq(_n: u32) {}
+
+pub fn enable_irq(_n: u32) {}
+
+pub fn reset() -> ! {
+    panic!("reset requested");
+}
+
+/// Marks one of the kernel's statics as in use, by setting its `in_use` flag.
+///
+/// In the kernel, finding the flag already set would mean recursive use. Unit
+/// tests share the statics between threads, though, so here we wait our turn.
+pub fn claim_static(in_use: &AtomicBool) {
+    while in_use.swap_polyfill(true, Ordering::Acquire) {
+        std::thread::yield_now();
+    }
+}
+
+impl AtomicExt for AtomicBool {
+    type Primitive = bool;
+
+    #[inline(always)]
+    fn swap_polyfill(
+        &self,
+        value: Self::Primitive,
+        ordering: Ordering,
+    ) -> Self::Primitive {
+        self.swap(value, ordering)
+    }
+}
@@ -0,0 +1,1015 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for the hosted simulator.
+//!
+//! The simulator runs a Hubris application as an ordinary 32-bit x86 Linux
+//! process. This is useful for running the test suite, and for poking at kernel
+//! logic, without hardware. It is not a faithful model of any particular
+//! microcontroller.
+//!
+//! # Simulation model
+//!
+//! Task memory is mapped into the process at the addresses the tasks were
+//! linked for, and filled in from the application image by `load_image`, which
+//! the kernel's `main` must call before `start_kernel`.
+//!
+//! Each task then gets a host thread, which runs the task's code on the task's
+//! own stack. All kernel code runs on one more thread, the process's main
+//! thread, which also stands in for the SysTick. As on a single-core
+//! microcontroller, only one task runs at a time: the kernel thread only lets
+//! the scheduled task's thread go once every other task thread has stopped
+//! and handed control back to it. This also gives us the same "kernel code is
+//! never preempted" property we rely on elsewhere.
+//!
+//! Task threads hand control to the kernel through host signals. The signal
+//! handlers do as little as they can, since hardly anything is safe to do in a
+//! signal handler: each sends a `Message` to the kernel thread over a pipe,
+//! then blocks reading the thread's own pipe until the kernel sends it a
+//! `Resume`. The cases are:
+//!
+//! - Syscalls: userlib executes `ud2` with `eax` pointing at a block of eight
+//!   words (the seven argument registers followed by the syscall number, in
+//!   the same order as `r4`-`r11` on ARM). This raises `SIGILL`. The kernel
+//!   copies the block into the task's `SavedState`, runs the syscall, and
+//!   copies the return registers back out the next time the task is scheduled.
+//! - Faults: `SIGSEGV`, `SIGBUS`, `SIGFPE`, and any `SIGILL` that isn't a
+//!   syscall are turned into the corresponding `FaultInfo`.
+//! - Preemption: when the kernel needs to switch tasks, it sends
+//!   `PREEMPT_SIGNAL` to the running task's thread, and carries on once that
+//!   thread reports that it has stopped.
+//!
+//! A task is (re)started by having the handler rewrite its saved signal
+//! context to point at the task's entry point and initial stack.
+//!
+//! # Limitations
+//!
+//! There is no memory protection between tasks, or between tasks and the
+//! kernel; the simulator is for testing logic, not isolation. There are no
+//! hardware interrupts, but simulated peripherals can use `raise_irq` to get
+//! the same behavior as an interrupt firing on real hardware: an interrupt
+//! raised while disabled stays pending until its owner enables it.
+
+use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicPtr, AtomicU64, Ordering,
+};
+use std::cell::Cell;
+use std::ops::Range;
+use std::os::unix::thread::JoinHandleExt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::atomic::AtomicExt;
+use crate::startup::with_task_table;
+use crate::task;
+use crate::time::Timestamp;
+use crate::umem::USlice;
+use abi::{FaultInfo, FaultSource, RegionAttributes};
+use unwrap_lite::UnwrapLite;
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// Pointer to the task the kernel has most recently chosen to run, mirroring
+/// the ARM implementation.
+static CURRENT_TASK_PTR: AtomicPtr<task::Task> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Kernel timestamp, in ticks. Unlike ARMv7-M, our host has 64-bit atomics.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Signal used to ask a running task's thread to park.
+const PREEMPT_SIGNAL: libc::c_int = libc::SIGUSR1;
+
+/// Size of the per-thread alternate signal stack that kernel code runs on.
+const KERNEL_STACK_SIZE: usize = 256 * 1024;
+
+/// Host page size assumed when mapping task memory.
+const PAGE_SIZE: u32 = 4096;
+
+/// Write end of the pipe that carries `Message`s to the kernel thread, or -1
+/// before the kernel has started.
+static KERNEL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// Simulated interrupts that are currently enabled. Only the kernel thread
+/// touches this.
+static ENABLED_IRQS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Simulated interrupts that have been raised but not yet delivered, because
+/// they're disabled or the kernel thread hasn't got to them yet.
+static PENDING_IRQS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Index of the task this thread runs, or `None` for non-task threads.
+    static TASK_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+
+    /// Read end of the pipe on which this task thread receives `Resume`s.
+    static RESUME_PIPE: Cell<libc::c_int> = const { Cell::new(-1) };
+}
+
+/// Sent by a task thread to the kernel thread when it stops running task code,
+/// or by `raise_irq` to wake the kernel thread.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+struct Message {
+    task: u32,
+    kind: u32,
+    /// For `MSG_SYSCALL`, the address of the syscall block; for `MSG_FAULT`,
+    /// the signal number.
+    a: u32,
+    /// For `MSG_SYSCALL`, the stack pointer; for `MSG_FAULT`, the faulting
+    /// address.
+    b: u32,
+}
+
+/// The task thread was preempted, or is ready to run for the first time.
+const MSG_PARK: u32 = 0;
+/// The task made a syscall.
+const MSG_SYSCALL: u32 = 1;
+/// The task faulted.
+const MSG_FAULT: u32 = 2;
+/// An interrupt has been raised.
+const MSG_IRQ: u32 = 3;
+
+/// Sent by the kernel thread to a stopped task thread to set it running again.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+struct Resume {
+    /// If nonzero, start the task over at `entry_point`, with its stack
+    /// pointer at `initial_stack`; otherwise, carry on where it stopped.
+    restart: u32,
+    entry_point: u32,
+    initial_stack: u32,
+}
+
+/// Simulated register state: the syscall argument/return block, and where to
+/// (re)start the task.
+#[derive(Debug, Default)]
+pub struct SavedState {
+    /// Syscall registers, in the same order as `r4`-`r11` on ARM.
+    regs: [u32; 8],
+    /// Task's stack pointer as of its last kernel entry.
+    sp: u32,
+    /// Set by `reinitialize`: the next time this task is scheduled, its thread
+    /// should start over at `entry_point` using `initial_stack`.
+    restart: bool,
+    entry_point: u32,
+    initial_stack: u32,
+}
+
+/// Map the simulated registers to syscall argument and return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    fn saved_registers(&self) -> abi::SavedRegisters {
+        abi::SavedRegisters {
+            regs: self.regs,
+            sp: self.sp,
+            exc_return: 0,
+        }
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.regs[7]
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+}
+
+/// On the simulator, `tick_divisor` is the number of microseconds of host time
+/// per kernel tick. There's no clock to program, so this does nothing;
+/// `start_first_task` paces the tick with it instead.
+pub unsafe fn set_clock_freq(_tick_divisor: u32) {}
+
+pub fn reinitialize(task: &mut task::Task) {
+    *task.save_mut() = SavedState::default();
+    let initial_stack = task.descriptor().initial_stack;
+    uassert!(initial_stack & 0x7 == 0);
+
+    // Zap the stack with the same pattern as on hardware, so that tools (and
+    // tests) looking at stack usage see the same thing.
+    for region in task.region_table().iter() {
+        if initial_stack < region.base {
+            continue;
+        }
+
+        if initial_stack > region.base + region.size {
+            continue;
+        }
+
+        let mut uslice: USlice<u32> = USlice::from_raw(
+            region.base as usize,
+            (initial_stack as usize - region.base as usize) >> 2,
+        )
+        .unwrap_lite();
+
+        let zap = task.try_write(&mut uslice).unwrap_lite();
+        for word in zap.iter_mut() {
+            *word = task::STACK_PAINT;
+        }
+    }
+
+    let entry_point = task.descriptor().entry_point;
+    let save = task.save_mut();
+    save.restart = true;
+    save.entry_point = entry_point;
+    save.initial_stack = initial_stack;
+}
+
+/// The simulator has no MPU; tasks can see all of memory.
+pub fn apply_memory_protection(_task: &task::Task) {}
+
+/// Maps task memory and loads the application image into it.
+///
+/// The image is read from the SREC file named by the `HUBRIS_SIM_IMAGE`
+/// environment variable (normally `final.srec` from the build archive).
+/// Records that fall outside of task memory (i.e. the kernel's share of the
+/// image, which is meaningless here) are ignored.
+///
+/// # Safety
+///
+/// This must be called exactly once, before `start_kernel`. It maps memory at
+/// fixed addresses; this will panic rather than clobber existing mappings, but
+/// it can't stop the host from later wanting those addresses for itself.
+pub unsafe fn load_image() {
+    let ranges = task_memory();
+    for range in &ranges {
+        // Safety: MAP_FIXED_NOREPLACE will fail rather than replace any
+        // existing mapping.
+        let p = unsafe {
+            libc::mmap(
+                range.start as usize as *mut libc::c_void,
+                (range.end - range.start) as usize,
+                libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+                libc::MAP_PRIVATE
+                    | libc::MAP_ANONYMOUS
+                    | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if p as usize != range.start as usize {
+            panic!("can't map task memory at {:#x?}", range);
+        }
+    }
+
+    let path = std::env::var_os("HUBRIS_SIM_IMAGE")
+        .expect("HUBRIS_SIM_IMAGE must name the image to run");
+    let srec = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("can't read {:?}: {}", path, e));
+    for (address, data) in srec.lines().filter_map(parse_srec_data) {
+        let end = address + data.len() as u32;
+        if !ranges.iter().any(|r| r.start <= address && end <= r.end) {
+            continue;
+        }
+        // Safety: we mapped this range above.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                address as usize as *mut u8,
+                data.len(),
+            );
+        }
+    }
+}
+
+/// Computes the page-aligned, merged set of address ranges covering all task
+/// memory.
+fn task_memory() -> Vec<Range<u32>> {
+    let mut ranges: Vec<Range<u32>> = crate::startup::region_descs()
+        .iter()
+        .filter(|r| {
+            r.size != 0 && !r.attributes.contains(RegionAttributes::DEVICE)
+        })
+        .map(|r| {
+            let start = r.base & !(PAGE_SIZE - 1);
+            let end = (r.base + r.size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+            start..end
+        })
+        .collect();
+    ranges.sort_by_key(|r| r.start);
+
+    let mut merged: Vec<Range<u32>> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => {
+                last.end = last.end.max(r.end);
+            }
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
+/// Parses a single SREC data record (S1, S2, or S3), returning its address and
+/// payload. Other record types yield `None`.
+fn parse_srec_data(line: &str) -> Option<(u32, Vec<u8>)> {
+    let line = line.trim();
+    let addr_len = match line.get(..2)? {
+        "S1" => 2,
+        "S2" => 3,
+        "S3" => 4,
+        _ => return None,
+    };
+    let bytes = (2..line.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(line.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .unwrap_or_else(|| panic!("malformed SREC record: {}", line));
+    // bytes[0] is the count; the final byte is the checksum.
+    uassert!(bytes.len() == bytes[0] as usize + 1);
+    let address = bytes[1..1 + addr_len]
+        .iter()
+        .fold(0u32, |a, &b| (a << 8) | u32::from(b));
+    Some((address, bytes[1 + addr_len..bytes.len() - 1].to_vec()))
+}
+
+pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
+    // Safety: our caller hands us a task from the task table.
+    unsafe {
+        set_current_task(task);
+    }
+
+    // Safety: the handlers below are written to be run as signal handlers.
+    unsafe {
+        install_handler(PREEMPT_SIGNAL, preempt_handler, false);
+        for sig in [libc::SIGILL, libc::SIGSEGV, libc::SIGBUS, libc::SIGFPE] {
+            install_handler(sig, trap_handler, true);
+        }
+    }
+
+    let (kernel_rx, kernel_tx) = pipe();
+    KERNEL_PIPE.store(kernel_tx, Ordering::Relaxed);
+
+    let task_count = with_task_table(|tasks| tasks.len());
+    let mut kernel = Kernel {
+        threads: Vec::with_capacity(task_count),
+        resume_pipes: Vec::with_capacity(task_count),
+        stopped: vec![false; task_count],
+        running: None,
+        preempt_sent: false,
+        syscall_blocks: vec![None; task_count],
+    };
+    for index in 0..task_count {
+        let (rx, tx) = pipe();
+        let thread = std::thread::Builder::new()
+            .name(format!("task{}", index))
+            .spawn(move || task_thread(index, rx))
+            .unwrap_lite();
+        kernel.threads.push(thread.as_pthread_t());
+        kernel.resume_pipes.push(tx);
+    }
+
+    // This thread now runs the kernel, and is the system tick timer.
+    let period = Duration::from_micros(u64::from(tick_divisor));
+    let mut next_tick = Instant::now() + period;
+    loop {
+        let timeout = next_tick.saturating_duration_since(Instant::now());
+        if let Some(msg) = recv_message(kernel_rx, timeout) {
+            kernel.handle(msg);
+        }
+        if Instant::now() >= next_tick {
+            next_tick += period;
+            tick();
+        }
+        deliver_irqs();
+        kernel.schedule();
+    }
+}
+
+/// State of the simulated CPU, owned by the kernel thread.
+struct Kernel {
+    /// Host thread for each task, indexed like the task table.
+    threads: Vec<libc::pthread_t>,
+    /// Write end of each task thread's `Resume` pipe.
+    resume_pipes: Vec<libc::c_int>,
+    /// Whether each task thread is stopped, waiting for a `Resume`.
+    stopped: Vec<bool>,
+    /// Index of the task whose thread is executing task code, if any. This
+    /// lags the current task across a context switch.
+    running: Option<usize>,
+    /// Set once we've asked `running` to stop.
+    preempt_sent: bool,
+    /// For each task stopped in a syscall, the address of its syscall block,
+    /// where its return registers go when it's resumed.
+    syscall_blocks: Vec<Option<u32>>,
+}
+
+impl Kernel {
+    /// Handles a message from a task thread (or `raise_irq`).
+    fn handle(&mut self, msg: Message) {
+        if msg.kind == MSG_IRQ {
+            // Interrupts are delivered by our caller.
+            return;
+        }
+        let index = msg.task as usize;
+        self.stopped[index] = true;
+        if self.running == Some(index) {
+            self.running = None;
+            self.preempt_sent = false;
+        }
+        match msg.kind {
+            MSG_SYSCALL => self.syscall(index, msg.a, msg.b),
+            MSG_FAULT => {
+                let info = match msg.a as libc::c_int {
+                    libc::SIGSEGV => FaultInfo::MemoryAccess {
+                        address: Some(msg.b),
+                        source: FaultSource::User,
+                    },
+                    libc::SIGBUS => FaultInfo::BusError {
+                        address: Some(msg.b),
+                        source: FaultSource::User,
+                    },
+                    libc::SIGFPE => FaultInfo::DivideByZero,
+                    _ => FaultInfo::IllegalInstruction,
+                };
+                with_task_table(|tasks| fault(tasks, index, info));
+            }
+            _ => (),
+        }
+    }
+
+    /// Runs the syscall described by the block at `block` for task `index`.
+    fn syscall(&mut self, index: usize, block: u32, sp: u32) {
+        let task_ptr = with_task_table(|tasks| {
+            let task = &mut tasks[index];
+            // The syscall block is in task memory, so check the task is
+            // allowed to touch it before we do.
+            let regs = USlice::<[u32; 8]>::from_raw(block as usize, 1)
+                .ok()
+                .and_then(|s| task.try_read(&s).ok().map(|r| r[0]));
+            match regs {
+                Some(regs) => {
+                    task.save_mut().regs = regs;
+                    task.save_mut().sp = sp;
+                    Some(task as *mut task::Task)
+                }
+                None => {
+                    fault(
+                        tasks,
+                        index,
+                        FaultInfo::MemoryAccess {
+                            address: Some(block),
+                            source: FaultSource::Kernel,
+                        },
+                    );
+                    None
+                }
+            }
+        });
+        if let Some(task_ptr) = task_ptr {
+            self.syscall_blocks[index] = Some(block);
+            let nr = with_task_table(|tasks| {
+                use task::ArchState;
+                tasks[index].save().syscall_descriptor()
+            });
+            // Safety: task_ptr is from the task table, and no task code is
+            // running.
+            unsafe {
+                crate::syscalls::syscall_entry(nr, task_ptr);
+            }
+        }
+    }
+
+    /// Gets the current task running, once nothing else is.
+    fn schedule(&mut self) {
+        let current = current_index();
+        match self.running {
+            None if self.stopped[current] => self.resume(current),
+            Some(running) if running != current && !self.preempt_sent => {
+                // Safety: we're signalling a task thread, which handles this
+                // signal.
+                unsafe {
+                    libc::pthread_kill(self.threads[running], PREEMPT_SIGNAL);
+                }
+                self.preempt_sent = true;
+            }
+            _ => (),
+        }
+    }
+
+    /// Sets stopped task `index` running again, either restarting it or
+    /// delivering syscall results as appropriate.
+    fn resume(&mut self, index: usize) {
+        let block = self.syscall_blocks[index].take();
+        let resume = with_task_table(|tasks| {
+            let save = tasks[index].save_mut();
+            if save.restart {
+                save.restart = false;
+                Resume {
+                    restart: 1,
+                    entry_point: save.entry_point,
+                    initial_stack: save.initial_stack,
+                }
+            } else {
+                if let Some(block) = block {
+                    let block = block as usize as *mut [u32; 8];
+                    // Safety: we checked this block was in task memory when
+                    // the syscall was made, and the task is stopped.
+                    unsafe {
+                        block.write(save.regs);
+                    }
+                }
+                Resume::default()
+            }
+        });
+        self.stopped[index] = false;
+        self.running = Some(index);
+        send(self.resume_pipes[index], &resume);
+    }
+}
+
+/// Body of each task's host thread. `resume_pipe` is the read end of the pipe
+/// on which the kernel thread sends us `Resume`s.
+fn task_thread(index: usize, resume_pipe: libc::c_int) {
+    TASK_INDEX.with(|t| t.set(Some(index)));
+    RESUME_PIPE.with(|p| p.set(resume_pipe));
+
+    let stack = Box::leak(vec![0u8; KERNEL_STACK_SIZE].into_boxed_slice());
+    let ss = libc::stack_t {
+        ss_sp: stack.as_mut_ptr().cast(),
+        ss_flags: 0,
+        ss_size: stack.len(),
+    };
+    // Safety: the alternate stack is leaked, so it lives as long as the
+    // thread does.
+    unsafe {
+        if libc::sigaltstack(&ss, core::ptr::null_mut()) != 0 {
+            panic!("sigaltstack failed");
+        }
+    }
+
+    // Enter the task by way of the preemption handler, which stops us until
+    // the task is scheduled, then points our context at its entry point.
+    // Safety: we are signalling ourselves with a signal we handle.
+    unsafe {
+        libc::pthread_kill(libc::pthread_self(), PREEMPT_SIGNAL);
+    }
+    unreachable!();
+}
+
+/// Advances the kernel timestamp and processes timers. This is our SysTick.
+fn tick() {
+    crate::profiling::event_timer_isr_enter();
+    let switch = with_task_table(|tasks| {
+        tasks[current_index()].account_ticks(1);
+        let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+        task::process_timers(tasks, Timestamp::from(now))
+    });
+    if switch != task::NextTask::Same {
+        switch_tasks();
+    }
+    crate::profiling::event_timer_isr_exit();
+}
+
+/// Raises simulated interrupt `n`, which is delivered to its owner exactly as
+/// the hardware interrupt handler does on ARM. If the interrupt is disabled,
+/// it stays pending until it's enabled again. This is intended for use by
+/// simulated peripherals, from any thread, but not from a signal handler.
+pub fn raise_irq(n: u32) {
+    if crate::startup::HUBRIS_IRQ_TASK_LOOKUP
+        .get(abi::InterruptNum(n))
+        .is_none()
+    {
+        panic!("unhandled IRQ {}", n);
+    }
+    {
+        let mut pending = PENDING_IRQS.lock().unwrap_lite();
+        if !pending.contains(&n) {
+            pending.push(n);
+        }
+    }
+    // If the kernel hasn't started yet, it'll find the interrupt pending once
+    // it does.
+    let fd = KERNEL_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        send(
+            fd,
+            &Message {
+                kind: MSG_IRQ,
+                ..Message::default()
+            },
+        );
+    }
+}
+
+/// Delivers any pending interrupts that are enabled. The rest stay
+/// pending until they're enabled.
+fn deliver_irqs() {
+    let ready: Vec<u32> = {
+        let enabled = ENABLED_IRQS.lock().unwrap_lite();
+        let mut pending = PENDING_IRQS.lock().unwrap_lite();
+        let ready = pending
+            .iter()
+            .copied()
+            .filter(|n| enabled.contains(n))
+            .collect();
+        pending.retain(|n| !enabled.contains(n));
+        ready
+    };
+    for n in ready {
+        fire_irq(n);
+    }
+}
+
+/// Delivers enabled interrupt `n` to its owner.
+fn fire_irq(n: u32) {
+    crate::profiling::event_isr_enter();
+    let owner = crate::startup::HUBRIS_IRQ_TASK_LOOKUP
+        .get(abi::InterruptNum(n))
+        .unwrap_lite();
+    let switch = with_task_table(|tasks| {
+        disable_irq(n);
+        let irq_num = n;
+        let n = task::NotificationSet(owner.notification);
+        let owner = &mut tasks[owner.task as usize];
+        let coalesced = owner.has_pending_notifications(n);
+        crate::irq_stats::record_fire(irq_num, coalesced);
+        owner.post(n)
+    });
+    if switch {
+        switch_tasks();
+    }
+    crate::profiling::event_isr_exit();
+}
+
+/// Our equivalent of PendSV: makes whichever task the scheduler now prefers
+/// the current task. The kernel thread stops the running task, if need be,
+/// and starts the new one.
+fn switch_tasks() {
+    with_task_table(|tasks| {
+        let next = task::select(current_index(), tasks);
+        let next = &mut tasks[next];
+        apply_memory_protection(next);
+        // Safety: next comes from the task table and we don't use it again
+        // until next kernel entry, so we meet set_current_task's requirements.
+        unsafe {
+            set_current_task(next);
+        }
+    });
+}
+
+/// Returns the index of the task the kernel has chosen to run.
+fn current_index() -> usize {
+    let task = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    // Safety: this pointer always refers into the task table, and we only
+    // read the immutable descriptor through it.
+    usize::from(unsafe { (*task).descriptor().index })
+}
+
+/// Creates a pipe, returning its read and write ends.
+fn pipe() -> (libc::c_int, libc::c_int) {
+    let mut fds = [0; 2];
+    // Safety: pipe writes two fds into the array we give it.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        panic!("can't create pipe");
+    }
+    (fds[0], fds[1])
+}
+
+/// Writes `msg` to the pipe `fd`. Our messages are smaller than `PIPE_BUF`, so
+/// the write is atomic, and they never get split up or interleaved.
+///
+/// This is async-signal-safe.
+fn send<T: Copy>(fd: libc::c_int, msg: &T) {
+    let size = core::mem::size_of::<T>();
+    loop {
+        // Safety: we're writing out the bytes of a `Copy` value.
+        let n = unsafe { libc::write(fd, (msg as *const T).cast(), size) };
+        if n == size as isize {
+            return;
+        }
+        if n < 0 && errno() == libc::EINTR {
+            continue;
+        }
+        // Nothing sensible can be done about this, least of all in a signal
+        // handler.
+        // Safety: abort is always safe to call.
+        unsafe { libc::abort() }
+    }
+}
+
+/// Reads a message of type `T` from the pipe `fd`, blocking until one
+/// arrives. Only use this for `Message` or `Resume`, whose bytes are always
+/// valid.
+///
+/// This is async-signal-safe.
+fn recv<T: Copy + Default>(fd: libc::c_int) -> T {
+    let mut msg = T::default();
+    let size = core::mem::size_of::<T>();
+    loop {
+        // Safety: messages are written whole by `send`, and any bytes are a
+        // valid `Message` or `Resume`.
+        let n = unsafe { libc::read(fd, (&mut msg as *mut T).cast(), size) };
+        if n == size as isize {
+            return msg;
+        }
+        if n < 0 && errno() == libc::EINTR {
+            continue;
+        }
+        // Safety: abort is always safe to call.
+        unsafe { libc::abort() }
+    }
+}
+
+/// Waits up to `timeout` for a message on the kernel pipe `fd`.
+fn recv_message(fd: libc::c_int, timeout: Duration) -> Option<Message> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ts = libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    // Safety: we pass one valid pollfd, and a valid timeout.
+    let n = unsafe { libc::ppoll(&mut pfd, 1, &ts, core::ptr::null()) };
+    if n > 0 && (pfd.revents & libc::POLLIN) != 0 {
+        Some(recv(fd))
+    } else {
+        None
+    }
+}
+
+fn errno() -> libc::c_int {
+    // Safety: errno is thread-local, and always readable.
+    unsafe { *libc::__errno_location() }
+}
+
+type Handler =
+    extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void);
+
+/// Installs `handler` for `sig`, running on the alternate stack.
+///
+/// # Safety
+///
+/// `handler` must be safe to run as a signal handler in any task thread.
+unsafe fn install_handler(
+    sig: libc::c_int,
+    handler: Handler,
+    mask_preempt: bool,
+) {
+    // Safety: all-zeroes is a valid sigaction, and we fill in the rest of it
+    // before handing it to the OS.
+    unsafe {
+        let mut sa: libc::sigaction = core::mem::zeroed();
+        sa.sa_sigaction = handler as usize;
+        sa.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut sa.sa_mask);
+        if mask_preempt {
+            // Preemption requests arriving during a kernel entry are handled
+            // once the task resumes.
+            libc::sigaddset(&mut sa.sa_mask, PREEMPT_SIGNAL);
+        }
+        if libc::sigaction(sig, &sa, core::ptr::null_mut()) != 0 {
+            panic!("can't install handler for signal {}", sig);
+        }
+    }
+}
+
+// The signal handlers below only touch atomics, const-initialized thread
+// locals, the interrupted context, and pipes. In particular, they mustn't
+// allocate, take locks, or panic.
+
+/// Handler for `PREEMPT_SIGNAL`.
+extern "C" fn preempt_handler(
+    _sig: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    if let Some(me) = TASK_INDEX.with(|t| t.get()) {
+        let msg = Message {
+            task: me as u32,
+            kind: MSG_PARK,
+            ..Message::default()
+        };
+        // Safety: context is the one the OS handed us.
+        unsafe { enter_kernel(msg, context) }
+    }
+}
+
+/// Handler for syscalls and faults.
+extern "C" fn trap_handler(
+    sig: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let me = match TASK_INDEX.with(|t| t.get()) {
+        Some(me) => me,
+        None => {
+            // This is a fault in the kernel (or the host runtime). Put back
+            // the default behavior and let it happen again, so the process
+            // dies in the usual way.
+            // Safety: restoring the default disposition is always okay.
+            unsafe {
+                let mut sa: libc::sigaction = core::mem::zeroed();
+                sa.sa_sigaction = libc::SIG_DFL;
+                libc::sigaction(sig, &sa, core::ptr::null_mut());
+            }
+            return;
+        }
+    };
+    // Safety: the OS hands us a valid ucontext.
+    let gregs =
+        unsafe { &mut (*context.cast::<libc::ucontext_t>()).uc_mcontext.gregs };
+    let pc = gregs[libc::REG_EIP as usize] as u32;
+
+    // Safety: the faulting instruction was fetched from mapped memory.
+    let is_syscall = sig == libc::SIGILL
+        && unsafe { *(pc as usize as *const [u8; 2]) } == [0x0f, 0x0b];
+    let msg = if is_syscall {
+        gregs[libc::REG_EIP as usize] =
+            gregs[libc::REG_EIP as usize].wrapping_add(2);
+        Message {
+            task: me as u32,
+            kind: MSG_SYSCALL,
+            a: gregs[libc::REG_EAX as usize] as u32,
+            b: gregs[libc::REG_ESP as usize] as u32,
+        }
+    } else {
+        Message {
+            task: me as u32,
+            kind: MSG_FAULT,
+            a: sig as u32,
+            // Safety: the OS hands us a valid siginfo.
+            b: unsafe { (*info).si_addr() } as u32,
+        }
+    };
+    // Safety: context is the one the OS handed us.
+    unsafe { enter_kernel(msg, context) }
+}
+
+/// Hands control to the kernel thread with `msg`, and waits to be resumed.
+///
+/// # Safety
+///
+/// `context` must be the `ucontext_t` passed to the current signal handler.
+unsafe fn enter_kernel(msg: Message, context: *mut libc::c_void) {
+    send(KERNEL_PIPE.load(Ordering::Relaxed), &msg);
+    let resume: Resume = recv(RESUME_PIPE.with(|p| p.get()));
+    if resume.restart != 0 {
+        // Safety: per our contract, this is a valid ucontext.
+        let gregs = unsafe {
+            &mut (*context.cast::<libc::ucontext_t>()).uc_mcontext.gregs
+        };
+        for r in [
+            libc::REG_EAX,
+            libc::REG_EBX,
+            libc::REG_ECX,
+            libc::REG_EDX,
+            libc::REG_ESI,
+            libc::REG_EDI,
+            libc::REG_EBP,
+        ] {
+            gregs[r as usize] = 0;
+        }
+        gregs[libc::REG_EIP as usize] = resume.entry_point as i32;
+        // Leave room for a (null) return address, so the entry point sees the
+        // stack alignment it expects.
+        gregs[libc::REG_ESP as usize] =
+            resume.initial_stack.wrapping_sub(4) as i32;
+    }
+}
+
+/// Faults task `index` and switches to whatever should run next.
+fn fault(tasks: &mut [task::Task], index: usize, info: FaultInfo) {
+    let next = match task::force_fault(tasks, index, info) {
+        task::NextTask::Specific(i) => i,
+        task::NextTask::Other => task::select(index, tasks),
+        task::NextTask::Same => index,
+    };
+    let next = &mut tasks[next];
+    apply_memory_protection(next);
+    // Safety: next comes from the task table and we don't use it again
+    // until next kernel entry, so we meet set_current_task's requirements.
+    unsafe {
+        set_current_task(next);
+    }
+}
+
+/// Records the address of `task` as the current user task.
+///
+/// # Safety
+///
+/// This records a pointer that aliases `task`. As long as you don't read that
+/// pointer while you have access to `task`, and as long as the `task` being
+/// stored is actually in the task table, you'll be okay.
+pub unsafe fn set_current_task(task: &mut task::Task) {
+    // We get called after every kernel entry, and most of the time the task
+    // carries on running; only count actual switches.
+    if !core::ptr::eq(CURRENT_TASK_PTR.load(Ordering::Relaxed), task) {
+        task.account_scheduled();
+    }
+    CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
+    crate::profiling::event_context_switch(task as *mut _ as usize);
+}
+
+/// Reads the tick counter.
+pub fn now() -> Timestamp {
+    Timestamp::from(TICKS.load(Ordering::Relaxed))
+}
+
+/// The simulator has no cycle counter, so this reads the bottom half of the
+/// tick counter.
+pub fn cycles() -> u32 {
+    u64::from(now()) as u32
+}
+
+/// Makes sure that timers get processed by `deadline`. The simulator ticks
+/// steadily whether or not anything is waiting, so there's nothing to do.
+pub fn request_wakeup(_deadline: Timestamp) {}
+
+pub fn disable_irq(n: u32) {
+    ENABLED_IRQS.lock().unwrap_lite().retain(|&i| i != n);
+}
+
+pub fn enable_irq(n: u32) {
+    let mut irqs = ENABLED_IRQS.lock().unwrap_lite();
+    if !irqs.contains(&n) {
+        irqs.push(n);
+    }
+}
+
+/// Marks one of the kernel's statics as in use, by setting its `in_use` flag.
+///
+/// The kernel never reenters itself, so finding the flag already set means
+/// the static is being used recursively, which is a bug: we panic.
+pub fn claim_static(in_use: &AtomicBool) {
+    if in_use.swap_polyfill(true, Ordering::Acquire) {
+        panic!(); // recursive use
+    }
+}
+
+/// Resets the simulated machine by re-executing the simulator.
+pub fn reset() -> ! {
+    use std::os::unix::process::CommandExt;
+
+    let exe = std::env::current_exe().unwrap_lite();
+    let err = std::process::Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    panic!("reset failed: {}", err)
+}
+
+impl AtomicExt for AtomicBool {
+    type Primitive = bool;
+
+    #[inline(always)]
+    fn swap_polyfill(
+        &self,
+        value: Self::Primitive,
+        ordering: Ordering,
+    ) -> Self::Primitive {
+        self.swap(value, ordering)
+    }
+}
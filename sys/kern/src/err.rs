@@ -16,7 +16,7 @@ use crate::task::{self, NextTask, Task};
 ///
 /// This is used internally as the returned error type for syscall
 /// implementations.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum UserError {
     /// A recoverable error. Recoverable errors are indicated to the errant task
     /// by returning a response code (the `u32` field). They may still cause a
@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel event log.
+//!
+//! The kernel records noteworthy events -- faults, restarts, and the like --
+//! in a small fixed-size ring, from which a privileged task (normally the
+//! supervisor) can drain them using the `read_kernel_event` kernel IPC. This
+//! gives the system a way of getting this information off the board that
+//! doesn't depend on a debugger being attached.
+//!
+//! When the ring is full, new events are counted and discarded rather than
+//! overwriting old ones. The count is reported as a `KernelEvent::Dropped`
+//! once the reader catches up, so the reader always sees events in order and
+//! knows where the gap is.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use abi::{KernelEvent, KernelEventRecord};
+
+/// Number of events the kernel can hold before it starts dropping them.
+const EVENT_LOG_LEN: usize = 16;
+
+struct EventLog {
+    entries: [Option<KernelEventRecord>; EVENT_LOG_LEN],
+    /// Index of the oldest entry.
+    first: usize,
+    /// Number of valid entries, starting at `first` and wrapping.
+    count: usize,
+    /// Number of events discarded since the log filled up.
+    dropped: u32,
+    /// Kernel time at which the first of the `dropped` events happened.
+    dropped_since: u64,
+}
+
+static mut EVENT_LOG: EventLog = EventLog {
+    entries: [None; EVENT_LOG_LEN],
+    first: 0,
+    count: 0,
+    dropped: 0,
+    dropped_since: 0,
+};
+
+/// Tracks when a mutable reference to `EVENT_LOG` is outstanding.
+static EVENT_LOG_IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Runs `body` with a reference to the event log.
+///
+/// Like `with_task_table`, this panics if called recursively (see
+/// `arch::claim_static`).
+fn with_event_log<R>(body: impl FnOnce(&mut EventLog) -> R) -> R {
+    crate::arch::claim_static(&EVENT_LOG_IN_USE);
+    // Safety: we have observed `EVENT_LOG_IN_USE` being false, which means
+    // we're not already within a call to with_event_log, so this reference
+    // can't alias.
+    let log = unsafe { &mut *core::ptr::addr_of_mut!(EVENT_LOG) };
+
+    let r = body(log);
+
+    EVENT_LOG_IN_USE.store(false, Ordering::Release);
+
+    r
+}
+
+/// Records `event` in the log, stamped with the current kernel time.
+pub fn record(event: KernelEvent) {
+    let timestamp = u64::from(crate::arch::now());
+    with_event_log(|log| {
+        // Once we've started dropping, keep dropping until the reader has
+        // seen the `Dropped` marker, so that nothing appears out of order.
+        if log.count == EVENT_LOG_LEN || log.dropped != 0 {
+            if log.dropped == 0 {
+                log.dropped_since = timestamp;
+            }
+            log.dropped = log.dropped.saturating_add(1);
+            return;
+        }
+        let i = (log.first + log.count) % EVENT_LOG_LEN;
+        log.entries[i] = Some(KernelEventRecord { timestamp, event });
+        log.count += 1;
+    })
+}
+
+/// Removes and returns the oldest event in the log, or `None` if the log is
+/// empty.
+pub fn take() -> Option<KernelEventRecord> {
+    with_event_log(|log| {
+        if log.count != 0 {
+            let record = log.entries[log.first].take();
+            log.first = (log.first + 1) % EVENT_LOG_LEN;
+            log.count -= 1;
+            record
+        } else if log.dropped != 0 {
+            let count = core::mem::replace(&mut log.dropped, 0);
+            Some(KernelEventRecord {
+                timestamp: log.dropped_since,
+                event: KernelEvent::Dropped { count },
+            })
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,179 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-interrupt statistics.
+//!
+//! The kernel counts each hardware interrupt it dispatches to a task, and
+//! times how long the owning task takes to re-enable it using `irq_control`.
+//! Times come from `arch::cycles`, which is the cycle counter where there is
+//! one.
+//! A privileged task (normally the supervisor) can read these with the
+//! `read_irq_stats` kernel IPC, which makes interrupt storms and slow drivers
+//! visible without a debugger attached.
+//!
+//! There's an entry for each interrupt in the application config, found by
+//! binary search in `HUBRIS_IRQ_NUMS`, which the build script sorts for us.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use abi::IrqStats;
+
+use crate::atomic::AtomicExt;
+use crate::startup::HUBRIS_IRQ_NUMS;
+
+/// Statistics for a single interrupt, along with what we need to keep them.
+#[derive(Copy, Clone, Debug, Default)]
+struct IrqRecord {
+    stats: IrqStats,
+    /// Time of the earliest fire that the owner hasn't yet re-enabled the
+    /// interrupt after, if any.
+    fired_at: Option<u32>,
+}
+
+impl IrqRecord {
+    const NEW: Self = Self {
+        stats: IrqStats {
+            fires: 0,
+            coalesced: 0,
+            handled: 0,
+            total_latency: 0,
+            max_latency: 0,
+        },
+        fired_at: None,
+    };
+
+    /// Records the interrupt firing at time `now`. It's `coalesced` with an
+    /// earlier fire if its owner still has the notification from that one
+    /// pending.
+    fn fired(&mut self, now: u32, coalesced: bool) {
+        self.stats.fires = self.stats.fires.wrapping_add(1);
+        if coalesced {
+            self.stats.coalesced = self.stats.coalesced.wrapping_add(1);
+        }
+        // If the owner hasn't caught up with an earlier fire, keep timing
+        // from that one.
+        self.fired_at.get_or_insert(now);
+    }
+
+    /// Records the owner re-enabling the interrupt at time `now`.
+    fn enabled(&mut self, now: u32) {
+        // Tasks often enable their interrupts before they've ever fired;
+        // there's nothing to time then.
+        if let Some(fired_at) = self.fired_at.take() {
+            // The clock wraps, so this is only right for latencies of less
+            // than 2^32 cycles, which is all we care about.
+            let latency = u64::from(now.wrapping_sub(fired_at));
+            let stats = &mut self.stats;
+            stats.handled = stats.handled.wrapping_add(1);
+            stats.total_latency = stats.total_latency.wrapping_add(latency);
+            stats.max_latency = stats.max_latency.max(latency);
+        }
+    }
+}
+
+static mut IRQ_RECORDS: [IrqRecord; HUBRIS_IRQ_NUMS.len()] =
+    [IrqRecord::NEW; HUBRIS_IRQ_NUMS.len()];
+
+/// Tracks when a mutable reference to `IRQ_RECORDS` is outstanding.
+static IRQ_RECORDS_IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Runs `body` with a reference to the record for interrupt `irq`, if it's
+/// one of ours.
+///
+/// Like `with_task_table`, this panics if called recursively.
+fn with_irq_record<R>(
+    irq: u32,
+    body: impl FnOnce(&mut IrqRecord) -> R,
+) -> Option<R> {
+    let index = HUBRIS_IRQ_NUMS.binary_search(&irq).ok()?;
+    if IRQ_RECORDS_IN_USE.swap_polyfill(true, Ordering::Acquire) {
+        panic!(); // recursive use of with_irq_record
+    }
+    // Safety: we have observed `IRQ_RECORDS_IN_USE` being false, which means
+    // we're not already within a call to with_irq_record, so this reference
+    // can't alias.
+    let records = unsafe { &mut *core::ptr::addr_of_mut!(IRQ_RECORDS) };
+
+    let r = body(&mut records[index]);
+
+    IRQ_RECORDS_IN_USE.store(false, Ordering::Release);
+
+    Some(r)
+}
+
+/// Records interrupt `irq` firing. It's `coalesced` with an earlier fire if
+/// its owner still has the notification from that one pending.
+pub fn record_fire(irq: u32, coalesced: bool) {
+    let now = crate::arch::cycles();
+    with_irq_record(irq, |r| r.fired(now, coalesced));
+}
+
+/// Records interrupt `irq` being re-enabled by its owner.
+pub fn record_enable(irq: u32) {
+    let now = crate::arch::cycles();
+    with_irq_record(irq, |r| r.enabled(now));
+}
+
+/// Returns the statistics for interrupt `irq`, or `None` if it isn't in the
+/// application config.
+pub fn stats(irq: u32) -> Option<IrqStats> {
+    with_irq_record(irq, |r| r.stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enable_before_fire_is_not_timed() {
+        let mut r = IrqRecord::NEW;
+        r.enabled(5);
+        assert_eq!(r.stats, IrqStats::default());
+    }
+
+    #[test]
+    fn latency_runs_from_fire_to_enable() {
+        let mut r = IrqRecord::NEW;
+        r.fired(10, false);
+        r.enabled(13);
+        r.fired(20, false);
+        r.enabled(21);
+        assert_eq!(
+            r.stats,
+            IrqStats {
+                fires: 2,
+                coalesced: 0,
+                handled: 2,
+                total_latency: 4,
+                max_latency: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn repeated_fires_are_timed_from_the_first() {
+        let mut r = IrqRecord::NEW;
+        r.fired(10, false);
+        r.fired(12, true);
+        r.enabled(15);
+        assert_eq!(
+            r.stats,
+            IrqStats {
+                fires: 2,
+                coalesced: 1,
+                handled: 1,
+                total_latency: 5,
+                max_latency: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn latency_survives_the_clock_wrapping() {
+        let mut r = IrqRecord::NEW;
+        r.fired(u32::MAX - 1, false);
+        r.enabled(3);
+        assert_eq!(r.stats.total_latency, 5);
+    }
+}
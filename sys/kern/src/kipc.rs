@@ -4,12 +4,15 @@
 
 //! Implementation of IPC operations on the virtual kernel task.
 
-use abi::{FaultInfo, Kipcnum, SchedState, TaskState, UsageError};
+use abi::{
+    AsyncSendStatus, FaultInfo, KernelEvent, Kipcnum, SchedState, TaskState,
+    UsageError,
+};
 
 use crate::arch;
-use crate::err::UserError;
-use crate::task::{current_id, ArchState, NextTask, Task};
-use crate::umem::USlice;
+use crate::err::{InteractFault, UserError};
+use crate::task::{current_id, ArchState, AsyncSend, NextTask, Task};
+use crate::umem::{safe_copy, USlice};
 use core::convert::TryFrom;
 
 /// Message dispatcher.
@@ -30,6 +33,31 @@ pub fn handle_kernel_message(
             read_image_id(tasks, caller, args.response?)
         }
         Ok(Kipcnum::Reset) => reset(tasks, caller, args.message?),
+        Ok(Kipcnum::ReadTaskStats) => {
+            read_task_stats(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadKernelEvent) => {
+            read_kernel_event(tasks, caller, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskRegisters) => {
+            read_task_registers(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskStack) => {
+            read_task_stack(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadPanicMessage) => {
+            read_panic_message(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::CollectAsyncReply) => {
+            collect_async_reply(tasks, caller, args.response?)
+        }
+        Ok(Kipcnum::CancelAsyncSend) => cancel_async_send(tasks, caller),
+        Ok(Kipcnum::ReadStackUsage) => {
+            read_stack_usage(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadIrqStats) => {
+            read_irq_stats(tasks, caller, args.message?, args.response?)
+        }
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
             Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
@@ -78,7 +106,7 @@ fn read_task_status(
     tasks: &mut [Task],
     caller: usize,
     message: USlice<u8>,
-    response: USlice<u8>,
+    mut response: USlice<u8>,
 ) -> Result<NextTask, UserError> {
     let index: u32 = deserialize_message(&tasks[caller], message)?;
     if index as usize >= tasks.len() {
@@ -88,15 +116,252 @@ fn read_task_status(
     }
     // cache other state before taking out a mutable borrow on tasks
     let other_state = *tasks[index as usize].state();
+    // The effective priority, including any inherited boost, follows the
+    // state for callers that leave room for it.
+    let priority = tasks[index as usize].priority().0;
+
+    let buf = tasks[caller].try_write(&mut response)?;
+    let response_len = match ssmarshal::serialize(buf, &(other_state, priority))
+    {
+        Ok(size) => size,
+        // Callers that only leave room for the state get just the state.
+        Err(ssmarshal::Error::EndOfStream) => {
+            serialize_response(&mut tasks[caller], response, &other_state)?
+        }
+        Err(_) => return Err(UsageError::BadKernelMessage.into()),
+    };
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_task_stats(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let stats = *tasks[index as usize].stats();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &stats)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_stack_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let usage = tasks[index as usize].stack_usage();
 
     let response_len =
-        serialize_response(&mut tasks[caller], response, &other_state)?;
+        serialize_response(&mut tasks[caller], response, &usage)?;
     tasks[caller]
         .save_mut()
         .set_send_response_and_length(0, response_len);
     Ok(NextTask::Same)
 }
 
+/// Reads the statistics for an interrupt, _by number._ Interrupts that aren't
+/// in the application config have none, and get `None`.
+fn read_irq_stats(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let irq: u32 = deserialize_message(&tasks[caller], message)?;
+    let stats = crate::irq_stats::stats(irq);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &stats)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_kernel_event(
+    tasks: &mut [Task],
+    caller: usize,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let event = crate::events::take();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &event)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+/// Reads the registers of a faulted task, as they were when it faulted. For
+/// tasks that aren't faulted, this returns `None`, since their registers are
+/// none of anyone's business.
+fn read_task_registers(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let task = &tasks[index as usize];
+    let regs = match task.state() {
+        TaskState::Faulted { .. } => Some(task.save().saved_registers()),
+        TaskState::Healthy(_) => None,
+    };
+
+    let response_len = serialize_response(&mut tasks[caller], response, &regs)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+/// Copies the live part of a faulted task's stack -- from its saved stack
+/// pointer up to its initial stack pointer -- into the response buffer, as raw
+/// bytes, truncating to fit. If the task isn't faulted, or its stack pointer
+/// doesn't point into its memory (as after a stack overflow), this copies
+/// nothing.
+fn read_task_stack(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    // A faulted task can't be the caller, which safe_copy relies on.
+    let mut copied = 0;
+    if let TaskState::Faulted { .. } = tasks[index].state() {
+        let sp = tasks[index].save().stack_pointer() as usize;
+        let top = tasks[index].descriptor().initial_stack as usize;
+        let stack = USlice::from_raw(sp, top.saturating_sub(sp))
+            .unwrap_or_else(|_| USlice::empty());
+        match safe_copy(tasks, index, stack, caller, response) {
+            Ok(n) => copied = n,
+            // Our caller's buffer is bad: that's on them.
+            Err(InteractFault { dst: Some(f), .. }) => return Err(f.into()),
+            // The task's stack pointer is bad: that's why we're here.
+            Err(_) => (),
+        }
+    }
+
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, copied);
+    Ok(NextTask::Same)
+}
+
+/// Copies the message a task panicked with, as raw bytes, into the response
+/// buffer, truncating to fit. This copies nothing unless the task is faulted
+/// because it panicked.
+fn read_panic_message(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    // The message lives in the kernel, so copy it out to a local first to
+    // avoid borrowing two tasks at once.
+    let mut buf = [0; abi::PANIC_MESSAGE_MAX];
+    let len = match tasks[index].state() {
+        TaskState::Faulted {
+            fault: FaultInfo::Panic,
+            ..
+        } => {
+            let msg = tasks[index].panic_message();
+            buf[..msg.len()].copy_from_slice(msg);
+            msg.len()
+        }
+        _ => 0,
+    };
+
+    let dest = tasks[caller].try_write(&mut response)?;
+    let n = len.min(dest.len());
+    dest[..n].copy_from_slice(&buf[..n]);
+    tasks[caller].save_mut().set_send_response_and_length(0, n);
+    Ok(NextTask::Same)
+}
+
+/// Reports on the caller's asynchronous send. If it's over, this also forgets
+/// about it, so that the caller can make another.
+fn collect_async_reply(
+    tasks: &mut [Task],
+    caller: usize,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let status = match tasks[caller].async_send() {
+        AsyncSend::Idle => AsyncSendStatus::Idle,
+        AsyncSend::Queued(_) | AsyncSend::AwaitingReply(_) => {
+            AsyncSendStatus::Pending
+        }
+        &AsyncSend::Done { code, len } => AsyncSendStatus::Done {
+            code,
+            len: len as u32,
+        },
+    };
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &status)?;
+    if let AsyncSendStatus::Done { .. } = status {
+        tasks[caller].set_async_send(AsyncSend::Idle);
+    }
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+/// Abandons the caller's asynchronous send, whatever state it's in. If the
+/// recipient has already received the message, its reply will be discarded.
+fn cancel_async_send(
+    tasks: &mut [Task],
+    caller: usize,
+) -> Result<NextTask, UserError> {
+    tasks[caller].set_async_send(AsyncSend::Idle);
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
+    Ok(NextTask::Same)
+}
+
 fn restart_task(
     tasks: &mut [Task],
     caller: usize,
@@ -111,16 +376,35 @@ fn restart_task(
         )));
     }
     let old_id = current_id(tasks, index);
+    let old_peer = crate::task::ipc_peer(tasks, index);
     tasks[index].reinitialize();
     if start {
         tasks[index].set_healthy_state(SchedState::Runnable);
     }
+    crate::events::record(KernelEvent::TaskRestarted {
+        task: index as u16,
+        started: start,
+    });
 
     // Restarting a task can have implications for other tasks. We don't want to
     // leave tasks sitting around waiting for a reply that will never come, for
     // example. So, make a pass over the task table and unblock anyone who was
     // expecting useful work from the now-defunct task.
     for (i, task) in tasks.iter_mut().enumerate() {
+        // Asynchronous sends are finished off with the same condolences as
+        // blocking ones below. The supervisor may well have one outstanding to
+        // the task it's restarting, so this doesn't skip the caller.
+        if i != index
+            && (task.is_async_sending_to(old_id)
+                || task.is_awaiting_async_reply_from(old_id))
+        {
+            let code = abi::dead_response_code(old_id.generation());
+            task.complete_async_send(code, 0);
+        }
+
+        // Nor will the task reply to messages its senders have given up on.
+        task.take_stale_reply_from(old_id);
+
         // Just to make this a little easier to think about, don't check either
         // of the tasks involved in the restart operation. Neither should be
         // affected anyway.
@@ -149,6 +433,13 @@ fn restart_task(
         }
     }
 
+    // If the task was blocked in IPC, whoever it was blocked on no longer
+    // inherits its priority. (Anyone blocked on the task has been unblocked
+    // above, and `reinitialize` dropped the boost they'd lent it.)
+    if let Some(peer) = old_peer {
+        crate::task::recompute_priority(tasks, peer);
+    }
+
     if index == caller {
         // Welp, they've restarted themselves. Best not return anything then.
         if !start {
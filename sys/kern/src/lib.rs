@@ -39,10 +39,14 @@ pub mod arch;
 
 pub mod atomic;
 pub mod err;
+pub mod events;
+pub mod irq_stats;
 pub mod kipc;
 pub mod profiling;
 pub mod startup;
 pub mod syscalls;
 pub mod task;
+#[cfg(test)]
+mod testing;
 pub mod time;
 pub mod umem;
@@ -145,4 +145,14 @@ pub(crate) fn with_task_table<R>(body: impl FnOnce(&mut [Task]) -> R) -> R {
     r
 }
 
+/// Returns the table of memory regions used by tasks.
+///
+/// On hardware these are only ever consulted through each task's region
+/// table, but the simulator needs the whole set up front to map task memory
+/// into the host process.
+#[cfg(hubris_sim)]
+pub(crate) fn region_descs() -> &'static [abi::RegionDesc] {
+    &HUBRIS_REGION_DESCS
+}
+
 include!(concat!(env!("OUT_DIR"), "/kconfig.rs"));
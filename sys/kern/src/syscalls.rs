@@ -38,7 +38,9 @@ use unwrap_lite::UnwrapLite;
 use crate::arch;
 use crate::err::{InteractFault, UserError};
 use crate::startup::with_task_table;
-use crate::task::{self, current_id, ArchState, NextTask, Task};
+use crate::task::{
+    self, current_id, ArchState, AsyncSend, AsyncSendArgs, NextTask, Task,
+};
 use crate::time::Timestamp;
 use crate::umem::{safe_copy, USlice};
 
@@ -96,28 +98,43 @@ pub unsafe extern "C" fn syscall_entry(nr: u32, task: *mut Task) {
 /// Factored out of `syscall_entry` to encapsulate the bits that don't need
 /// unsafe.
 fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
-    let res = match Sysnum::try_from(nr) {
-        Ok(Sysnum::Send) => send(tasks, current),
+    tasks[current].account_syscall();
+
+    // The high bits of the descriptor are only meaningful to `SendTimeout`
+    // and `SendAsync`; anything else with them set is as bogus as an unknown
+    // number.
+    let extra = nr >> abi::SYSNUM_BITS;
+    let sysnum = match Sysnum::try_from(nr & !(!0 << abi::SYSNUM_BITS)) {
+        Ok(Sysnum::SendTimeout) => Ok(Sysnum::SendTimeout),
+        Ok(Sysnum::SendAsync) if extra < 32 => Ok(Sysnum::SendAsync),
+        Ok(_) if extra != 0 => Err(()),
+        other => other,
+    };
+    let res = match sysnum {
+        Ok(Sysnum::Send) => send(tasks, current, None),
+        Ok(Sysnum::SendTimeout) => send(tasks, current, Some(extra)),
         Ok(Sysnum::Recv) => recv(tasks, current).map_err(UserError::from),
         Ok(Sysnum::Reply) => reply(tasks, current).map_err(UserError::from),
-        Ok(Sysnum::SetTimer) => Ok(set_timer(&mut tasks[current], arch::now())),
+        Ok(Sysnum::SetTimer) => set_timer(&mut tasks[current], arch::now()),
         Ok(Sysnum::BorrowRead) => borrow_read(tasks, current),
         Ok(Sysnum::BorrowWrite) => borrow_write(tasks, current),
         Ok(Sysnum::BorrowInfo) => borrow_info(tasks, current),
         Ok(Sysnum::IrqControl) => irq_control(tasks, current),
         Ok(Sysnum::Panic) => explicit_panic(tasks, current),
-        Ok(Sysnum::GetTimer) => Ok(get_timer(&mut tasks[current], arch::now())),
+        Ok(Sysnum::GetTimer) => get_timer(&mut tasks[current], arch::now()),
         Ok(Sysnum::RefreshTaskId) => refresh_task_id(tasks, current),
         Ok(Sysnum::Post) => post(tasks, current),
         Ok(Sysnum::ReplyFault) => {
             reply_fault(tasks, current).map_err(UserError::from)
         }
+        Ok(Sysnum::SendAsync) => send_async(tasks, current, extra),
         Err(_) => {
             // Bogus syscall number! That's a fault.
             Err(FaultInfo::SyscallUsage(UsageError::BadSyscallNumber).into())
         }
     };
-    match res {
+    let prio = tasks[current].priority();
+    let next = match res {
         Ok(nt) => nt,
         Err(UserError::Recoverable(code, hint)) => {
             tasks[current].save_mut().set_error_response(code);
@@ -126,36 +143,72 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
         Err(UserError::Unrecoverable(fault)) => {
             task::force_fault(tasks, current, fault)
         }
+    };
+
+    // If the syscall cost the caller a boost it had inherited (say, by
+    // replying to the client that lent it), a more important task may now be
+    // waiting for the CPU.
+    if next == NextTask::Same
+        && prio.is_more_important_than(tasks[current].priority())
+    {
+        NextTask::Other
+    } else {
+        next
     }
 }
 
-/// Implementation of the SEND IPC primitive.
+/// Implementation of the SEND IPC primitive, and its `SEND_TIMEOUT` variant.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
 ///
+/// `timeout` is the number of ticks after which the kernel will give up on the
+/// send (or on waiting for its reply) and resume the caller with `TIMED_OUT`.
+/// If `None`, the caller may wait forever.
+///
 /// # Panics
 ///
 /// If `caller` is out of range for `tasks`.
-fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
+fn send(
+    tasks: &mut [Task],
+    caller: usize,
+    timeout: Option<u32>,
+) -> Result<NextTask, UserError> {
     // Extract callee.
     let callee_id = tasks[caller].save().as_send_args().callee;
 
-    // Check IPC filter - TODO
-    // Open question: should out-of-range task IDs be handled by faulting below,
-    // or by failing the IPC filter? Either condition will fault...
-
-    // Route kernel messages.
+    // Route kernel messages. These are handled on the spot, so there's no
+    // need for a deadline.
     if callee_id == TaskId::KERNEL {
         return crate::kipc::handle_kernel_message(tasks, caller);
     }
 
+    // Arm the send deadline, if any, before we might block. It's cleared
+    // again when the send finishes, however that happens.
+    let deadline =
+        timeout.map(|t| Timestamp::from(u64::from(arch::now()) + u64::from(t)));
+    tasks[caller].set_send_deadline(deadline);
+    if let Some(deadline) = deadline {
+        arch::request_wakeup(deadline);
+    }
+
+    // The callee couldn't tell a reply to this message from a reply to an
+    // asynchronous one we've already sent it, so we only allow one at a time.
+    if tasks[caller].is_async_sending_to(callee_id)
+        || tasks[caller].is_awaiting_async_reply_from(callee_id)
+    {
+        return Err(FaultInfo::SyscallUsage(UsageError::AsyncSendBusy).into());
+    }
+
     // Verify the given callee ID, converting it into a table index on success.
     let callee = task::check_task_id_against_table(tasks, callee_id)?;
+    check_caller_allowed(tasks, caller, callee)?;
 
     // Check for ready peer.
     let mut next_task = NextTask::Same;
     let caller_id = current_id(tasks, caller);
-    if tasks[callee].state().can_accept_message_from(caller_id) {
+    if tasks[callee].state().can_accept_message_from(caller_id)
+        && !tasks[caller].is_owed_stale_reply_by(callee_id)
+    {
         // Callee is waiting in receive -- either an open receive, or a
         // closed receive from just us. Either way, we can directly deliver the
         // message and switch tasks...unless either task was naughty, in which
@@ -164,6 +217,7 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
             Ok(_) => {
                 // Delivery succeeded! The initiating task is now blocked in
                 // reply. Switch directly to the callee.
+                task::lend_priority(tasks, caller);
                 return Ok(NextTask::Specific(callee));
             }
             Err(interact) => {
@@ -181,11 +235,104 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     // Caller needs to block sending, callee is either busy or
     // faulted.
     tasks[caller].set_healthy_state(SchedState::InSend(callee_id));
+    task::lend_priority(tasks, caller);
     // We may not know what task to run next, but we're pretty sure it isn't the
     // caller.
     Ok(NextTask::Other.combine(next_task))
 }
 
+/// Implementation of the `SEND_ASYNC` syscall.
+///
+/// This is like SEND, except that the caller keeps running. The message is
+/// delivered when the callee gets around to receiving it, and the reply is
+/// written into the caller's response buffer whenever it comes, at which point
+/// the kernel posts notification bit `bit` to the caller. The caller then finds
+/// out how it went using the `collect_async_reply` kernel IPC.
+///
+/// A task can have only one asynchronous send outstanding, and it can't lend
+/// memory with it.
+///
+/// `caller` is a valid task index (i.e. not directly from user code).
+///
+/// # Panics
+///
+/// If `caller` is out of range for `tasks`.
+fn send_async(
+    tasks: &mut [Task],
+    caller: usize,
+    bit: u32,
+) -> Result<NextTask, UserError> {
+    let send_args = tasks[caller].save().as_send_args();
+
+    if !matches!(tasks[caller].async_send(), AsyncSend::Idle) {
+        return Err(FaultInfo::SyscallUsage(UsageError::AsyncSendBusy).into());
+    }
+    if send_args.lease_table?.len() != 0 {
+        return Err(FaultInfo::SyscallUsage(UsageError::AsyncSendLeases).into());
+    }
+    let message = send_args.message?;
+    let response = send_args.response?;
+
+    // The kernel doesn't take asynchronous messages, and nor can we deliver a
+    // message to a task that's still running.
+    let caller_id = current_id(tasks, caller);
+    let callee_id = send_args.callee;
+    if callee_id == TaskId::KERNEL || callee_id == caller_id {
+        return Err(FaultInfo::SyscallUsage(UsageError::IllegalTask).into());
+    }
+
+    // Verify the given callee ID, converting it into a table index on success.
+    // As with SEND, a dead callee gets the caller an error code.
+    let callee = task::check_task_id_against_table(tasks, callee_id)?;
+    check_caller_allowed(tasks, caller, callee)?;
+
+    let args = AsyncSendArgs {
+        callee: callee_id,
+        operation: send_args.operation,
+        message,
+        response,
+        notification: task::NotificationSet(1 << bit),
+    };
+    tasks[caller].set_async_send(AsyncSend::Queued(args.clone()));
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
+
+    if tasks[callee].state().can_accept_message_from(caller_id)
+        && !tasks[caller].is_owed_stale_reply_by(callee_id)
+    {
+        // The callee is ready now, so hand it the message straight away.
+        match deliver_async(tasks, caller, callee, args) {
+            Ok(()) => {
+                let prio = tasks[caller].priority();
+                if tasks[callee].priority().is_more_important_than(prio) {
+                    return Ok(NextTask::Specific(callee));
+                }
+            }
+            Err(interact) => {
+                // Whoever misbehaved gets faulted. If it was the callee, the
+                // send stays queued until the callee is restarted, which
+                // finishes it off with the usual dead-task response code.
+                return Ok(interact.apply_to_dst(tasks, callee)?);
+            }
+        }
+    }
+
+    Ok(NextTask::Same)
+}
+
+/// Checks the allow-list, if any, that `callee` has for tasks sending to it.
+/// Sending to a task that doesn't allow it is a fault in `caller`.
+fn check_caller_allowed(
+    tasks: &[Task],
+    caller: usize,
+    callee: usize,
+) -> Result<(), UserError> {
+    if tasks[callee].descriptor().allows_caller(caller) {
+        Ok(())
+    } else {
+        Err(FaultInfo::SyscallUsage(UsageError::CallerNotAllowed).into())
+    }
+}
+
 /// Implementation of the RECV IPC primitive.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
@@ -227,9 +374,9 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         // First possibility: that task you're asking about is DEAD.
         let sender_idx = task::check_task_id_against_table(tasks, sender_id)?;
         // Second possibility: task has a message for us.
-        if tasks[sender_idx].state().is_sending_to(caller_id) {
+        if let Some(message) = pending_message(&tasks[sender_idx], caller_id) {
             // Oh hello sender!
-            match deliver(tasks, sender_idx, caller) {
+            match deliver_from(tasks, sender_idx, caller, message) {
                 Ok(_) => {
                     // Delivery succeeded! Sender is now blocked in reply. Go ahead
                     // and let the caller resume.
@@ -262,12 +409,18 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         // the caller.
         let mut last = caller; // keep track of scan position.
 
-        // Is anyone blocked waiting to send to us?
-        while let Some(sender) = task::priority_scan(last, tasks, |t| {
-            t.state().is_sending_to(caller_id)
-        }) {
-            // Oh hello sender!
-            match deliver(tasks, sender, caller) {
+        // Does anyone have a message waiting for us?
+        while let Some(sender) =
+            task::priority_scan(last, tasks, |t| has_message_for(t, caller_id))
+        {
+            // Oh hello sender! (The scan has just checked that there's a
+            // message, but if there somehow weren't, there'd be nothing to
+            // do but block.)
+            let message = match pending_message(&tasks[sender], caller_id) {
+                Some(message) => message,
+                None => break,
+            };
+            match deliver_from(tasks, sender, caller, message) {
                 Ok(_) => {
                     // Delivery succeeded! Sender is now blocked in reply. Go ahead
                     // and let the caller resume.
@@ -317,9 +470,18 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
         Ok(x) => x,
     };
 
+    if tasks[callee].take_stale_reply_from(caller_id) {
+        // This is the reply to a message the callee gave up waiting for. It's
+        // not waiting for this one, whatever it's doing now, so drop it.
+        return Ok(NextTask::Same);
+    }
+
     if tasks[callee].state()
         != &TaskState::Healthy(SchedState::InReply(caller_id))
     {
+        if tasks[callee].is_awaiting_async_reply_from(caller_id) {
+            return reply_async(tasks, caller, callee);
+        }
         // Huh. The target task is off doing something else. This can happen if
         // application-specific supervisory logic unblocks it before we've had a
         // chance to reply (e.g. to implement timeouts).
@@ -380,6 +542,9 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
         .save_mut()
         .set_send_response_and_length(reply_args.response_code, amount_copied);
     tasks[callee].set_healthy_state(SchedState::Runnable);
+    // The caller no longer inherits the callee's priority.
+    // `safe_syscall_entry` takes care of switching away if that matters.
+    task::recompute_priority(tasks, caller);
 
     // KEY ASSUMPTION: sends go from less important tasks to more important
     // tasks. As a result, Reply doesn't have scheduling implications unless
@@ -387,31 +552,79 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
     Ok(NextTask::Same)
 }
 
+/// Delivers a reply to an asynchronous send. `reply` has checked that
+/// `callee` is waiting for one from `caller`.
+fn reply_async(
+    tasks: &mut [Task],
+    caller: usize,
+    callee: usize,
+) -> Result<NextTask, FaultInfo> {
+    let reply_args = tasks[caller].save().as_reply_args();
+    let src_slice = reply_args
+        .message
+        .map_err(|_| FaultInfo::SyscallUsage(UsageError::InvalidSlice))?;
+    let dest_slice = match tasks[callee].async_send() {
+        AsyncSend::AwaitingReply(args) => args.response.clone(),
+        _ => return Ok(NextTask::Same),
+    };
+
+    let amount_copied = safe_copy(tasks, caller, src_slice, callee, dest_slice);
+    let amount_copied = match amount_copied {
+        Ok(n) => n,
+        Err(interact) => return interact.apply_to_dst(tasks, callee),
+    };
+
+    // The sender is only woken if it was waiting for the notification, and
+    // unlike a synchronous sender, it may well be more important than us.
+    let prio = tasks[caller].priority();
+    if tasks[callee]
+        .complete_async_send(reply_args.response_code, amount_copied)
+        && tasks[callee].priority().is_more_important_than(prio)
+    {
+        Ok(NextTask::Specific(callee))
+    } else {
+        Ok(NextTask::Same)
+    }
+}
+
 /// Implementation of the `SET_TIMER` syscall.
-fn set_timer(task: &mut Task, now: Timestamp) -> NextTask {
+fn set_timer(task: &mut Task, now: Timestamp) -> Result<NextTask, UserError> {
     let args = task.save().as_set_timer_args();
+    check_timer_index(args.timer)?;
     if let Some(deadline) = args.deadline {
         // timer is being enabled
         if deadline <= now {
             // timer is already expired
-            task.set_timer(None, args.notification);
+            task.set_timer(args.timer, None, args.notification);
             // We don't care if we woke the task, because it's already running!
             let _ = task.post(args.notification);
-            return NextTask::Same;
+            return Ok(NextTask::Same);
         }
+        arch::request_wakeup(deadline);
     }
-    task.set_timer(args.deadline, args.notification);
-    NextTask::Same
+    task.set_timer(args.timer, args.deadline, args.notification);
+    Ok(NextTask::Same)
 }
 
 /// Implementation of the `GET_TIMER` syscall.
-fn get_timer(task: &mut Task, now: Timestamp) -> NextTask {
-    // This syscall takes no arguments.
+fn get_timer(task: &mut Task, now: Timestamp) -> Result<NextTask, UserError> {
+    let args = task.save().as_get_timer_args();
+    check_timer_index(args.timer)?;
 
-    let (dl, n) = task.timer();
+    let (dl, n) = task.timer(args.timer);
 
     task.save_mut().set_time_result(now, dl, n);
-    NextTask::Same
+    Ok(NextTask::Same)
+}
+
+/// Checks a user-provided timer index against the number of timers each task
+/// has been configured with.
+fn check_timer_index(timer: usize) -> Result<(), UserError> {
+    if timer < crate::startup::TIMERS_PER_TASK {
+        Ok(())
+    } else {
+        Err(FaultInfo::SyscallUsage(UsageError::TimerOutOfRange).into())
+    }
 }
 
 fn borrow_read(
@@ -424,7 +637,7 @@ fn borrow_read(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease =
+    let (lender, lease) =
         borrow_lease(tasks, caller, lender, args.lease_number, args.offset)?;
 
     // Does the lease grant us the ability to read from the memory?
@@ -467,7 +680,7 @@ fn borrow_write(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease =
+    let (lender, lease) =
         borrow_lease(tasks, caller, lender, args.lease_number, args.offset)?;
 
     // Does the lease grant us the ability to write to the memory?
@@ -509,7 +722,7 @@ fn borrow_info(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease = borrow_lease(tasks, caller, lender, args.lease_number, 0)?;
+    let (_, lease) = borrow_lease(tasks, caller, lender, args.lease_number, 0)?;
 
     tasks[caller]
         .save_mut()
@@ -517,18 +730,109 @@ fn borrow_info(
     Ok(NextTask::Same)
 }
 
+/// Finds lease `lease_number` that `lender` has lent to `caller`, offset by
+/// `offset` bytes, following it back to the task that owns the memory if it's
+/// a forwarded lease. Returns the owner's index along with the lease.
 fn borrow_lease(
     tasks: &mut [Task],
     caller: usize,
     lender: usize,
     lease_number: usize,
     offset: usize,
-) -> Result<ULease, UserError> {
-    let caller_id = current_id(tasks, caller);
+) -> Result<(usize, ULease), UserError> {
+    let mut borrower = caller;
+    let mut lender = lender;
+    let mut lease_number = lease_number;
+    // Each hop along a chain of forwarded leases can only narrow what the
+    // borrower gets.
+    let mut attributes = LeaseAttributes::READ | LeaseAttributes::WRITE;
+    let mut length = u32::MAX;
+
+    let mut lease = loop {
+        let lease = match read_lease(tasks, borrower, lender, lease_number)? {
+            Some(lease) => lease,
+            None if borrower == caller => {
+                // Borrower provided an invalid lease number. Borrower was told
+                // the number of leases on successful RECV and should respect
+                // that. (Note: if the lender's lease table changed shape, this
+                // will fault the borrower, which might be bad.)
+                return Err(FaultInfo::SyscallUsage(
+                    UsageError::LeaseOutOfRange,
+                )
+                .into());
+            }
+            None => {
+                // Someone along the chain forwarded a lease that its client
+                // never gave it. That's not the borrower's fault.
+                return Err(UserError::Recoverable(
+                    abi::DEFECT,
+                    NextTask::Same,
+                ));
+            }
+        };
+        if !lease.attributes.contains(LeaseAttributes::FORWARD) {
+            break lease;
+        }
+        attributes &= lease.attributes;
+        length = length.min(lease.length);
+
+        // The lender is passing on a lease from one of its own clients, which
+        // the client must still be waiting on. (The chain can't loop back on
+        // itself, because every task along it is blocked in reply to the
+        // next.)
+        let lender_id = current_id(tasks, lender);
+        let client = TaskId(lease.base_address as u16);
+        let client = match task::check_task_id_against_table(tasks, client) {
+            Ok(i) => i,
+            // Whether the client is dead or never existed, the lender has
+            // offered us something it doesn't have.
+            Err(_) => {
+                return Err(UserError::Recoverable(abi::DEFECT, NextTask::Same))
+            }
+        };
+        if tasks[client].state()
+            != &TaskState::Healthy(SchedState::InReply(lender_id))
+        {
+            return Err(UserError::Recoverable(abi::DEFECT, NextTask::Same));
+        }
+        borrower = lender;
+        lender = client;
+        lease_number = (lease.base_address >> 16) as usize;
+    };
+    lease.attributes &= attributes;
+    lease.length = lease.length.min(length);
+
+    // Attempt to offset the lease. Handle cases where the offset is bogus.
+    // First, we must convert to u32, which _should be_ a no-op but we'll do it
+    // the careful way:
+    let offset = u32::try_from(offset).unwrap_lite();
+    // Now, proceed only if both neither the length nor address computation
+    // wrap.
+    if let (Some(off_len), Some(off_addr)) = (
+        lease.length.checked_sub(offset),
+        lease.base_address.checked_add(offset),
+    ) {
+        lease.base_address = off_addr;
+        lease.length = off_len;
+        Ok((lender, lease))
+    } else {
+        Err(FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange).into())
+    }
+}
+
+/// Reads entry `lease_number` from the lease table of `lender`, which must be
+/// blocked in reply to `borrower`. Returns `None` if there's no such entry.
+fn read_lease(
+    tasks: &mut [Task],
+    borrower: usize,
+    lender: usize,
+    lease_number: usize,
+) -> Result<Option<ULease>, UserError> {
+    let borrower_id = current_id(tasks, borrower);
 
     // Check state of lender and range of lease table.
     if tasks[lender].state()
-        != &TaskState::Healthy(SchedState::InReply(caller_id))
+        != &TaskState::Healthy(SchedState::InReply(borrower_id))
     {
         // The alleged lender isn't lending anything at all.
         // Let's assume this is a defecting lender.
@@ -560,32 +864,7 @@ fn borrow_lease(
     // Try reading the lease. This is unsafe in the general case, but since
     // we've just convinced ourselves that the lease table is in task memory,
     // we can do this safely.
-    let lease = leases.get(lease_number).cloned();
-    // Is the lease number provided by the borrower legitimate?
-    if let Some(mut lease) = lease {
-        // Attempt to offset the lease. Handle cases where the offset is bogus.
-        // First, we must convert to u32, which _should be_ a no-op but we'll do
-        // it the careful way:
-        let offset = u32::try_from(offset).unwrap_lite();
-        // Now, proceed only if both neither the length nor address computation
-        // wrap.
-        if let (Some(off_len), Some(off_addr)) = (
-            lease.length.checked_sub(offset),
-            lease.base_address.checked_add(offset),
-        ) {
-            lease.base_address = off_addr;
-            lease.length = off_len;
-            Ok(lease)
-        } else {
-            Err(FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange).into())
-        }
-    } else {
-        // Borrower provided an invalid lease number. Borrower was told the
-        // number of leases on successful RECV and should respect that. (Note:
-        // if the lender's lease table changed shape, this will fault the
-        // borrower, which might be bad.)
-        Err(FaultInfo::SyscallUsage(UsageError::LeaseOutOfRange).into())
-    }
+    Ok(leases.get(lease_number).cloned())
 }
 
 /// Performs the architecture-specific bookkeeping to activate `task` on next
@@ -686,6 +965,89 @@ fn deliver(
     Ok(())
 }
 
+/// Delivers the asynchronous send queued by `caller`, with arguments `args`,
+/// to `callee`, which must be waiting to receive it. This is the `SEND_ASYNC`
+/// equivalent of `deliver`, and behaves the same way, except that `caller`
+/// goes on running.
+fn deliver_async(
+    tasks: &mut [Task],
+    caller: usize,
+    callee: usize,
+    args: AsyncSendArgs,
+) -> Result<(), InteractFault> {
+    let caller_id = task::current_id(tasks, caller);
+
+    let recv_args = tasks[callee].save().as_recv_args();
+    let dest_slice = recv_args.buffer.map_err(InteractFault::in_dst)?;
+
+    let amount_copied =
+        safe_copy(tasks, caller, args.message.clone(), callee, dest_slice)?;
+    tasks[callee].save_mut().set_recv_result(
+        caller_id,
+        u32::from(args.operation),
+        amount_copied,
+        args.response.len(),
+        0,
+    );
+
+    tasks[caller].set_async_send(AsyncSend::AwaitingReply(args));
+    tasks[callee].set_healthy_state(SchedState::Runnable);
+    Ok(())
+}
+
+/// A message that some task has waiting for another; see `pending_message`.
+enum Pending {
+    /// The sender is blocked sending it.
+    Send,
+    /// The sender has queued it with `SEND_ASYNC`.
+    Async(AsyncSendArgs),
+}
+
+/// Works out whether `sender` has a message for the task `target`, either
+/// because it's blocked sending to it, or because it has an asynchronous send
+/// queued. If it has both, the blocked send goes first, since the sender has
+/// been waiting. (A faulted task's asynchronous send waits until it's
+/// restarted, which cancels it.)
+fn pending_message(sender: &Task, target: TaskId) -> Option<Pending> {
+    // Nothing more goes from `sender` to `target` until `target` has replied
+    // to a message that `sender` gave up on, so that the replies can't be
+    // confused.
+    if sender.is_owed_stale_reply_by(target) {
+        return None;
+    }
+    if sender.state().is_sending_to(target) {
+        return Some(Pending::Send);
+    }
+    match (sender.state(), sender.async_send()) {
+        (TaskState::Healthy(_), AsyncSend::Queued(args))
+            if args.callee == target =>
+        {
+            Some(Pending::Async(args.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Checks whether `sender` has a message for the task `target`, as found by
+/// `pending_message`.
+fn has_message_for(sender: &Task, target: TaskId) -> bool {
+    pending_message(sender, target).is_some()
+}
+
+/// Delivers `message`, as found by `pending_message`, from `caller` to
+/// `callee`.
+fn deliver_from(
+    tasks: &mut [Task],
+    caller: usize,
+    callee: usize,
+    message: Pending,
+) -> Result<(), InteractFault> {
+    match message {
+        Pending::Send => deliver(tasks, caller, callee),
+        Pending::Async(args) => deliver_async(tasks, caller, callee, args),
+    }
+}
+
 fn irq_control(
     tasks: &mut [Task],
     caller: usize,
@@ -714,6 +1076,9 @@ fn irq_control(
         )))?;
     for i in irqs.iter() {
         operation(i.0);
+        if args.control == 1 {
+            crate::irq_stats::record_enable(i.0);
+        }
     }
     Ok(NextTask::Same)
 }
@@ -722,7 +1087,11 @@ fn explicit_panic(
     tasks: &mut [Task],
     caller: usize,
 ) -> Result<NextTask, UserError> {
-    // It's the easiest syscall!
+    // Keep what we can of the message; a bad slice just means there's
+    // nothing to keep. Other than that, it's the easiest syscall!
+    if let Ok(message) = tasks[caller].save().as_panic_args().message {
+        tasks[caller].record_panic_message(message);
+    }
     Ok(task::force_fault(tasks, caller, FaultInfo::Panic))
 }
 
@@ -797,9 +1166,26 @@ fn reply_fault(
         Ok(x) => x,
     };
 
+    if tasks[callee].take_stale_reply_from(caller_id) {
+        // As in `reply`, the callee gave up on this message, so it's in no
+        // position to be faulted over it.
+        return Ok(NextTask::Same);
+    }
+
     if tasks[callee].state()
         != &TaskState::Healthy(SchedState::InReply(caller_id))
     {
+        if tasks[callee].is_awaiting_async_reply_from(caller_id) {
+            // An asynchronous sender isn't ours to fault; it gets told that
+            // we refused the message instead. As in `reply_async`, it may be
+            // more important than us.
+            let prio = tasks[caller].priority();
+            if tasks[callee].complete_async_send(abi::REFUSED, 0)
+                && tasks[callee].priority().is_more_important_than(prio)
+            {
+                return Ok(NextTask::Specific(callee));
+            }
+        }
         // Huh. The target task is off doing something else. This can happen if
         // application-specific supervisory logic unblocks it before we've had a
         // chance to reply (e.g. to implement timeouts).
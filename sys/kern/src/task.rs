@@ -7,17 +7,21 @@
 use core::convert::TryFrom;
 
 use abi::{
-    FaultInfo, FaultSource, Generation, Priority, RegionAttributes, RegionDesc,
-    ReplyFaultReason, SchedState, TaskDesc, TaskFlags, TaskId, TaskState,
-    ULease, UsageError,
+    FaultInfo, FaultSource, Generation, KernelEvent, Priority,
+    RegionAttributes, RegionDesc, ReplyFaultReason, SchedState, StackUsage,
+    TaskDesc, TaskFlags, TaskId, TaskState, TaskStats, ULease, UsageError,
 };
 use zerocopy::FromBytes;
 
 use crate::err::UserError;
-use crate::startup::HUBRIS_FAULT_NOTIFICATION;
+use crate::startup::{HUBRIS_FAULT_NOTIFICATION, TIMERS_PER_TASK};
 use crate::time::Timestamp;
 use crate::umem::USlice;
 
+/// Pattern that `arch::reinitialize` fills a task's stack with, so that we can
+/// later tell how much of it has been used.
+pub const STACK_PAINT: u32 = 0xbaddcafe;
+
 /// Internal representation of a task.
 ///
 /// The fields of this struct are private to this module so that we can maintain
@@ -30,12 +34,29 @@ pub struct Task {
     /// Saved machine state of the user program.
     save: crate::arch::SavedState,
     // NOTE: it is critical that the above field appear first!
-    /// Current priority of the task.
+    /// Current effective priority of the task. This starts out as the priority
+    /// from the descriptor, and may be boosted by `lend_priority` while more
+    /// important tasks are blocked on this one.
     priority: Priority,
     /// State used to make status and scheduling decisions.
     state: TaskState,
-    /// State for tracking the task's timer.
-    timer: TimerState,
+    /// State for tracking the task's timers. The number of timers each task
+    /// gets is set in the kernel config.
+    timers: [TimerState; TIMERS_PER_TASK],
+    /// Deadline after which a `SendTimeout` in progress gives up, if any.
+    send_deadline: Option<Timestamp>,
+    /// Tasks that received a message from this one, which then gave up
+    /// waiting for the reply, if those replies haven't turned up yet, as a
+    /// bitmask of task indices. See `abandon_send`.
+    stale_replies: u64,
+    /// Progress of this task's asynchronous send, if it has made one.
+    async_send: AsyncSend,
+    /// Accounting for CPU time and kernel entries. Unlike most state here,
+    /// this is not reset when the task restarts.
+    stats: TaskStats,
+    /// Start of the message passed to the `PANIC` syscall, if the task has
+    /// panicked since it was last started.
+    panic_message: PanicMessage,
     /// Restart count for this task. We increment this whenever we reinitialize
     /// the task. The low bits of this become the task's generation number.
     generation: u32,
@@ -72,7 +93,12 @@ impl Task {
             generation: 0,
             notifications: 0,
             save: crate::arch::SavedState::default(),
-            timer: crate::task::TimerState::default(),
+            timers: [TimerState::default(); TIMERS_PER_TASK],
+            send_deadline: None,
+            stale_replies: 0,
+            async_send: AsyncSend::Idle,
+            stats: TaskStats::default(),
+            panic_message: PanicMessage::EMPTY,
         }
     }
 
@@ -214,6 +240,12 @@ impl Task {
         false
     }
 
+    /// Checks whether any of the notification bits in `n` have been posted to
+    /// this task and not yet taken.
+    pub fn has_pending_notifications(&self, n: NotificationSet) -> bool {
+        self.notifications & n.0 != 0
+    }
+
     /// Assuming that this task is in or entering a RECV, inspects the RECV
     /// notification mask argument and compares it to the notification bits. If
     /// if any bits are set in both words, clears those bits in the notification
@@ -245,7 +277,9 @@ impl Task {
         self.state == TaskState::Healthy(SchedState::Runnable)
     }
 
-    /// Configures this task's timer.
+    /// Configures one of this task's timers.
+    ///
+    /// `index` selects the timer, and must be less than `TIMERS_PER_TASK`.
     ///
     /// `deadline` specifies the moment when the timer should fire, in kernel
     /// time. If `None`, the timer will never fire.
@@ -254,17 +288,123 @@ impl Task {
     /// fires.
     pub fn set_timer(
         &mut self,
+        index: usize,
         deadline: Option<Timestamp>,
         notifications: NotificationSet,
     ) {
-        self.timer.deadline = deadline;
-        self.timer.to_post = notifications;
+        let timer = &mut self.timers[index];
+        timer.deadline = deadline;
+        timer.to_post = notifications;
     }
 
-    /// Reads out the state of this task's timer, as previously set by
+    /// Reads out the state of one of this task's timers, as previously set by
     /// `set_timer`.
-    pub fn timer(&self) -> (Option<Timestamp>, NotificationSet) {
-        (self.timer.deadline, self.timer.to_post)
+    pub fn timer(&self, index: usize) -> (Option<Timestamp>, NotificationSet) {
+        let timer = &self.timers[index];
+        (timer.deadline, timer.to_post)
+    }
+
+    /// Sets the deadline after which this task's current send should be
+    /// abandoned, or `None` to let it wait forever.
+    pub fn set_send_deadline(&mut self, deadline: Option<Timestamp>) {
+        self.send_deadline = deadline;
+    }
+
+    /// If this task is blocked sending, or waiting for a reply, gives up and
+    /// makes it runnable with a `TIMED_OUT` response. Returns `true` if it was
+    /// blocked.
+    ///
+    /// If the peer has already received the message, it doesn't know that
+    /// we've given up, and will reply eventually. We remember that, so that
+    /// the reply can be discarded rather than being taken for the reply to
+    /// some later message; until it turns up, this task's messages to the same
+    /// peer are held back (see `is_owed_stale_reply_by`). We remember this for
+    /// each peer separately, but only for the first 64 task indices, as with
+    /// caller allow-lists; a send received by a task beyond those waits for
+    /// its reply regardless of the deadline, and this returns `false`.
+    fn abandon_send(&mut self) -> bool {
+        match self.state {
+            TaskState::Healthy(SchedState::InSend(_)) => (),
+            TaskState::Healthy(SchedState::InReply(peer)) => {
+                match stale_reply_bit(peer) {
+                    Some(bit) => self.stale_replies |= bit,
+                    None => return false,
+                }
+            }
+            _ => return false,
+        }
+        self.save.set_error_response(abi::TIMED_OUT);
+        self.state = TaskState::Healthy(SchedState::Runnable);
+        true
+    }
+
+    /// Checks whether `peer` has yet to reply to a message from this task that
+    /// this task gave up waiting for. If so, nothing more should be delivered
+    /// from this task to `peer` until it does, or we couldn't tell the replies
+    /// apart.
+    pub fn is_owed_stale_reply_by(&self, peer: TaskId) -> bool {
+        stale_reply_bit(peer).map_or(false, |bit| self.stale_replies & bit != 0)
+    }
+
+    /// Forgets that `peer` owes this task a stale reply (because it has just
+    /// made it, or because it has been restarted and never will). Returns
+    /// `true` if it did.
+    pub fn take_stale_reply_from(&mut self, peer: TaskId) -> bool {
+        let owed = self.is_owed_stale_reply_by(peer);
+        if let Some(bit) = stale_reply_bit(peer) {
+            self.stale_replies &= !bit;
+        }
+        owed
+    }
+
+    /// Returns the state of this task's asynchronous send.
+    pub fn async_send(&self) -> &AsyncSend {
+        &self.async_send
+    }
+
+    /// Updates the state of this task's asynchronous send.
+    pub fn set_async_send(&mut self, state: AsyncSend) {
+        self.async_send = state;
+    }
+
+    /// Checks whether this task has an asynchronous send waiting to be
+    /// received by `target`.
+    pub fn is_async_sending_to(&self, target: TaskId) -> bool {
+        matches!(&self.async_send, AsyncSend::Queued(s) if s.callee == target)
+    }
+
+    /// Checks whether this task has an asynchronous send that's been received
+    /// by `target`, and is waiting for its reply.
+    pub fn is_awaiting_async_reply_from(&self, target: TaskId) -> bool {
+        matches!(
+            &self.async_send,
+            AsyncSend::AwaitingReply(s) if s.callee == target
+        )
+    }
+
+    /// Finishes this task's asynchronous send, with response code `code` and
+    /// `len` bytes of reply, and posts the notification it asked for. Returns
+    /// `true` if that woke the task, as for `post`.
+    pub fn complete_async_send(&mut self, code: u32, len: usize) -> bool {
+        let notification = match &self.async_send {
+            AsyncSend::Queued(s) | AsyncSend::AwaitingReply(s) => {
+                s.notification
+            }
+            _ => return false,
+        };
+        self.async_send = AsyncSend::Done { code, len };
+        self.post(notification)
+    }
+
+    /// Returns the deadline this task should be scheduled by, if it is in the
+    /// deadline scheduling class and has a timer armed. If it has more than
+    /// one, the earliest counts.
+    pub fn sched_deadline(&self) -> Option<Timestamp> {
+        if self.descriptor.flags.contains(TaskFlags::DEADLINE) {
+            self.timers.iter().filter_map(|t| t.deadline).min()
+        } else {
+            None
+        }
     }
 
     /// Rewrites this task's state back to its initial form, to effect a task
@@ -279,13 +419,88 @@ impl Task {
     /// like to run the task after reinitializing it, you must do so explicitly.
     pub fn reinitialize(&mut self) {
         self.generation = self.generation.wrapping_add(1);
-        self.timer = TimerState::default();
+        self.priority = self.base_priority();
+        self.timers = [TimerState::default(); TIMERS_PER_TASK];
+        self.send_deadline = None;
+        self.stale_replies = 0;
+        self.async_send = AsyncSend::Idle;
         self.notifications = 0;
         self.state = TaskState::default();
+        self.panic_message = PanicMessage::EMPTY;
 
         crate::arch::reinitialize(self);
     }
 
+    /// Keeps a copy of the start of the message passed to the `PANIC`
+    /// syscall, truncated to `abi::PANIC_MESSAGE_MAX` bytes. If the task hands
+    /// us memory it can't read, we keep nothing: it's panicking anyway, so
+    /// there's no point in faulting it for that.
+    pub fn record_panic_message(&mut self, message: USlice<u8>) {
+        let mut recorded = PanicMessage::EMPTY;
+        if let Ok(bytes) = self.try_read(&message) {
+            let n = bytes.len().min(abi::PANIC_MESSAGE_MAX);
+            recorded.bytes[..n].copy_from_slice(&bytes[..n]);
+            recorded.len = n as u8;
+        }
+        self.panic_message = recorded;
+    }
+
+    /// Returns the recorded panic message, which is empty unless the task has
+    /// panicked since it was last started.
+    pub fn panic_message(&self) -> &[u8] {
+        &self.panic_message.bytes[..usize::from(self.panic_message.len)]
+    }
+
+    /// Returns this task's accounting statistics.
+    pub fn stats(&self) -> &TaskStats {
+        &self.stats
+    }
+
+    /// Charges `ticks` ticks to this task, which should be the one that was
+    /// running when the timer interrupt that ended them happened.
+    pub fn account_ticks(&mut self, ticks: u64) {
+        self.stats.run_ticks = self.stats.run_ticks.wrapping_add(ticks);
+    }
+
+    /// Records that this task has been switched to.
+    pub fn account_scheduled(&mut self) {
+        self.stats.times_scheduled = self.stats.times_scheduled.wrapping_add(1);
+    }
+
+    /// Records that this task has made a syscall.
+    pub fn account_syscall(&mut self) {
+        self.stats.syscalls = self.stats.syscalls.wrapping_add(1);
+    }
+
+    /// Works out how deep this task's stack has gone since it was started, by
+    /// looking for the deepest word that no longer holds `STACK_PAINT`.
+    ///
+    /// The stack is taken to run from the base of the region containing the
+    /// initial stack pointer up to the initial stack pointer.
+    pub fn stack_usage(&self) -> StackUsage {
+        let top = self.descriptor.initial_stack;
+        let base = self
+            .region_table
+            .iter()
+            .find(|r| r.base < top && top <= r.base.wrapping_add(r.size))
+            .map_or(top, |r| r.base);
+        let size = top - base;
+
+        let stack = USlice::<u32>::from_raw(base as usize, size as usize / 4)
+            .unwrap_or_else(|_| USlice::empty());
+        let unused = match self.try_read(&stack) {
+            Ok(words) => {
+                words.iter().take_while(|&&w| w == STACK_PAINT).count() * 4
+            }
+            // Not normal memory? Then we can't say anything about it.
+            Err(_) => 0,
+        };
+        StackUsage {
+            size,
+            max_used: size - unused as u32,
+        }
+    }
+
     /// Returns a reference to the `TaskDesc` that was used to initially create
     /// this task.
     pub fn descriptor(&self) -> &'static TaskDesc {
@@ -303,11 +518,18 @@ impl Task {
         Generation::from(self.generation as u8 & MASK)
     }
 
-    /// Returns this task's priority.
+    /// Returns this task's effective priority, including any boost inherited
+    /// from tasks blocked on it.
     pub fn priority(&self) -> Priority {
         self.priority
     }
 
+    /// Returns the priority this task was assigned at build time, ignoring any
+    /// inherited boost.
+    pub fn base_priority(&self) -> Priority {
+        Priority(self.descriptor.priority)
+    }
+
     /// Returns a reference to this task's current state, for inspection.
     pub fn state(&self) -> &TaskState {
         &self.state
@@ -326,6 +548,10 @@ impl Task {
     ///
     /// If you attempt to use this to bring a task out of fault state.
     pub fn set_healthy_state(&mut self, s: SchedState) {
+        // A send deadline only means anything while the send is in progress.
+        if !matches!(s, SchedState::InSend(_) | SchedState::InReply(_)) {
+            self.send_deadline = None;
+        }
         let last = core::mem::replace(&mut self.state, s.into());
         if let TaskState::Faulted { .. } = last {
             panic!();
@@ -343,17 +569,35 @@ impl Task {
     }
 }
 
+/// Fixed-size copy of a task's panic message.
+#[derive(Copy, Clone, Debug)]
+struct PanicMessage {
+    len: u8,
+    bytes: [u8; abi::PANIC_MESSAGE_MAX],
+}
+
+impl PanicMessage {
+    const EMPTY: Self = Self {
+        len: 0,
+        bytes: [0; abi::PANIC_MESSAGE_MAX],
+    };
+}
+
 /// Interface that must be implemented by the `arch::SavedState` type. This
 /// gives architecture-independent access to task state for the rest of the
 /// kernel.
 ///
 /// Architectures need to implement the `argX` and `retX` functions plus
-/// `syscall_descriptor`, and the rest of the trait (such as the argument proxy
-/// types) will just work.
+/// `syscall_descriptor` and `saved_registers`, and the rest of the trait (such
+/// as the argument proxy types) will just work.
 pub trait ArchState: Default {
     /// TODO: this is probably not needed here.
     fn stack_pointer(&self) -> u32;
 
+    /// Returns the saved registers in architecture-independent form, for
+    /// debugging.
+    fn saved_registers(&self) -> abi::SavedRegisters;
+
     /// Reads syscall argument register 0.
     fn arg0(&self) -> u32;
     /// Reads syscall argument register 1.
@@ -460,7 +704,8 @@ pub trait ArchState: Default {
     /// results.
     fn as_set_timer_args(&self) -> SetTimerArgs {
         SetTimerArgs {
-            deadline: if self.arg0() != 0 {
+            timer: (self.arg0() >> 16) as usize,
+            deadline: if self.arg0() & 1 != 0 {
                 Some(Timestamp::from(
                     u64::from(self.arg2()) << 32 | u64::from(self.arg1()),
                 ))
@@ -471,6 +716,14 @@ pub trait ArchState: Default {
         }
     }
 
+    /// Interprets arguments as for the `GET_TIMER` syscall and returns the
+    /// results.
+    fn as_get_timer_args(&self) -> GetTimerArgs {
+        GetTimerArgs {
+            timer: self.arg0() as usize,
+        }
+    }
+
     /// Interprets arguments as for the `BORROW_*` family of syscalls and
     /// returns the result.
     fn as_borrow_args(&self) -> BorrowArgs {
@@ -622,10 +875,17 @@ pub struct ReplyFaultArgs {
 /// Decoded arguments for the `SET_TIMER` syscall.
 #[derive(Clone, Debug)]
 pub struct SetTimerArgs {
+    pub timer: usize,
     pub deadline: Option<Timestamp>,
     pub notification: NotificationSet,
 }
 
+/// Decoded arguments for the `GET_TIMER` syscall.
+#[derive(Clone, Debug)]
+pub struct GetTimerArgs {
+    pub timer: usize,
+}
+
 /// Decoded arguments for the `BORROW_*` syscalls.
 #[derive(Clone, Debug)]
 pub struct BorrowArgs {
@@ -661,10 +921,39 @@ pub struct PostArgs {
     pub notification_bits: NotificationSet,
 }
 
+/// Progress of a task's asynchronous send (see the `SEND_ASYNC` syscall).
+///
+/// The sender keeps running throughout, so unlike a normal send, this isn't
+/// part of its `SchedState`, and doesn't lend the recipient its priority.
+#[derive(Clone, Debug)]
+pub enum AsyncSend {
+    /// No asynchronous send is outstanding or waiting to be collected.
+    Idle,
+    /// Waiting for the recipient to receive the message.
+    Queued(AsyncSendArgs),
+    /// The recipient has received the message, and we're waiting for its
+    /// reply.
+    AwaitingReply(AsyncSendArgs),
+    /// The exchange is over, and the sender hasn't collected the result yet.
+    Done { code: u32, len: usize },
+}
+
+/// Arguments of an asynchronous send, which we have to keep in the task
+/// because the sender's registers move on without it.
+#[derive(Clone, Debug)]
+pub struct AsyncSendArgs {
+    pub callee: TaskId,
+    pub operation: u16,
+    pub message: USlice<u8>,
+    pub response: USlice<u8>,
+    /// Notification to post to the sender when the exchange is over.
+    pub notification: NotificationSet,
+}
+
 /// State for a task timer.
 ///
 /// Task timers are used to multiplex the hardware timer.
-#[derive(Debug, Default)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct TimerState {
     /// Deadline, in kernel time, at which this timer should fire. If `None`,
     /// the timer is disabled.
@@ -717,24 +1006,68 @@ impl NextTask {
 
 /// Processes all enabled timers in the task table, posting notifications for
 /// any that have expired by `current_time` (and disabling them atomically).
+///
+/// This also handles send deadlines: any task still blocked in a `SendTimeout`
+/// whose deadline has passed is resumed with `TIMED_OUT`.
 pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
     let mut sched_hint = NextTask::Same;
-    for (index, task) in tasks.iter_mut().enumerate() {
-        if let Some(deadline) = task.timer.deadline {
+    for index in 0..tasks.len() {
+        let task = &mut tasks[index];
+        for t in 0..TIMERS_PER_TASK {
+            let timer = &mut task.timers[t];
+            if let Some(deadline) = timer.deadline {
+                if deadline <= current_time {
+                    timer.deadline = None;
+                    let to_post = timer.to_post;
+                    if task.notifications & to_post.0 != 0 {
+                        crate::events::record(KernelEvent::TimerOverrun {
+                            task: index as u16,
+                        });
+                    }
+                    let task_hint = if task.post(to_post) {
+                        NextTask::Specific(index)
+                    } else {
+                        NextTask::Same
+                    };
+                    sched_hint = sched_hint.combine(task_hint)
+                }
+            }
+        }
+
+        if let Some(deadline) = task.send_deadline {
             if deadline <= current_time {
-                task.timer.deadline = None;
-                let task_hint = if task.post(task.timer.to_post) {
-                    NextTask::Specific(index)
-                } else {
-                    NextTask::Same
-                };
-                sched_hint = sched_hint.combine(task_hint)
+                task.send_deadline = None;
+                let peer = ipc_peer(tasks, index);
+                if tasks[index].abandon_send() {
+                    // The abandoned send no longer lends its priority to
+                    // anyone.
+                    if let Some(peer) = peer {
+                        recompute_priority(tasks, peer);
+                    }
+                    sched_hint = sched_hint.combine(NextTask::Specific(index));
+                }
             }
         }
     }
     sched_hint
 }
 
+/// Returns the earliest time at which `process_timers` has work to do -- that
+/// is, the earliest armed timer or send deadline -- or `None` if there isn't
+/// any.
+pub fn next_deadline(tasks: &[Task]) -> Option<Timestamp> {
+    tasks
+        .iter()
+        .flat_map(|task| {
+            task.timers
+                .iter()
+                .map(|t| t.deadline)
+                .chain(core::iter::once(task.send_deadline))
+        })
+        .flatten()
+        .min()
+}
+
 /// Checks a user-provided `TaskId` for validity against `table`.
 ///
 /// On success, returns an index that can be used to dereference `table` without
@@ -761,12 +1094,29 @@ pub fn check_task_id_against_table(
     Ok(id.index())
 }
 
+/// Returns the bit for `peer` in `Task::stale_replies`, if it has one.
+fn stale_reply_bit(peer: TaskId) -> Option<u64> {
+    let index = peer.index();
+    if index < u64::BITS as usize {
+        Some(1 << index)
+    } else {
+        None
+    }
+}
+
 /// Selects a new task to run after `previous`. Tries to be fair, kind of.
 ///
+/// The most important runnable priority always wins. Within that priority,
+/// tasks in the deadline scheduling class with an armed timer go first,
+/// earliest deadline first; otherwise we round-robin as `priority_scan` does.
+///
 /// If no tasks are runnable, the kernel panics.
 pub fn select(previous: usize, tasks: &[Task]) -> usize {
-    priority_scan(previous, tasks, |t| t.is_runnable())
-        .expect("no tasks runnable")
+    let choice = priority_scan(previous, tasks, |t| t.is_runnable())
+        .expect("no tasks runnable");
+    let prio = tasks[choice].priority;
+    deadline_scan(previous, tasks, |t| t.is_runnable() && t.priority == prio)
+        .unwrap_or(choice)
 }
 
 /// Scans `tasks` for the next task, after `previous`, that satisfies `pred`. If
@@ -807,6 +1157,115 @@ pub fn priority_scan(
     choice.map(|(idx, _)| idx)
 }
 
+/// Scans `tasks` for the task, after `previous`, that satisfies `pred` and has
+/// the earliest scheduling deadline (see `Task::sched_deadline`). Tasks without
+/// one are ignored. Ties go to the first task in order after `previous`, mod
+/// `tasks.len()`, as in `priority_scan`.
+///
+/// Note that this does not consider priority; callers that care should
+/// restrict `pred` to a single priority.
+///
+/// # Panics
+///
+/// If `previous` is not a valid index in `tasks`.
+pub fn deadline_scan(
+    previous: usize,
+    tasks: &[Task],
+    pred: impl Fn(&Task) -> bool,
+) -> Option<usize> {
+    uassert!(previous < tasks.len());
+    let search_order = (previous + 1..tasks.len()).chain(0..previous + 1);
+    let mut choice = None;
+    for i in search_order {
+        if !pred(&tasks[i]) {
+            continue;
+        }
+        let deadline = match tasks[i].sched_deadline() {
+            Some(d) => d,
+            None => continue,
+        };
+
+        if let Some((_, earliest)) = choice {
+            if deadline >= earliest {
+                continue;
+            }
+        }
+
+        choice = Some((i, deadline));
+    }
+
+    choice.map(|(idx, _)| idx)
+}
+
+/// Passes the effective priority of `tasks[client]`, which has just become
+/// blocked in IPC, to the task it's blocked on. This is half of priority
+/// inheritance for IPC; the other half is `recompute_priority`.
+///
+/// A task that is blocked sending to another task, or waiting for its reply,
+/// lends that task its effective priority if it's more important. Lending is
+/// transitive: a server that is itself blocked on another server passes its
+/// clients' priority along, so this walks the chain of blocked tasks for as
+/// long as the boost makes a difference.
+///
+/// This needs to be called whenever a task enters the `InSend` or `InReply`
+/// states.
+pub fn lend_priority(tasks: &mut [Task], client: usize) {
+    let mut client = client;
+    // Each step makes a task strictly more important, so this terminates even
+    // if the chain loops back on itself.
+    while let Some(server) = ipc_peer(tasks, client) {
+        let prio = tasks[client].priority;
+        if !prio.is_more_important_than(tasks[server].priority) {
+            break;
+        }
+        tasks[server].priority = prio;
+        client = server;
+    }
+}
+
+/// Works out the effective priority of `tasks[server]` afresh, after a task
+/// that was blocked on it in IPC has stopped being so (because it got its
+/// reply, timed out, faulted, or whatever), and passes any change along the
+/// chain of tasks it is itself blocked on.
+///
+/// This needs to be called whenever a task leaves the `InSend` or `InReply`
+/// states, with the task it was blocked on.
+pub fn recompute_priority(tasks: &mut [Task], server: usize) {
+    let mut server = server;
+    // Each step makes a task strictly less important, so this terminates even
+    // if the chain loops back on itself.
+    loop {
+        let mut prio = tasks[server].base_priority();
+        for client in 0..tasks.len() {
+            if ipc_peer(tasks, client) == Some(server)
+                && tasks[client].priority.is_more_important_than(prio)
+            {
+                prio = tasks[client].priority;
+            }
+        }
+        if prio == tasks[server].priority {
+            break;
+        }
+        tasks[server].priority = prio;
+        match ipc_peer(tasks, server) {
+            Some(next) => server = next,
+            None => break,
+        }
+    }
+}
+
+/// Returns the index of the task that `tasks[index]` is blocked on in IPC, if
+/// any, and if that task hasn't restarted since.
+pub fn ipc_peer(tasks: &[Task], index: usize) -> Option<usize> {
+    match tasks[index].state {
+        TaskState::Healthy(SchedState::InSend(peer))
+        | TaskState::Healthy(SchedState::InReply(peer)) => {
+            check_task_id_against_table(tasks, peer).ok()
+        }
+        _ => None,
+    }
+}
+
 /// Puts a task into a forced fault condition.
 ///
 /// The task is designated by the `index` parameter. We need access to the
@@ -829,7 +1288,9 @@ pub fn force_fault(
     index: usize,
     fault: FaultInfo,
 ) -> NextTask {
+    let peer = ipc_peer(tasks, index);
     let task = &mut tasks[index];
+    task.send_deadline = None;
     task.state = match task.state {
         TaskState::Healthy(sched) => TaskState::Faulted {
             original_state: sched,
@@ -844,6 +1305,15 @@ pub fn force_fault(
             }
         }
     };
+    crate::events::record(KernelEvent::TaskFaulted {
+        task: index as u16,
+        fault,
+    });
+    // If the task was blocked in IPC, whoever it was blocked on no longer
+    // inherits its priority.
+    if let Some(peer) = peer {
+        recompute_priority(tasks, peer);
+    }
     let supervisor_awoken =
         tasks[0].post(NotificationSet(HUBRIS_FAULT_NOTIFICATION));
     if supervisor_awoken {
@@ -868,3 +1338,557 @@ fn region_covers<T>(region: &abi::RegionDesc, slice: &USlice<T>) -> bool {
     (region.base as usize) <= slice.base_addr()
         && slice.end_addr() <= region_end
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TaskTableBuilder;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// Makes a runnable task at `priority`, in the deadline scheduling class
+    /// if `deadline` is set.
+    fn task(priority: u8, deadline: bool) -> Task {
+        let mut flags = TaskFlags::START_AT_BOOT;
+        if deadline {
+            flags |= TaskFlags::DEADLINE;
+        }
+        let descriptor = Box::leak(Box::new(TaskDesc {
+            regions: [0; abi::REGIONS_PER_TASK],
+            entry_point: 0,
+            initial_stack: 0,
+            priority,
+            flags,
+            index: 0,
+            allowed_callers: 0,
+        }));
+        Task::from_descriptor(descriptor, &[])
+    }
+
+    fn arm(task: &mut Task, deadline: u64) {
+        task.set_timer(0, Some(deadline.into()), NotificationSet(1));
+    }
+
+    #[test]
+    fn select_round_robins_without_deadlines() {
+        let tasks = [task(1, false), task(1, false), task(1, true)];
+        assert_eq!(select(0, &tasks), 1);
+        assert_eq!(select(1, &tasks), 2);
+        assert_eq!(select(2, &tasks), 0);
+    }
+
+    #[test]
+    fn select_prefers_armed_deadline_task_at_same_priority() {
+        let mut tasks = [task(1, false), task(1, false), task(1, true)];
+        arm(&mut tasks[2], 100);
+        assert_eq!(select(0, &tasks), 2);
+        assert_eq!(select(2, &tasks), 2);
+    }
+
+    #[test]
+    fn select_picks_earliest_deadline() {
+        let mut tasks = [task(1, true), task(1, true), task(1, true)];
+        arm(&mut tasks[0], 300);
+        arm(&mut tasks[1], 200);
+        arm(&mut tasks[2], 100);
+        assert_eq!(select(0, &tasks), 2);
+
+        // Ties go round-robin.
+        arm(&mut tasks[1], 100);
+        assert_eq!(select(0, &tasks), 1);
+        assert_eq!(select(1, &tasks), 2);
+    }
+
+    #[test]
+    fn select_ignores_timer_of_priority_class_task() {
+        let mut tasks = [task(1, true), task(1, false), task(1, true)];
+        arm(&mut tasks[1], 100);
+        arm(&mut tasks[2], 200);
+        assert_eq!(select(0, &tasks), 2);
+    }
+
+    #[test]
+    fn select_respects_priority_over_deadline() {
+        let mut tasks = [task(2, true), task(1, false), task(2, true)];
+        arm(&mut tasks[0], 100);
+        arm(&mut tasks[2], 200);
+        assert_eq!(select(0, &tasks), 1);
+
+        tasks[1].set_healthy_state(SchedState::InRecv(None));
+        assert_eq!(select(1, &tasks), 0);
+    }
+
+    #[test]
+    fn select_skips_blocked_deadline_task() {
+        let mut tasks = [task(1, false), task(1, true), task(1, false)];
+        arm(&mut tasks[1], 100);
+        tasks[1].set_healthy_state(SchedState::InRecv(None));
+        assert_eq!(select(0, &tasks), 2);
+    }
+
+    #[test]
+    fn select_round_robins_among_unarmed_deadline_tasks() {
+        let tasks = [task(1, true), task(1, true), task(1, true)];
+        assert_eq!(deadline_scan(0, &tasks, |t| t.is_runnable()), None);
+        assert_eq!(select(0, &tasks), 1);
+        assert_eq!(select(1, &tasks), 2);
+        assert_eq!(select(2, &tasks), 0);
+    }
+
+    #[test]
+    fn select_forgets_cancelled_deadline() {
+        let mut tasks = [task(1, false), task(1, true), task(1, false)];
+        arm(&mut tasks[1], 100);
+        assert_eq!(select(2, &tasks), 1);
+
+        tasks[1].set_timer(0, None, NotificationSet(1));
+        assert_eq!(select(2, &tasks), 0);
+    }
+
+    #[test]
+    fn sched_deadline_is_earliest_timer() {
+        let mut t = task(1, true);
+        t.set_timer(0, Some(300.into()), NotificationSet(1));
+        t.set_timer(1, Some(200.into()), NotificationSet(2));
+        assert_eq!(t.sched_deadline(), Some(200.into()));
+
+        t.set_timer(1, None, NotificationSet(2));
+        assert_eq!(t.sched_deadline(), Some(300.into()));
+    }
+
+    #[test]
+    fn blocked_client_boosts_server() {
+        let mut tasks = [task(0, false), task(3, false), task(1, false)];
+        let server = current_id(&tasks, 1);
+        tasks[2].set_healthy_state(SchedState::InSend(server));
+        lend_priority(&mut tasks, 2);
+        assert_eq!(tasks[1].priority(), Priority(1));
+        assert_eq!(tasks[1].base_priority(), Priority(3));
+
+        tasks[2].set_healthy_state(SchedState::InReply(server));
+        assert_eq!(tasks[1].priority(), Priority(1));
+
+        tasks[2].set_healthy_state(SchedState::Runnable);
+        recompute_priority(&mut tasks, 1);
+        assert_eq!(tasks[1].priority(), Priority(3));
+    }
+
+    #[test]
+    fn less_important_client_does_not_lower_server() {
+        let mut tasks = [task(0, false), task(1, false), task(3, false)];
+        let server = current_id(&tasks, 1);
+        tasks[2].set_healthy_state(SchedState::InSend(server));
+        lend_priority(&mut tasks, 2);
+        assert_eq!(tasks[1].priority(), Priority(1));
+    }
+
+    #[test]
+    fn boost_is_transitive() {
+        let mut tasks = [
+            task(0, false),
+            task(1, false),
+            task(4, false),
+            task(3, false),
+        ];
+        let outer = current_id(&tasks, 3);
+        let inner = current_id(&tasks, 2);
+        tasks[3].set_healthy_state(SchedState::InSend(inner));
+        lend_priority(&mut tasks, 3);
+        tasks[1].set_healthy_state(SchedState::InReply(outer));
+        lend_priority(&mut tasks, 1);
+        assert_eq!(tasks[3].priority(), Priority(1));
+        assert_eq!(tasks[2].priority(), Priority(1));
+
+        // When the client goes, the boost unwinds all the way down.
+        tasks[1].set_healthy_state(SchedState::Runnable);
+        recompute_priority(&mut tasks, 3);
+        assert_eq!(tasks[3].priority(), Priority(3));
+        assert_eq!(tasks[2].priority(), Priority(3));
+    }
+
+    #[test]
+    fn other_clients_keep_server_boosted() {
+        let mut tasks = [
+            task(0, false),
+            task(4, false),
+            task(1, false),
+            task(2, false),
+        ];
+        let server = current_id(&tasks, 1);
+        for client in [2, 3] {
+            tasks[client].set_healthy_state(SchedState::InSend(server));
+            lend_priority(&mut tasks, client);
+        }
+        assert_eq!(tasks[1].priority(), Priority(1));
+
+        tasks[2].set_healthy_state(SchedState::Runnable);
+        recompute_priority(&mut tasks, 1);
+        assert_eq!(tasks[1].priority(), Priority(2));
+    }
+
+    #[test]
+    fn boost_around_a_loop_terminates() {
+        let mut tasks = [
+            task(0, false),
+            task(3, false),
+            task(4, false),
+            task(1, false),
+        ];
+        // Tasks 1 and 2 have managed to deadlock each other.
+        let a = current_id(&tasks, 1);
+        let b = current_id(&tasks, 2);
+        tasks[1].set_healthy_state(SchedState::InSend(b));
+        lend_priority(&mut tasks, 1);
+        tasks[2].set_healthy_state(SchedState::InSend(a));
+        lend_priority(&mut tasks, 2);
+        assert_eq!(tasks[1].priority(), Priority(3));
+        assert_eq!(tasks[2].priority(), Priority(3));
+
+        tasks[3].set_healthy_state(SchedState::InSend(a));
+        lend_priority(&mut tasks, 3);
+        assert_eq!(tasks[1].priority(), Priority(1));
+        assert_eq!(tasks[2].priority(), Priority(1));
+
+        // Breaking the loop clears the boost it was holding up.
+        let _ = force_fault(&mut tasks, 2, FaultInfo::Panic);
+        tasks[3].set_healthy_state(SchedState::Runnable);
+        recompute_priority(&mut tasks, 1);
+        assert_eq!(tasks[1].priority(), Priority(3));
+    }
+
+    #[test]
+    fn stale_peer_is_not_boosted() {
+        let mut tasks = [task(0, false), task(3, false), task(1, false)];
+        let server = current_id(&tasks, 1);
+        tasks[2].set_healthy_state(SchedState::InSend(server));
+        tasks[1].reinitialize();
+        lend_priority(&mut tasks, 2);
+        assert_eq!(tasks[1].priority(), Priority(3));
+    }
+
+    #[test]
+    fn fault_drops_boost() {
+        let mut tasks = [task(0, false), task(3, false), task(1, false)];
+        let server = current_id(&tasks, 1);
+        tasks[2].set_healthy_state(SchedState::InReply(server));
+        lend_priority(&mut tasks, 2);
+        assert_eq!(tasks[1].priority(), Priority(1));
+
+        let _ = force_fault(&mut tasks, 2, FaultInfo::Panic);
+        assert_eq!(tasks[1].priority(), Priority(3));
+    }
+
+    #[test]
+    fn timeout_drops_boost() {
+        let mut tasks = [task(0, false), task(3, false), task(1, false)];
+        let server = current_id(&tasks, 1);
+        tasks[2].set_send_deadline(Some(Timestamp::from(10)));
+        tasks[2].set_healthy_state(SchedState::InSend(server));
+        lend_priority(&mut tasks, 2);
+        assert_eq!(tasks[1].priority(), Priority(1));
+
+        let _ = process_timers(&mut tasks, Timestamp::from(10));
+        assert_eq!(tasks[1].priority(), Priority(3));
+    }
+
+    #[test]
+    fn timeout_after_receipt_leaves_reply_owed() {
+        let mut tasks = [task(0, false), task(1, false), task(2, false)];
+        let server = current_id(&tasks, 1);
+        tasks[2].set_send_deadline(Some(Timestamp::from(10)));
+        tasks[2].set_healthy_state(SchedState::InReply(server));
+        let _ = process_timers(&mut tasks, Timestamp::from(10));
+        assert!(tasks[2].is_runnable());
+        assert!(tasks[2].is_owed_stale_reply_by(server));
+
+        // While that's outstanding, a send to another task still times out
+        // after that task has received it, and is tracked separately.
+        let other = current_id(&tasks, 0);
+        tasks[2].set_send_deadline(Some(Timestamp::from(20)));
+        tasks[2].set_healthy_state(SchedState::InReply(other));
+        let _ = process_timers(&mut tasks, Timestamp::from(20));
+        assert!(tasks[2].is_runnable());
+        assert!(tasks[2].is_owed_stale_reply_by(other));
+
+        assert!(tasks[2].take_stale_reply_from(server));
+        assert!(!tasks[2].is_owed_stale_reply_by(server));
+        assert!(tasks[2].is_owed_stale_reply_by(other));
+    }
+
+    #[test]
+    fn finished_send_clears_deadline() {
+        let mut tasks = [task(0, false), task(1, false)];
+        let server = current_id(&tasks, 0);
+        tasks[1].set_send_deadline(Some(Timestamp::from(10)));
+        tasks[1].set_healthy_state(SchedState::InSend(server));
+        tasks[1].set_healthy_state(SchedState::InReply(server));
+        assert_eq!(next_deadline(&tasks), Some(Timestamp::from(10)));
+
+        tasks[1].set_healthy_state(SchedState::Runnable);
+        assert_eq!(next_deadline(&tasks), None);
+    }
+
+    /// Puts `task` into an open receive, listening for the notifications in
+    /// `mask`.
+    fn recv(task: &mut Task, mask: u32) {
+        task.save_mut().regs[2] = mask;
+        task.save_mut().regs[3] = 0;
+        task.set_healthy_state(SchedState::InRecv(None));
+    }
+
+    #[test]
+    fn priority_scan_prefers_most_important() {
+        let tasks = TaskTableBuilder::new().task(2).task(0).task(1).build();
+        assert_eq!(priority_scan(0, &tasks, |_| true), Some(1));
+        assert_eq!(
+            priority_scan(0, &tasks, |t| t.priority() != Priority(0)),
+            Some(2)
+        );
+        assert_eq!(priority_scan(0, &tasks, |_| false), None);
+    }
+
+    #[test]
+    fn priority_scan_breaks_ties_after_previous() {
+        let tasks = TaskTableBuilder::new().tasks(4, 1).build();
+        for previous in 0..4 {
+            assert_eq!(
+                priority_scan(previous, &tasks, |_| true),
+                Some((previous + 1) % 4)
+            );
+        }
+        // The previous task is considered last, but it is considered.
+        assert_eq!(
+            priority_scan(1, &tasks, |t| t.descriptor().index == 1),
+            Some(1)
+        );
+    }
+
+    /// Across random task tables, `select` always picks a runnable task at the
+    /// most important runnable priority, and takes turns evenly among all such
+    /// tasks.
+    #[test]
+    fn select_is_fair() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0x5c4ed);
+        for _ in 0..1000 {
+            let n = rng.gen_range(1..=8);
+            let mut builder = TaskTableBuilder::new();
+            for _ in 0..n {
+                builder = builder.task(rng.gen_range(0..4));
+            }
+            let mut tasks = builder.build();
+            for task in tasks.iter_mut() {
+                if rng.gen_bool(0.3) {
+                    task.set_healthy_state(SchedState::InRecv(None));
+                }
+            }
+            // The scheduler insists that something be runnable.
+            let idle = rng.gen_range(0..n);
+            tasks[idle].set_healthy_state(SchedState::Runnable);
+
+            let best = tasks
+                .iter()
+                .filter(|t| t.is_runnable())
+                .map(|t| t.priority().0)
+                .min()
+                .unwrap();
+            let eligible = |i: usize| {
+                tasks[i].is_runnable() && tasks[i].priority() == Priority(best)
+            };
+            let rounds = (0..n).filter(|&i| eligible(i)).count();
+
+            let mut counts = vec![0; n];
+            let mut previous = rng.gen_range(0..n);
+            for _ in 0..3 * rounds {
+                previous = select(previous, &tasks);
+                counts[previous] += 1;
+            }
+            for (i, &count) in counts.iter().enumerate() {
+                let expected = if eligible(i) { 3 } else { 0 };
+                assert_eq!(count, expected, "task {} of {:?}", i, counts);
+            }
+        }
+    }
+
+    #[test]
+    fn select_skips_task_not_started_at_boot() {
+        let tasks = TaskTableBuilder::new()
+            .task(1)
+            .task(0)
+            .flags(TaskFlags::empty())
+            .build();
+        assert_eq!(tasks[1].state(), &TaskState::default());
+        assert_eq!(select(0, &tasks), 0);
+    }
+
+    #[test]
+    fn check_task_id_accepts_current_id() {
+        let tasks = TaskTableBuilder::new().tasks(3, 0).build();
+        let id = current_id(&tasks, 2);
+        assert_eq!(check_task_id_against_table(&tasks, id), Ok(2));
+    }
+
+    #[test]
+    fn check_task_id_faults_out_of_range() {
+        let tasks = TaskTableBuilder::new().tasks(3, 0).build();
+        let id = TaskId::for_index_and_gen(3, Generation::ZERO);
+        assert_eq!(
+            check_task_id_against_table(&tasks, id),
+            Err(UserError::from(FaultInfo::SyscallUsage(
+                UsageError::TaskOutOfRange
+            )))
+        );
+    }
+
+    #[test]
+    fn check_task_id_reports_restart() {
+        let mut tasks = TaskTableBuilder::new().tasks(3, 0).build();
+        let old = current_id(&tasks, 1);
+        tasks[1].reinitialize();
+        let new = current_id(&tasks, 1);
+        assert_ne!(old, new);
+        assert_eq!(
+            check_task_id_against_table(&tasks, old),
+            Err(UserError::Recoverable(
+                abi::dead_response_code(new.generation()),
+                NextTask::Same
+            ))
+        );
+    }
+
+    /// A task's generation wraps around after it's been restarted as many
+    /// times as a `TaskId` can count, at which point IDs from that many
+    /// restarts ago look current again.
+    #[test]
+    fn generation_wraps_around() {
+        let mut tasks = TaskTableBuilder::new().tasks(2, 0).build();
+        let first = current_id(&tasks, 1);
+        let generations = 1 << (16 - TaskId::INDEX_BITS);
+        for i in 1..generations {
+            tasks[1].reinitialize();
+            let id = current_id(&tasks, 1);
+            assert_eq!(id.index(), 1);
+            assert_ne!(id, first, "wrapped early, after {} restarts", i);
+            assert_eq!(id, first_id_plus(first, i));
+        }
+        tasks[1].reinitialize();
+        assert_eq!(current_id(&tasks, 1), first);
+        assert_eq!(check_task_id_against_table(&tasks, first), Ok(1));
+    }
+
+    /// Advances `id` by `n` generations.
+    fn first_id_plus(id: TaskId, n: usize) -> TaskId {
+        (0..n).fold(id, |id, _| id.next_generation())
+    }
+
+    #[test]
+    fn post_accumulates_while_not_receiving() {
+        let mut tasks = TaskTableBuilder::new().task(0).build();
+        assert!(!tasks[0].post(NotificationSet(0b01)));
+        assert!(!tasks[0].post(NotificationSet(0b10)));
+        assert!(tasks[0].is_runnable());
+
+        // Entering a receive then finds the bits waiting.
+        recv(&mut tasks[0], 0b10);
+        assert_eq!(tasks[0].take_notifications(), Some(0b10));
+        assert_eq!(tasks[0].take_notifications(), None);
+    }
+
+    #[test]
+    fn post_wakes_receiver_only_for_masked_bits() {
+        let mut tasks = TaskTableBuilder::new().task(0).build();
+        recv(&mut tasks[0], 0b100);
+
+        assert!(!tasks[0].post(NotificationSet(0b011)));
+        assert!(!tasks[0].is_runnable());
+
+        assert!(tasks[0].post(NotificationSet(0b100)));
+        assert!(tasks[0].is_runnable());
+        // The kernel delivered the notification as a message from itself,
+        // carrying only the bits that were asked for.
+        assert_eq!(tasks[0].save().regs[1], u32::from(TaskId::KERNEL.0));
+        assert_eq!(tasks[0].save().regs[2], 0b100);
+
+        // The bits that weren't asked for are still pending.
+        recv(&mut tasks[0], !0);
+        assert_eq!(tasks[0].take_notifications(), Some(0b011));
+    }
+
+    #[test]
+    fn post_is_ignored_by_closed_receive() {
+        let mut tasks = TaskTableBuilder::new().tasks(2, 0).build();
+        recv(&mut tasks[0], !0);
+        let sender = current_id(&tasks, 1);
+        tasks[0].save_mut().regs[3] = u32::from(sender.0) | 1 << 31;
+        tasks[0].set_healthy_state(SchedState::InRecv(Some(sender)));
+
+        assert!(!tasks[0].post(NotificationSet(1)));
+        assert!(!tasks[0].is_runnable());
+    }
+
+    #[test]
+    fn process_timers_fires_expired_timers() {
+        let mut tasks = TaskTableBuilder::new().tasks(3, 0).build();
+        recv(&mut tasks[1], 0b1);
+        tasks[1].set_timer(0, Some(Timestamp::from(100)), NotificationSet(0b1));
+        tasks[2].set_timer(0, Some(Timestamp::from(200)), NotificationSet(0b1));
+
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(99)),
+            NextTask::Same
+        );
+        assert!(!tasks[1].is_runnable());
+
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(100)),
+            NextTask::Specific(1)
+        );
+        assert!(tasks[1].is_runnable());
+        // Timers are one-shot.
+        assert_eq!(tasks[1].timer(0), (None, NotificationSet(0b1)));
+        // The other timer is still waiting.
+        assert_eq!(tasks[2].timer(0).0, Some(Timestamp::from(200)));
+        assert_eq!(next_deadline(&tasks), Some(Timestamp::from(200)));
+    }
+
+    #[test]
+    fn process_timers_handles_every_timer_of_a_task() {
+        let mut tasks = TaskTableBuilder::new().task(0).build();
+        tasks[0].set_timer(0, Some(Timestamp::from(10)), NotificationSet(0b01));
+        tasks[0].set_timer(1, Some(Timestamp::from(20)), NotificationSet(0b10));
+
+        // Nobody is listening, so nobody needs to be scheduled.
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(30)),
+            NextTask::Same
+        );
+        assert_eq!(tasks[0].timer(0).0, None);
+        assert_eq!(tasks[0].timer(1).0, None);
+        recv(&mut tasks[0], !0);
+        assert_eq!(tasks[0].take_notifications(), Some(0b11));
+        assert_eq!(next_deadline(&tasks), None);
+    }
+
+    #[test]
+    fn process_timers_wakes_several_tasks() {
+        let mut tasks = TaskTableBuilder::new().tasks(3, 0).build();
+        for task in &mut tasks[1..] {
+            recv(task, 0b1);
+            task.set_timer(0, Some(Timestamp::from(50)), NotificationSet(0b1));
+        }
+        // Two tasks woke, so the scheduler has to sort it out.
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(50)),
+            NextTask::Other
+        );
+        assert!(tasks[1].is_runnable());
+        assert!(tasks[2].is_runnable());
+    }
+
+    #[test]
+    fn stack_is_painted_and_unused_at_start() {
+        let tasks = TaskTableBuilder::new().task(0).build();
+        let usage = tasks[0].stack_usage();
+        assert_eq!(usage.size, crate::testing::TASK_STACK);
+        assert_eq!(usage.max_used, 0);
+    }
+}
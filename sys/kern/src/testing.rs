@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Support for unit tests that need a task table.
+//!
+//! `TaskTableBuilder` makes task tables shaped like the ones the kernel builds
+//! at startup, except that they're owned by the test. Each task gets a region
+//! of private, readable and writable memory, with its stack at the bottom, as
+//! `xtask` would lay it out. That memory is real -- the kernel will happily
+//! copy in and out of it -- and lives below 4 GiB, since task addresses are
+//! 32 bits wide even on a 64-bit host.
+
+use std::sync::Mutex;
+
+use abi::{RegionAttributes, RegionDesc, TaskDesc, TaskFlags};
+
+use crate::task::Task;
+use crate::umem::USlice;
+
+/// Size of the memory region given to each task, in bytes.
+pub const TASK_MEMORY: u32 = 1024;
+
+/// Size of the stack at the bottom of each task's memory, in bytes.
+pub const TASK_STACK: u32 = 256;
+
+/// Address at which we map the memory handed out to tasks. This is roughly
+/// where SRAM lives on our microcontrollers, and is (we hope) somewhere the
+/// host isn't using.
+const ARENA_BASE: u32 = 0x2000_0000;
+
+/// Amount of memory we map for tasks. This is never freed, so it needs to
+/// cover every task table built by every test in the process.
+const ARENA_SIZE: u32 = 64 << 20;
+
+/// Next free address in the arena, or `None` before it's been mapped.
+static ARENA_NEXT: Mutex<Option<u32>> = Mutex::new(None);
+
+/// Carves `size` bytes out of the arena, mapping it if we haven't yet.
+fn allocate(size: u32) -> u32 {
+    let mut next = ARENA_NEXT.lock().unwrap_or_else(|e| e.into_inner());
+    let base = next.unwrap_or_else(|| {
+        // Safety: MAP_FIXED_NOREPLACE will fail rather than replace any
+        // existing mapping.
+        let p = unsafe {
+            libc::mmap(
+                ARENA_BASE as usize as *mut libc::c_void,
+                ARENA_SIZE as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE
+                    | libc::MAP_ANONYMOUS
+                    | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if p as usize != ARENA_BASE as usize {
+            panic!("can't map test task memory at {:#x}", ARENA_BASE);
+        }
+        ARENA_BASE
+    });
+    assert!(base + size <= ARENA_BASE + ARENA_SIZE, "out of task memory");
+    *next = Some(base + size);
+    base
+}
+
+/// Builds a task table for tests.
+///
+/// Tasks are added in index order. Unless told otherwise, each one starts at
+/// boot, so it's `Runnable` in the finished table.
+#[derive(Default)]
+pub struct TaskTableBuilder {
+    tasks: Vec<(u8, TaskFlags)>,
+}
+
+impl TaskTableBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a task at `priority`.
+    pub fn task(mut self, priority: u8) -> Self {
+        self.tasks.push((priority, TaskFlags::START_AT_BOOT));
+        self
+    }
+
+    /// Adds `n` tasks at `priority`.
+    pub fn tasks(mut self, n: usize, priority: u8) -> Self {
+        for _ in 0..n {
+            self = self.task(priority);
+        }
+        self
+    }
+
+    /// Replaces the flags of the most recently added task.
+    pub fn flags(mut self, flags: TaskFlags) -> Self {
+        self.tasks.last_mut().expect("no task to apply flags to").1 = flags;
+        self
+    }
+
+    pub fn build(self) -> Vec<Task> {
+        self.tasks
+            .into_iter()
+            .enumerate()
+            .map(|(index, (priority, flags))| {
+                let base = allocate(TASK_MEMORY);
+                let region: &'static RegionDesc =
+                    Box::leak(Box::new(RegionDesc {
+                        base,
+                        size: TASK_MEMORY,
+                        attributes: RegionAttributes::READ
+                            | RegionAttributes::WRITE,
+                    }));
+                let descriptor = Box::leak(Box::new(TaskDesc {
+                    regions: [0; abi::REGIONS_PER_TASK],
+                    entry_point: 0,
+                    initial_stack: base + TASK_STACK,
+                    priority,
+                    flags,
+                    index: index as u16,
+                    allowed_callers: 0,
+                }));
+                let regions = Box::leak(vec![region].into_boxed_slice());
+
+                let mut task = Task::from_descriptor(descriptor, regions);
+                crate::arch::reinitialize(&mut task);
+                task
+            })
+            .collect()
+    }
+}
+
+/// Returns a slice of `len` bytes at `offset` in `task`'s memory, above its
+/// stack.
+pub fn buffer(task: &Task, offset: u32, len: u32) -> USlice<u8> {
+    assert!(TASK_STACK + offset + len <= TASK_MEMORY);
+    let base = task.region_table()[0].base + TASK_STACK + offset;
+    USlice::from_raw(base as usize, len as usize).unwrap()
+}
+
+/// Fills `slice` in `task`'s memory with `data`.
+pub fn fill(task: &mut Task, mut slice: USlice<u8>, data: &[u8]) {
+    task.try_write(&mut slice).unwrap().copy_from_slice(data);
+}
+
+/// Reads back `slice` from `task`'s memory.
+pub fn contents(task: &Task, slice: &USlice<u8>) -> Vec<u8> {
+    task.try_read(slice).unwrap().to_vec()
+}
@@ -334,3 +334,169 @@ fn index2_distinct<T>(
         panic!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TaskTableBuilder, TASK_MEMORY};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// Picks a base address and length, clustered near the bottom or the top
+    /// of the address space so that overlaps (and near misses) are common.
+    fn arbitrary_range(rng: &mut impl Rng) -> (usize, usize) {
+        let len = rng.gen_range(0..=64);
+        let base = if rng.gen() {
+            rng.gen_range(0..=128)
+        } else {
+            usize::MAX - rng.gen_range(0..=128)
+        };
+        (base, len)
+    }
+
+    /// Checks whether two ranges overlap, the obvious way, with enough bits
+    /// that nothing can wrap. Like `USlice::aliases`, this treats an empty
+    /// range as overlapping nothing, even if it lies inside the other.
+    fn overlaps(a: (usize, usize), b: (usize, usize)) -> bool {
+        if a.1 == 0 || b.1 == 0 {
+            return false;
+        }
+        let (a0, b0) = (a.0 as u128, b.0 as u128);
+        let (a1, b1) = (a0 + a.1 as u128, b0 + b.1 as u128);
+        a0 < b1 && b0 < a1
+    }
+
+    #[test]
+    fn from_raw_rejects_wrapping_slices() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0x51ce);
+        for _ in 0..10_000 {
+            let (base, len) = arbitrary_range(&mut rng);
+            let wraps = base as u128 + len as u128 > usize::MAX as u128;
+            assert_eq!(
+                USlice::<u8>::from_raw(base, len).is_err(),
+                wraps,
+                "{:#x} + {}",
+                base,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn aliases_matches_model() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0xa11a5);
+        let mut hits = 0;
+        for _ in 0..100_000 {
+            let a = arbitrary_range(&mut rng);
+            let b = arbitrary_range(&mut rng);
+            let (sa, sb) = match (
+                USlice::<u8>::from_raw(a.0, a.1),
+                USlice::<u8>::from_raw(b.0, b.1),
+            ) {
+                (Ok(sa), Ok(sb)) => (sa, sb),
+                _ => continue,
+            };
+            let expected = overlaps(a, b);
+            assert_eq!(sa.aliases(&sb), expected, "{:x?} vs {:x?}", a, b);
+            assert_eq!(sb.aliases(&sa), expected, "{:x?} vs {:x?}", b, a);
+            hits += usize::from(expected);
+        }
+        // Make sure we actually tested some overlapping cases.
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn aliases_at_top_of_address_space() {
+        let top = USlice::<u8>::from_raw(usize::MAX - 4, 4).unwrap();
+        let below = USlice::<u8>::from_raw(usize::MAX - 8, 4).unwrap();
+        let straddle = USlice::<u8>::from_raw(usize::MAX - 5, 2).unwrap();
+        assert!(top.aliases(&top));
+        assert!(!top.aliases(&below));
+        assert!(top.aliases(&straddle));
+        assert!(below.aliases(&straddle));
+        assert!(!USlice::<u8>::empty().aliases(&USlice::empty()));
+    }
+
+    #[test]
+    fn safe_copy_copies_shorter_length() {
+        let mut tasks = TaskTableBuilder::new().tasks(2, 0).build();
+        let src = testing::buffer(&tasks[0], 0, 8);
+        let dst = testing::buffer(&tasks[1], 0, 4);
+        testing::fill(&mut tasks[0], src.clone(), b"abcdefgh");
+
+        assert_eq!(safe_copy(&mut tasks, 0, src, 1, dst.clone()).unwrap(), 4);
+        assert_eq!(testing::contents(&tasks[1], &dst), b"abcd");
+    }
+
+    #[test]
+    fn safe_copy_blames_aliased_destination() {
+        let mut tasks = TaskTableBuilder::new().tasks(2, 0).build();
+        let src = testing::buffer(&tasks[0], 0, 8);
+        let fault = safe_copy(&mut tasks, 0, src.clone(), 1, src).unwrap_err();
+        assert!(fault.src.is_none());
+        assert!(fault.dst.is_some());
+    }
+
+    /// Picks a slice that usually lies in the memory of task `owner`, but may
+    /// hang off either end, or lie in the other task's memory. Returns the
+    /// slice and whether `owner` should be able to access it.
+    fn pick(
+        rng: &mut impl Rng,
+        bases: &[usize; 2],
+        owner: usize,
+    ) -> (USlice<u8>, bool) {
+        let region = if rng.gen_ratio(1, 8) {
+            1 - owner
+        } else {
+            owner
+        };
+        let size = TASK_MEMORY as usize;
+        let offset = rng.gen_range(0..size + 16);
+        let len = rng.gen_range(0..=64.min(size + 32 - offset));
+        // Decide from the address range itself, since running off the end of
+        // one task's memory may land in the other's.
+        let addr = bases[region] + offset;
+        let start = bases[owner];
+        let ok = len == 0 || (start <= addr && addr + len <= start + size);
+        (USlice::from_raw(addr, len).unwrap(), ok)
+    }
+
+    /// Copies between random slices, in and around the memory of two tasks,
+    /// and checks that `safe_copy` copies exactly when both sides are
+    /// accessible, and otherwise blames the right task(s).
+    #[test]
+    fn safe_copy_checks_both_sides() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0xc0b1);
+        let mut tasks = TaskTableBuilder::new().tasks(2, 0).build();
+        let bases = [
+            tasks[0].region_table()[0].base as usize,
+            tasks[1].region_table()[0].base as usize,
+        ];
+        let mut copies = 0;
+        for _ in 0..10_000 {
+            let (src, src_ok) = pick(&mut rng, &bases, 0);
+            let (dst, dst_ok) = pick(&mut rng, &bases, 1);
+            let data: Vec<u8> = (0..src.len()).map(|_| rng.gen()).collect();
+            if src_ok {
+                testing::fill(&mut tasks[0], src.clone(), &data);
+            }
+            let dst_ok = dst_ok && !src.aliases(&dst);
+
+            match safe_copy(&mut tasks, 0, src.clone(), 1, dst.clone()) {
+                Ok(n) => {
+                    assert!(src_ok && dst_ok, "{:x?} -> {:x?}", src, dst);
+                    assert_eq!(n, src.len().min(dst.len()));
+                    let copied = testing::contents(&tasks[1], &dst);
+                    assert_eq!(copied[..n], data[..n]);
+                    copies += 1;
+                }
+                Err(fault) => {
+                    assert!(!(src_ok && dst_ok), "{:x?} -> {:x?}", src, dst);
+                    assert_eq!(fault.src.is_some(), !src_ok);
+                    assert_eq!(fault.dst.is_some(), !dst_ok);
+                }
+            }
+        }
+        assert!(copies > 1000);
+    }
+}
@@ -7,8 +7,11 @@ use std::env;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile();
 
-    // Do an architecture check.
-    if env::var("CARGO_CFG_TARGET_OS").unwrap() != "none" {
+    // Do an architecture check. The simulator target is the one hosted
+    // target we accept.
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() != "none"
+        && env::var("TARGET").unwrap() != build_util::SIM_TARGET
+    {
         eprintln!("***********************************************");
         eprintln!("Hi!");
         eprintln!("You appear to be building this natively,");
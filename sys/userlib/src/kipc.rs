@@ -24,6 +24,179 @@ pub fn read_task_status(task: usize) -> abi::TaskState {
     ssmarshal::deserialize(&response[..len]).unwrap_lite().0
 }
 
+/// Reads the status of `task` along with its effective priority, which may be
+/// more important than its configured priority if it has inherited a boost
+/// from a task blocked on it in IPC.
+pub fn read_task_status_and_priority(
+    task: usize,
+) -> (abi::TaskState, abi::Priority) {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<(abi::TaskState, u8)>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStatus as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    let (state, priority): (abi::TaskState, u8) =
+        ssmarshal::deserialize(&response[..len]).unwrap_lite().0;
+    (state, abi::Priority(priority))
+}
+
+/// Reads the kernel's CPU time and syscall accounting for `task`.
+pub fn read_task_stats(task: usize) -> abi::TaskStats {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::TaskStats>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStats as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads how deep `task`'s stack has gone since it was last started.
+pub fn read_stack_usage(task: usize) -> abi::StackUsage {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::StackUsage>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadStackUsage as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads the kernel's statistics for hardware interrupt `irq`, or returns
+/// `None` if the application doesn't use that interrupt.
+pub fn read_irq_stats(irq: u32) -> Option<abi::IrqStats> {
+    let mut response = [0; core::mem::size_of::<Option<abi::IrqStats>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadIrqStats as u16,
+        irq.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Takes the oldest event out of the kernel's event log, or returns `None` if
+/// the log is empty.
+///
+/// The log has a single reader: events read here are gone for good. This is
+/// intended for use by the supervisor.
+pub fn read_kernel_event() -> Option<abi::KernelEventRecord> {
+    let mut response =
+        [0; core::mem::size_of::<Option<abi::KernelEventRecord>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadKernelEvent as u16,
+        &[],
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reports on our asynchronous send (see `sys_send_async`). If it's over, the
+/// kernel forgets about it, so we can make another.
+pub fn collect_async_reply() -> abi::AsyncSendStatus {
+    let mut response = [0; core::mem::size_of::<abi::AsyncSendStatus>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::CollectAsyncReply as u16,
+        &[],
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Abandons our asynchronous send, if any. A reply that turns up afterwards is
+/// discarded, and the buffers passed to `sys_send_async` are ours again.
+pub fn cancel_async_send() {
+    let (rc, _len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::CancelAsyncSend as u16,
+        &[],
+        &mut [],
+        &[],
+    );
+    assert_eq!(rc, 0);
+}
+
+/// Reads the registers the kernel saved for `task` when it faulted, or
+/// returns `None` if it isn't faulted.
+pub fn read_task_registers(task: usize) -> Option<abi::SavedRegisters> {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<Option<abi::SavedRegisters>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskRegisters as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Copies the stack of faulted task `task`, starting at its stack pointer,
+/// into `buf`, and returns the number of bytes copied. This is zero if the
+/// task isn't faulted, or if its stack pointer was bad.
+pub fn read_task_stack(task: usize, buf: &mut [u8]) -> usize {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStack as u16,
+        task.as_bytes(),
+        buf,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    len
+}
+
+/// Reads the start of the message that `task` panicked with into `buf`, and
+/// returns the part of `buf` that was filled in. This is empty if the task
+/// isn't faulted because of a panic.
+///
+/// The message is truncated to `abi::PANIC_MESSAGE_MAX` bytes, and so may end
+/// partway through a UTF-8 sequence.
+pub fn read_panic_message(
+    task: usize,
+    buf: &mut [u8; abi::PANIC_MESSAGE_MAX],
+) -> &[u8] {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadPanicMessage as u16,
+        task.as_bytes(),
+        buf,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    &buf[..len]
+}
+
 pub fn restart_task(task: usize, start: bool) {
     // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
     let msg = (task as u32, start);
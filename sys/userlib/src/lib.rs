@@ -23,6 +23,9 @@
 //! all registers.
 //!
 //! See: https://github.com/rust-lang/rust/issues/73450#issuecomment-650463347
+//!
+//! When building for the hosted simulator, the stubs (and `_start`) come from
+//! the `sim` module instead.
 
 #![no_std]
 #![feature(asm_const)]
@@ -37,11 +40,19 @@ pub use num_derive::{FromPrimitive, ToPrimitive};
 pub use num_traits::{FromPrimitive, ToPrimitive};
 pub use unwrap_lite::UnwrapLite;
 
+#[cfg(not(hubris_sim))]
 use core::arch;
 use core::marker::PhantomData;
+#[cfg(hubris_sim)]
+pub use sim::HostConsole;
+#[cfg(hubris_sim)]
+use sim::*;
 
 pub mod hl;
 pub mod kipc;
+pub mod shared;
+#[cfg(hubris_sim)]
+mod sim;
 pub mod task_slot;
 pub mod units;
 pub mod util;
@@ -86,6 +97,30 @@ impl<'a> Lease<'a> {
             _marker: PhantomData,
         }
     }
+
+    /// Passes lease number `index` of a message from `client` along to
+    /// another task, without copying it through our own memory.
+    ///
+    /// This is only useful while `client` is waiting for our reply. The
+    /// recipient sees at most `length` bytes of the client's lease, and gets
+    /// at most the access in `attributes` -- it can't get access the client
+    /// didn't grant us.
+    pub fn forward(
+        client: TaskId,
+        index: usize,
+        attributes: LeaseAttributes,
+        length: usize,
+    ) -> Lease<'static> {
+        assert!(index <= usize::from(u16::MAX));
+        Lease {
+            _kern_rep: abi::ULease {
+                attributes: attributes | LeaseAttributes::FORWARD,
+                base_address: u32::from(client.0) | (index as u32) << 16,
+                length: length as u32,
+            },
+            _marker: PhantomData,
+        }
+    }
 }
 
 impl<'a> From<&'a [u8]> for Lease<'a> {
@@ -137,6 +172,98 @@ pub fn sys_send(
     unsafe { sys_send_stub(&mut args).into() }
 }
 
+/// Like `sys_send`, but gives up if the exchange isn't over within `timeout`
+/// kernel ticks, returning `TIMED_OUT` as the response code.
+///
+/// The timeout covers the whole exchange: both waiting for `target` to receive
+/// the message, and waiting for its reply. If the reply turns up after we've
+/// given up, it's discarded; until then, further messages to `target` wait
+/// to be received, so that they can't pick it up by mistake.
+///
+/// This makes it safe to call servers you don't entirely trust, which is
+/// otherwise something a supervisor must never do.
+///
+/// # Panics
+///
+/// If `timeout` is greater than `MAX_SEND_TIMEOUT`.
+#[inline(always)]
+pub fn sys_send_timeout(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    leases: &[Lease<'_>],
+    timeout: u32,
+) -> (u32, usize) {
+    assert!(timeout <= MAX_SEND_TIMEOUT);
+    let mut args = SendArgs {
+        packed_target_operation: u32::from(target.0) << 16
+            | u32::from(operation),
+        outgoing_ptr: outgoing.as_ptr(),
+        outgoing_len: outgoing.len(),
+        incoming_ptr: incoming.as_mut_ptr(),
+        incoming_len: incoming.len(),
+        lease_ptr: leases.as_ptr(),
+        lease_len: leases.len(),
+    };
+    let descriptor = timeout << SYSNUM_BITS | Sysnum::SendTimeout as u32;
+    unsafe { sys_send_timeout_stub(&mut args, descriptor).into() }
+}
+
+/// Sends a message to `target` without waiting for the reply.
+///
+/// The kernel delivers the message when `target` receives it, writes its reply
+/// into `incoming` whenever it comes, and then posts notification bit
+/// `notification_bit` to us. Use `kipc::collect_async_reply` to find out the
+/// response code and reply length (and to free things up for the next send).
+///
+/// Only one asynchronous send can be outstanding at a time, and it can't lend
+/// memory. While it's outstanding, a `sys_send` to the same task is a fault.
+///
+/// On success, the message has been queued; if `target` is dead, this returns
+/// its dead-task response code, as `sys_send` would.
+///
+/// This lets a task that must never block on a less important one -- such as
+/// the supervisor -- make requests of it anyway.
+///
+/// # Safety
+///
+/// The kernel reads `outgoing` and writes `incoming` after this returns, so
+/// both must stay valid, and `incoming` must be left alone, until the reply
+/// has been collected or the send cancelled with `kipc::cancel_async_send`.
+///
+/// # Panics
+///
+/// If `notification_bit` is 32 or more.
+#[inline(always)]
+pub unsafe fn sys_send_async(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    notification_bit: u8,
+) -> Result<(), u32> {
+    assert!(notification_bit < 32);
+    let mut args = SendArgs {
+        packed_target_operation: u32::from(target.0) << 16
+            | u32::from(operation),
+        outgoing_ptr: outgoing.as_ptr(),
+        outgoing_len: outgoing.len(),
+        incoming_ptr: incoming.as_mut_ptr(),
+        incoming_len: incoming.len(),
+        lease_ptr: core::ptr::null(),
+        lease_len: 0,
+    };
+    let descriptor =
+        u32::from(notification_bit) << SYSNUM_BITS | Sysnum::SendAsync as u32;
+    let (rc, _) = sys_send_timeout_stub(&mut args, descriptor).into();
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(rc)
+    }
+}
+
 #[allow(dead_code)] // this gets used from asm
 #[repr(C)] // field order matters
 struct SendArgs<'a> {
@@ -152,6 +279,7 @@ struct SendArgs<'a> {
 /// Core implementation of the SEND syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     cfg_if::cfg_if! {
@@ -221,6 +349,79 @@ unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     }
 }
 
+/// Core implementation of the SEND_TIMEOUT syscall.
+///
+/// This is the same as `sys_send_stub`, except that the syscall descriptor,
+/// which carries the timeout, is passed in rather than being a constant.
+#[cfg(not(hubris_sim))]
+#[naked]
+unsafe extern "C" fn sys_send_timeout_stub(
+    _args: &mut SendArgs<'_>,
+    _descriptor: u32,
+) -> RcLen {
+    cfg_if::cfg_if! {
+        if #[cfg(armv6m)] {
+            arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r7, lr}}
+                mov r4, r8
+                mov r5, r9
+                mov r6, r10
+                mov r7, r11
+                push {{r4-r7}}
+                @ Load the syscall descriptor.
+                mov r11, r1
+                @ Load in args from the struct.
+                ldm r0!, {{r4-r7}}
+                ldm r0, {{r0-r2}}
+                mov r8, r0
+                mov r9, r1
+                mov r10, r2
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r7}}
+                mov r8, r4
+                mov r9, r5
+                mov r10, r6
+                mov r11, r7
+                pop {{r4-r7, pc}}
+                ",
+                options(noreturn),
+            )
+        } else if #[cfg(any(armv7m, armv8m))] {
+            arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r11}}
+                @ Load in args from the struct.
+                ldm r0, {{r4-r10}}
+                @ Load the syscall descriptor.
+                mov r11, r1
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r11}}
+                @ Fin.
+                bx lr
+                ",
+                options(noreturn),
+            )
+        } else {
+            compile_error!("missing sys_send_timeout_stub for ARM profile");
+        }
+    }
+}
+
 /// Performs an "open" RECV that will accept messages from any task or
 /// notifications from the kernel.
 ///
@@ -329,6 +530,7 @@ pub struct RecvMessage {
 /// Core implementation of the RECV syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 #[must_use]
 unsafe extern "C" fn sys_recv_stub(
@@ -446,6 +648,7 @@ pub fn sys_reply(peer: TaskId, code: u32, message: &[u8]) {
 /// Core implementation of the REPLY syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_reply_stub(
     _peer: u32,
@@ -529,12 +732,26 @@ unsafe extern "C" fn sys_reply_stub(
 /// had it been set earlier -- that is, if the deadline is `<=` the current time
 /// -- the `notifications` will be posted immediately and the timer will not be
 /// enabled.
+///
+/// This is the task's first timer; see `sys_set_timer_n` for the others.
 #[inline(always)]
 pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
+    sys_set_timer_n(0, deadline, notifications)
+}
+
+/// Sets one of this task's timers, which are numbered from 0. Each timer works
+/// like the one set by `sys_set_timer` (which is timer 0), independently of the
+/// others.
+///
+/// The number of timers each task has is set by `timers-per-task` in the
+/// `[kernel]` section of the application config, and defaults to 1. Naming a
+/// timer beyond that is a fault.
+#[inline(always)]
+pub fn sys_set_timer_n(timer: u16, deadline: Option<u64>, notifications: u32) {
     let raw_deadline = deadline.unwrap_or(0);
     unsafe {
         sys_set_timer_stub(
-            deadline.is_some() as u32,
+            u32::from(timer) << 16 | deadline.is_some() as u32,
             raw_deadline as u32,
             (raw_deadline >> 32) as u32,
             notifications,
@@ -545,6 +762,7 @@ pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
 /// Core implementation of the SET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_set_timer_stub(
     _set_timer: u32,
@@ -633,6 +851,7 @@ pub fn sys_borrow_read(
 /// Core implementation of the BORROW_READ syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_borrow_read_stub(_args: *mut BorrowReadArgs) -> RcLen {
     cfg_if::cfg_if! {
@@ -728,6 +947,7 @@ pub fn sys_borrow_write(
 /// Core implementation of the BORROW_WRITE syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_borrow_write_stub(
     _args: *mut BorrowWriteArgs,
@@ -845,6 +1065,7 @@ pub struct BorrowInfo {
 /// Core implementation of the BORROW_INFO syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_borrow_info_stub(
     _lender: u32,
@@ -921,6 +1142,7 @@ pub fn sys_irq_control(mask: u32, enable: bool) {
 /// Core implementation of the IRQ_CONTROL syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_irq_control_stub(_mask: u32, _enable: u32) {
     cfg_if::cfg_if! {
@@ -988,6 +1210,7 @@ pub fn sys_panic(msg: &[u8]) -> ! {
 /// Core implementation of the PANIC syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
     cfg_if::cfg_if! {
@@ -1053,13 +1276,22 @@ unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
 /// `deadline` and `on_dl` are as configured by `sys_set_timer`.
 ///
 /// `now` is monotonically advancing and can't be changed.
+///
+/// This reads the task's first timer; see `sys_get_timer_n` for the others.
 #[inline(always)]
 pub fn sys_get_timer() -> TimerState {
+    sys_get_timer_n(0)
+}
+
+/// Reads the state of one of this task's timers, as set by `sys_set_timer_n`.
+/// This is otherwise the same as `sys_get_timer`.
+#[inline(always)]
+pub fn sys_get_timer_n(timer: u16) -> TimerState {
     use core::mem::MaybeUninit;
 
     let mut out = MaybeUninit::<RawTimerState>::uninit();
     unsafe {
-        sys_get_timer_stub(out.as_mut_ptr());
+        sys_get_timer_stub(u32::from(timer), out.as_mut_ptr());
     }
     // Safety: stub fully initializes output struct.
     let out = unsafe { out.assume_init() };
@@ -1098,8 +1330,9 @@ struct RawTimerState {
 /// Core implementation of the GET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
-unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
+unsafe extern "C" fn sys_get_timer_stub(_timer: u32, _out: *mut RawTimerState) {
     cfg_if::cfg_if! {
         if #[cfg(armv6m)] {
             arch::asm!("
@@ -1114,15 +1347,17 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
                 eors r4, r4
                 adds r4, #{sysnum}
                 mov r11, r4
+                @ Move register arguments into place.
+                mov r4, r0
 
                 @ To the kernel!
                 svc #0
 
                 @ Write all the results out into the raw output buffer.
-                stm r0!, {{r4-r7}}
+                stm r1!, {{r4-r7}}
                 mov r4, r8
                 mov r5, r9
-                stm r0!, {{r4, r5}}
+                stm r1!, {{r4, r5}}
                 @ Restore the registers we used.
                 pop {{r4-r7}}
                 mov r11, r7
@@ -1138,6 +1373,8 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
             arch::asm!("
                 @ Spill the registers we're about to use to pass stuff.
                 push {{r4-r11}}
+                @ Move register arguments into place.
+                mov r4, r0
                 @ Load the constant syscall number.
                 mov r11, {sysnum}
 
@@ -1145,7 +1382,7 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
                 svc #0
 
                 @ Write all the results out into the raw output buffer.
-                stm r0, {{r4-r9}}
+                stm r1, {{r4-r9}}
                 @ Restore the registers we used.
                 pop {{r4-r11}}
                 @ Fin.
@@ -1165,6 +1402,7 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
 #[doc(hidden)]
 #[no_mangle]
 #[link_section = ".text.start"]
+#[cfg(not(hubris_sim))]
 #[naked]
 pub unsafe extern "C" fn _start() -> ! {
     // Provided by the user program:
@@ -1457,6 +1695,7 @@ pub fn sys_refresh_task_id(task_id: TaskId) -> TaskId {
 /// Core implementation of the REFRESH_TASK_ID syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -1526,6 +1765,7 @@ pub fn sys_post(task_id: TaskId, bits: u32) -> u32 {
 /// Core implementation of the POST syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_post_stub(_tid: u32, _mask: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -1596,6 +1836,7 @@ pub fn sys_reply_fault(task_id: TaskId, reason: ReplyFaultReason) {
 /// Core implementation of the REPLY_FAULT syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_sim))]
 #[naked]
 unsafe extern "C" fn sys_reply_fault_stub(_tid: u32, _reason: u32) {
     cfg_if::cfg_if! {
@@ -32,6 +32,22 @@ cfg_if::cfg_if! {
                 { let _ = cortex_m_semihosting::hprintln!($s, $($tt)*); }
             };
         }
+    } else if #[cfg(feature = "log-sim")] {
+        #[macro_export]
+        macro_rules! sys_log {
+            ($s:expr) => {
+                {
+                    use core::fmt::Write;
+                    let _ = writeln!($crate::HostConsole, $s);
+                }
+            };
+            ($s:expr, $($tt:tt)*) => {
+                {
+                    use core::fmt::Write;
+                    let _ = writeln!($crate::HostConsole, $s, $($tt)*);
+                }
+            };
+        }
     } else if #[cfg(feature = "log-null")] {
         #[macro_export]
         macro_rules! sys_log {
@@ -86,3 +102,31 @@ macro_rules! task_slot {
         }
     };
 }
+
+/// Declares a static `SharedRegion` for the shared region named `$region` in
+/// the application config, which must be mapped into this task.
+///
+/// ```ignore
+/// shared_region!(ETH_RX, eth_rx);
+/// ```
+#[macro_export]
+macro_rules! shared_region {
+    ($var:ident, $region:ident) => {
+        $crate::macros::paste::paste! {
+            static $var: $crate::shared::SharedRegion = {
+                extern "C" {
+                    static [< __SHARED_ $region:upper _BASE >]: u8;
+                    static [< __SHARED_ $region:upper _END >]: u8;
+                }
+                // Safety: these symbols are defined by the build system, and
+                // we only take their addresses.
+                unsafe {
+                    $crate::shared::SharedRegion::new(
+                        core::ptr::addr_of!([< __SHARED_ $region:upper _BASE >]),
+                        core::ptr::addr_of!([< __SHARED_ $region:upper _END >]),
+                    )
+                }
+            };
+        }
+    };
+}
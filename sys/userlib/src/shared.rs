@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Memory shared between tasks.
+//!
+//! An application can declare regions of memory that are mapped into several
+//! tasks, in `[shared-regions.*]` sections of its `app.toml`:
+//!
+//! ```toml
+//! [shared-regions.eth_rx]
+//! size = 4096
+//! tasks = {net = "read-write", udpecho = "read"}
+//! ```
+//!
+//! The build system allocates the region (from `ram`, unless it says otherwise
+//! with `memory = "..."`), maps it into each task with the access given, and
+//! tells each task's linker where it ended up. A task gets at it with the
+//! `shared_region!` macro:
+//!
+//! ```ignore
+//! shared_region!(ETH_RX, eth_rx);
+//! ```
+//!
+//! The kernel doesn't know or care what's in a shared region, so the tasks
+//! using it have to agree on how -- for instance, a ring buffer whose indices
+//! each side updates atomically, with a notification to say that there's
+//! something new. Because other tasks can change the contents at any time,
+//! this module only hands out raw pointers.
+
+/// A shared region mapped into this task. Use `shared_region!` to get one.
+pub struct SharedRegion {
+    base: *const u8,
+    end: *const u8,
+}
+
+// Safety: a `SharedRegion` only tells you where the memory is; using it is
+// already unsafe.
+unsafe impl Sync for SharedRegion {}
+
+impl SharedRegion {
+    #[doc(hidden)]
+    pub const fn new(base: *const u8, end: *const u8) -> Self {
+        Self { base, end }
+    }
+
+    /// Returns a pointer to the start of the region. Writing through it will
+    /// fault if the region is only mapped for reading.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base as *mut u8
+    }
+
+    /// Returns the size of the region in bytes. This may be larger than the
+    /// `app.toml` asked for, since the build system rounds sizes up to suit
+    /// the MPU.
+    pub fn len(&self) -> usize {
+        self.end as usize - self.base as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
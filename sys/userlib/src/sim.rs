@@ -0,0 +1,339 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Syscall stubs for the hosted simulator.
+//!
+//! These have the same names and signatures as the ARM stubs in the crate
+//! root. Instead of `svc`, they trap into the simulator kernel with `ud2`,
+//! passing the address of a block of eight words in `eax`: the seven syscall
+//! argument registers followed by the syscall number, in the same order as
+//! `r4`-`r11` on ARM. The kernel overwrites the block with the return
+//! registers before resuming us. (See the kernel's `arch::sim` module.)
+//!
+//! Because the block lives in memory rather than in registers, none of the
+//! register-allocation contortions needed on ARM apply here, and these are
+//! ordinary functions.
+
+use super::*;
+
+/// Traps into the kernel with the given register block.
+#[inline(always)]
+fn syscall(nr: Sysnum, regs: [u32; 8]) -> [u32; 8] {
+    syscall_with_descriptor(nr as u32, regs)
+}
+
+/// Traps into the kernel with the given register block and a full syscall
+/// descriptor, for syscalls that use its high bits.
+#[inline(always)]
+fn syscall_with_descriptor(descriptor: u32, mut regs: [u32; 8]) -> [u32; 8] {
+    regs[7] = descriptor;
+    // Safety: the kernel only reads and writes the block we hand it.
+    unsafe {
+        core::arch::asm!(
+            "ud2",
+            in("eax") &mut regs as *mut [u32; 8],
+            options(nostack),
+        );
+    }
+    regs
+}
+
+fn rc_len(regs: [u32; 8]) -> RcLen {
+    RcLen(u64::from(regs[0]) | u64::from(regs[1]) << 32)
+}
+
+pub(crate) unsafe extern "C" fn sys_send_stub(
+    args: &mut SendArgs<'_>,
+) -> RcLen {
+    rc_len(syscall(
+        Sysnum::Send,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.lease_ptr as u32,
+            args.lease_len as u32,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_send_timeout_stub(
+    args: &mut SendArgs<'_>,
+    descriptor: u32,
+) -> RcLen {
+    rc_len(syscall_with_descriptor(
+        descriptor,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.lease_ptr as u32,
+            args.lease_len as u32,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_recv_stub(
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+    notification_mask: u32,
+    specific_sender: u32,
+    out: *mut RawRecvMessage,
+) -> u32 {
+    let regs = syscall(
+        Sysnum::Recv,
+        [
+            buffer_ptr as u32,
+            buffer_len as u32,
+            notification_mask,
+            specific_sender,
+            0,
+            0,
+            0,
+            0,
+        ],
+    );
+    // Safety: our caller passes a pointer to an output struct.
+    unsafe {
+        out.write(RawRecvMessage {
+            sender: regs[1],
+            operation: regs[2],
+            message_len: regs[3] as usize,
+            response_capacity: regs[4] as usize,
+            lease_count: regs[5] as usize,
+        });
+    }
+    regs[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_reply_stub(
+    peer: u32,
+    code: u32,
+    message_ptr: *const u8,
+    message_len: usize,
+) {
+    syscall(
+        Sysnum::Reply,
+        [
+            peer,
+            code,
+            message_ptr as u32,
+            message_len as u32,
+            0,
+            0,
+            0,
+            0,
+        ],
+    );
+}
+
+pub(crate) unsafe extern "C" fn sys_set_timer_stub(
+    set_timer: u32,
+    deadline_lo: u32,
+    deadline_hi: u32,
+    notification: u32,
+) {
+    syscall(
+        Sysnum::SetTimer,
+        [
+            set_timer,
+            deadline_lo,
+            deadline_hi,
+            notification,
+            0,
+            0,
+            0,
+            0,
+        ],
+    );
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_read_stub(
+    args: *mut BorrowReadArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument struct.
+    let args = unsafe { &*args };
+    rc_len(syscall(
+        Sysnum::BorrowRead,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            args.dest as u32,
+            args.dest_len as u32,
+            0,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_write_stub(
+    args: *mut BorrowWriteArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument struct.
+    let args = unsafe { &*args };
+    rc_len(syscall(
+        Sysnum::BorrowWrite,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            args.src as u32,
+            args.src_len as u32,
+            0,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_info_stub(
+    lender: u32,
+    index: usize,
+    out: *mut RawBorrowInfo,
+) {
+    let regs =
+        syscall(Sysnum::BorrowInfo, [lender, index as u32, 0, 0, 0, 0, 0, 0]);
+    // Safety: our caller passes a pointer to an output struct.
+    unsafe {
+        out.write(RawBorrowInfo {
+            rc: regs[0],
+            atts: regs[1],
+            length: regs[2] as usize,
+        });
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_irq_control_stub(mask: u32, enable: u32) {
+    syscall(Sysnum::IrqControl, [mask, enable, 0, 0, 0, 0, 0, 0]);
+}
+
+pub(crate) unsafe extern "C" fn sys_panic_stub(
+    msg: *const u8,
+    len: usize,
+) -> ! {
+    syscall(Sysnum::Panic, [msg as u32, len as u32, 0, 0, 0, 0, 0, 0]);
+    // The kernel doesn't return from a panic; if it somehow does, make sure
+    // we stop anyway.
+    loop {
+        // Safety: trapping with a null block gets the kernel to fault us.
+        unsafe { core::arch::asm!("ud2", in("eax") 0, options(nostack)) }
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_get_timer_stub(
+    timer: u32,
+    out: *mut RawTimerState,
+) {
+    let regs = syscall(Sysnum::GetTimer, [timer, 0, 0, 0, 0, 0, 0, 0]);
+    // Safety: our caller passes a pointer to an output struct.
+    unsafe {
+        out.write(RawTimerState {
+            now_lo: regs[0],
+            now_hi: regs[1],
+            set: regs[2],
+            dl_lo: regs[3],
+            dl_hi: regs[4],
+            on_dl: regs[5],
+        });
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_refresh_task_id_stub(tid: u32) -> u32 {
+    syscall(Sysnum::RefreshTaskId, [tid, 0, 0, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_post_stub(tid: u32, mask: u32) -> u32 {
+    syscall(Sysnum::Post, [tid, mask, 0, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_reply_fault_stub(tid: u32, reason: u32) {
+    syscall(Sysnum::ReplyFault, [tid, reason, 0, 0, 0, 0, 0, 0]);
+}
+
+/// Writer for the simulator's console (the host process's standard error).
+///
+/// This is what `sys_log!` uses with the `log-sim` feature. It makes host
+/// system calls directly, without involving the Hubris kernel, which is
+/// exactly the kind of thing that can't work on hardware.
+pub struct HostConsole;
+
+impl core::fmt::Write for HostConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        const SYS_WRITE: i32 = 4;
+        const STDERR: u32 = 2;
+
+        let mut rest = s.as_bytes();
+        while !rest.is_empty() {
+            let n: i32;
+            // Safety: write(2) only reads the buffer we give it. ebx can't be
+            // named as an operand on x86, so we swap the fd into it by hand.
+            unsafe {
+                core::arch::asm!(
+                    "xchg {fd}, ebx",
+                    "int 0x80",
+                    "xchg {fd}, ebx",
+                    fd = inout(reg) STDERR => _,
+                    inlateout("eax") SYS_WRITE => n,
+                    in("ecx") rest.as_ptr(),
+                    in("edx") rest.len(),
+                    options(nostack),
+                );
+            }
+            if n <= 0 {
+                return Err(core::fmt::Error);
+            }
+            rest = &rest[n as usize..];
+        }
+        Ok(())
+    }
+}
+
+/// This is the entry point for the task, invoked by the kernel. Its job is to
+/// set up our memory before jumping to user-defined `main`.
+#[doc(hidden)]
+#[no_mangle]
+#[link_section = ".text.start"]
+pub unsafe extern "C" fn _start() -> ! {
+    // Provided by the user program:
+    extern "Rust" {
+        fn main() -> !;
+    }
+    // Provided by the linker script.
+    extern "C" {
+        static mut __sdata: u32;
+        static mut __edata: u32;
+        static __sidata: u32;
+        static mut __sbss: u32;
+        static mut __ebss: u32;
+    }
+
+    // Safety: the linker script guarantees that these bound the data and BSS
+    // sections, which are 4-byte aligned and padded, and nothing has touched
+    // them yet.
+    unsafe {
+        let mut src = core::ptr::addr_of!(__sidata);
+        let mut dest = core::ptr::addr_of_mut!(__sdata);
+        while dest < core::ptr::addr_of_mut!(__edata) {
+            dest.write_volatile(src.read());
+            src = src.add(1);
+            dest = dest.add(1);
+        }
+
+        let mut dest = core::ptr::addr_of_mut!(__sbss);
+        while dest < core::ptr::addr_of_mut!(__ebss) {
+            dest.write_volatile(0);
+            dest = dest.add(1);
+        }
+
+        main()
+    }
+}
@@ -12,7 +12,11 @@ extern crate userlib;
 #[export_name = "main"]
 fn main() -> ! {
     loop {
-        if cfg!(feature = "insomniac") {
+        if cfg!(hubris_sim) {
+            // The simulator has no interrupts to wait for, and no debugger to
+            // upset, so spinning is all we can do.
+            core::hint::spin_loop();
+        } else if cfg!(feature = "insomniac") {
             // In insomniac-mode, we just spinloop to absorb idle cycles. This
             // is useful on certain processors where entering a low-power state
             // interrupts debugging.
@@ -6,6 +6,7 @@
 
 #![no_std]
 
+use derive_idol_err::IdolError;
 use serde::{Deserialize, Serialize};
 use userlib::*;
 
@@ -25,4 +26,89 @@ pub enum ResetReason {
     Unknown, // TODO remove and use `Option<ResetReason>` once we switch to hubpack
 }
 
+/// Errors from the supervisor's watchdog operations.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[repr(u32)]
+pub enum WatchdogError {
+    /// This supervisor was built without support for the chip's watchdog.
+    Unsupported = 1,
+    /// The requested timeout is outside what the hardware can do, or too
+    /// short for the supervisor to reliably kick it.
+    BadTimeout = 2,
+}
+
+/// What a task looked like when it last faulted, as captured by jefe before
+/// restarting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FaultSnapshot {
+    /// Kernel time at which jefe took the snapshot.
+    pub timestamp: u64,
+    pub fault: FaultInfo,
+    /// Registers saved by the kernel. Those stacked by the hardware are at
+    /// the start of the stack bytes.
+    pub registers: SavedRegisters,
+    /// Number of stack bytes captured, starting at `registers.sp`. These can
+    /// be read with `read_fault_snapshot_stack`.
+    pub stack_len: u32,
+    /// Number of bytes of panic message captured, if `fault` is a panic.
+    /// These can be read with `read_fault_snapshot_panic_message`.
+    pub panic_message_len: u32,
+}
+
+/// Errors from the fault snapshot operations.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[repr(u32)]
+pub enum SnapshotError {
+    /// The task index is out of range.
+    BadTask = 1,
+    /// The task hasn't faulted since boot, or snapshots are turned off.
+    NoSnapshot = 2,
+}
+
+/// How jefe has been restarting a task, for spotting crash loops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartStatus {
+    /// Number of times jefe has restarted the task since boot.
+    pub restarts: u32,
+    /// Number of faults within the task's current restart window.
+    pub recent_faults: u32,
+    /// If the task is waiting out a back-off, the kernel time at which jefe
+    /// will restart it.
+    pub restart_at: Option<u64>,
+    /// Whether the task exceeded its restart limit and is being held.
+    pub held: bool,
+}
+
+impl RestartStatus {
+    /// Checks whether the task looks to be stuck in a crash loop: that is,
+    /// it is being slowed down or held by its restart policy.
+    pub fn is_crash_looping(&self) -> bool {
+        self.held || self.restart_at.is_some()
+    }
+}
+
+/// Errors from the restart status operations.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[repr(u32)]
+pub enum RestartError {
+    /// The task index is out of range.
+    BadTask = 1,
+}
+
+/// Errors from the stack usage operation.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[repr(u32)]
+pub enum StackError {
+    /// The task index is out of range.
+    BadTask = 1,
+}
+
+/// Errors from the interrupt statistics operation.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[repr(u32)]
+pub enum IrqError {
+    /// The interrupt isn't used by this application.
+    NoSuchIrq = 1,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -41,6 +41,119 @@ fn main() -> Result<()> {
     }
     writeln!(out, "];")?;
 
+    let policy = "crate::restart::Policy";
+    writeln!(
+        out,
+        "pub(crate) const RESTART_POLICIES: [({}, {}); {}] = [",
+        task,
+        policy,
+        cfg.restart_policy.len()
+    )?;
+    for (name, p) in cfg.restart_policy {
+        writeln!(
+            out,
+            "    ({}::{}, {} {{ max_restarts: {}, window_ms: {}, \
+             backoff_ms: {}, max_backoff_ms: {}, \
+             escalate: crate::restart::Escalation::{:?} }}),",
+            task,
+            name,
+            policy,
+            p.max_restarts,
+            p.window_ms,
+            p.backoff_ms,
+            p.max_backoff_ms.unwrap_or(p.window_ms),
+            p.escalate,
+        )?;
+    }
+    writeln!(out, "];")?;
+
+    let task_ids = build_util::task_ids();
+    let jefe = std::env::var("HUBRIS_TASK_NAME")?;
+    let mut dependencies = vec![];
+    for (dependent, names) in &cfg.restart_with {
+        task_ids.names_to_ids(&[dependent])?;
+        task_ids.names_to_ids(names).with_context(|| {
+            format!("bad restart dependency for `{}`", dependent)
+        })?;
+        for dependency in names {
+            if dependency == dependent {
+                anyhow::bail!("task `{}` can't restart with itself", dependent);
+            }
+            if *dependency == jefe || *dependent == jefe {
+                anyhow::bail!(
+                    "`{}` can't be part of a restart dependency",
+                    jefe
+                );
+            }
+            dependencies.push((dependency, dependent));
+        }
+    }
+    writeln!(
+        out,
+        "pub(crate) const RESTART_DEPENDENCIES: [({}, {}); {}] = [",
+        task,
+        task,
+        dependencies.len()
+    )?;
+    for (dependency, dependent) in dependencies {
+        writeln!(
+            out,
+            "    ({}::{}, {}::{}),",
+            task, dependency, task, dependent
+        )?;
+    }
+    writeln!(out, "];")?;
+
+    writeln!(
+        out,
+        "pub(crate) const FAULT_SNAPSHOTS: bool = {};",
+        cfg.snapshot_stack_bytes.is_some()
+    )?;
+    writeln!(
+        out,
+        "pub(crate) const SNAPSHOT_STACK_BYTES: usize = {};",
+        cfg.snapshot_stack_bytes.unwrap_or(0)
+    )?;
+
+    let watchdog = cfg.watchdog.unwrap_or_default();
+    if watchdog.enabled
+        && std::env::var("CARGO_FEATURE_H743").is_err()
+        && std::env::var("CARGO_FEATURE_H753").is_err()
+        && std::env::var("CARGO_FEATURE_LPC55").is_err()
+    {
+        anyhow::bail!(
+            "jefe watchdog is configured, but no chip feature is enabled"
+        );
+    }
+    // Jefe kicks the watchdog from its 100 ms timer; leave room for it to be
+    // late a couple of times.
+    if watchdog.timeout_ms < 300 {
+        anyhow::bail!(
+            "jefe watchdog timeout of {} ms is too short (minimum 300 ms)",
+            watchdog.timeout_ms
+        );
+    }
+    writeln!(
+        out,
+        "pub(crate) const WATCHDOG_AT_BOOT: bool = {};",
+        watchdog.enabled
+    )?;
+    writeln!(
+        out,
+        "pub(crate) const WATCHDOG_TIMEOUT_MS: u32 = {};",
+        watchdog.timeout_ms
+    )?;
+    writeln!(
+        out,
+        "pub(crate) const CRITICAL: [{}; {}] = [",
+        task,
+        watchdog.critical.len()
+    )?;
+    for name in watchdog.critical {
+        writeln!(out, "    {}::{},", task, name)?;
+    }
+    writeln!(out, "];")?;
+
     Ok(())
 }
 
@@ -55,6 +168,86 @@ struct Config {
     /// Map of operation names to tasks allowed to call them.
     #[serde(default)]
     allowed_callers: BTreeMap<String, Vec<String>>,
+    /// Restart policies, as a map from task name to `RestartPolicy`. Tasks
+    /// without one are restarted immediately, however often they fault.
+    #[serde(default)]
+    restart_policy: BTreeMap<String, RestartPolicy>,
+    /// Restart dependencies, as a map from task name to the names of tasks it
+    /// depends on. Whenever jefe restarts one of those, it restarts this task
+    /// too.
+    #[serde(default)]
+    restart_with: BTreeMap<String, Vec<String>>,
+    /// If present, jefe keeps a snapshot of each task as of its most recent
+    /// fault, including this many bytes from the top of its stack.
+    #[serde(default)]
+    snapshot_stack_bytes: Option<usize>,
+    /// Hardware watchdog settings, if this application uses the watchdog.
+    #[serde(default)]
+    watchdog: Option<Watchdog>,
+}
+
+/// How to restart a task that keeps faulting.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartPolicy {
+    /// Number of restarts allowed within `window_ms` before escalating.
+    max_restarts: u32,
+    /// Length of the window over which restarts are counted, in milliseconds.
+    window_ms: u64,
+    /// Delay before the first restart in a window, in milliseconds. The delay
+    /// doubles with each further restart in the same window.
+    #[serde(default)]
+    backoff_ms: u64,
+    /// Upper limit on the back-off delay, in milliseconds. Defaults to
+    /// `window_ms`.
+    #[serde(default)]
+    max_backoff_ms: Option<u64>,
+    /// What to do once a task exceeds `max_restarts`.
+    escalate: Escalation,
+}
+
+/// Action to take when a task is crash-looping.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum Escalation {
+    /// Stop restarting the task, leaving it faulted.
+    Hold,
+    /// Reset the whole system.
+    Reset,
+}
+
+/// Hardware watchdog configuration.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Watchdog {
+    /// Whether to arm the watchdog at boot. Defaults to `true`; setting it to
+    /// `false` leaves the watchdog off until someone calls `enable_watchdog`.
+    #[serde(default = "default_true")]
+    enabled: bool,
+    /// Watchdog timeout, in milliseconds.
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u32,
+    /// Names of tasks that must be healthy for the watchdog to be kicked.
+    #[serde(default)]
+    critical: Vec<String>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_ms: default_timeout_ms(),
+            critical: vec![],
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timeout_ms() -> u32 {
+    2000
 }
 
 /// Description of something a task wants done on state change.
@@ -7,32 +7,58 @@
 //! The supervisor is responsible for:
 //!
 //! - Maintaining the system console output (currently via semihosting).
-//! - Monitoring tasks for failures and restarting them.
+//! - Monitoring tasks for failures and restarting them, along with any tasks
+//!   configured to restart with them.
+//! - Evacuating kernel log information, by handing out events from the
+//!   kernel's event log to whoever asks (e.g. a management network task).
+//! - Managing the hardware watchdog, which it kicks only while the tasks
+//!   marked critical in the application config are healthy (see the
+//!   `watchdog` module).
+//! - Reporting how deep each task's stack has gone, for sizing stacks.
+//! - Reporting the kernel's per-interrupt statistics, for spotting interrupt
+//!   storms.
 //!
 //! It will probably become responsible for:
 //!
-//! - Evacuating kernel log information.
 //! - Coordinating certain shared resources, such as the RCC and GPIO muxing.
-//! - Managing a watchdog timer.
 //!
 //! It's unwise for the supervisor to use `SEND`, ever, except to talk to the
 //! kernel. This is because a `SEND` to a misbehaving task could block forever,
-//! taking out the supervisor. The long-term idea is to provide some sort of
-//! asynchronous messaging from the supervisor to less-trusted tasks, but that
-//! doesn't exist yet, so we're mostly using RECV/REPLY and notifications. This
-//! means that hardware drivers required for this task must be built in instead
-//! of running in separate tasks.
+//! taking out the supervisor. The kernel's `SEND_ASYNC` would let us ask
+//! less-trusted tasks things without that risk, but nothing here needs to yet,
+//! so we're using RECV/REPLY and notifications. This means that hardware
+//! drivers required for this task must be built in instead of running in
+//! separate tasks.
 
 #![no_std]
 #![no_main]
 
 mod external;
+mod restart;
+mod snapshot;
+mod watchdog;
 
 use core::convert::Infallible;
 
 use hubris_num_tasks::NUM_TASKS;
-use task_jefe_api::ResetReason;
+use idol_runtime::{Leased, RequestError};
+use ringbuf::*;
+use snapshot::Snapshots;
+use task_jefe_api::{
+    FaultSnapshot, IrqError, ResetReason, RestartError, RestartStatus,
+    SnapshotError, StackError, WatchdogError,
+};
 use userlib::*;
+use watchdog::Watchdog;
+
+/// Restart policy escalations, by task index.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    Holding(usize),
+}
+
+ringbuf!(Trace, 4, Trace::None);
 
 fn log_fault(t: usize, fault: &abi::FaultInfo) {
     match fault {
@@ -81,8 +107,16 @@ fn log_fault(t: usize, fault: &abi::FaultInfo) {
         }
 
         abi::FaultInfo::Panic => {
-            sys_log!("Task #{} Panic!", t);
-            loop {}
+            let mut buf = [0; abi::PANIC_MESSAGE_MAX];
+            let msg = kipc::read_panic_message(t, &mut buf);
+            // The kernel truncates long messages, possibly in the middle of a
+            // character; print what we can.
+            let msg = match core::str::from_utf8(msg) {
+                Ok(s) => s,
+                Err(e) => core::str::from_utf8(&msg[..e.valid_up_to()])
+                    .unwrap_or_default(),
+            };
+            sys_log!("Task #{} Panic: {}", t, msg);
         }
 
         abi::FaultInfo::Injected(who) => {
@@ -125,6 +159,8 @@ fn main() -> ! {
         [Disposition::Restart; hubris_num_tasks::NUM_TASKS];
     let mut logged: [bool; hubris_num_tasks::NUM_TASKS] =
         [false; hubris_num_tasks::NUM_TASKS];
+    let mut restarts: [restart::History; hubris_num_tasks::NUM_TASKS] =
+        [restart::History::default(); hubris_num_tasks::NUM_TASKS];
     let deadline = sys_get_timer().now + TIMER_INTERVAL;
 
     sys_set_timer(Some(deadline), TIMER_MASK);
@@ -136,7 +172,13 @@ fn main() -> ! {
         deadline,
         disposition: &mut disposition,
         logged: &mut logged,
+        restarts: &mut restarts,
+        snapshots: Snapshots::claim(),
         reset_reason: ResetReason::Unknown,
+        watchdog: Watchdog::new(
+            generated::WATCHDOG_AT_BOOT,
+            generated::WATCHDOG_TIMEOUT_MS,
+        ),
     };
     let mut buf = [0u8; idl::INCOMING_SIZE];
 
@@ -149,8 +191,11 @@ struct ServerImpl<'s> {
     state: u32,
     disposition: &'s mut [Disposition; NUM_TASKS],
     logged: &'s mut [bool; NUM_TASKS],
+    restarts: &'s mut [restart::History; NUM_TASKS],
+    snapshots: Snapshots,
     deadline: u64,
     reset_reason: ResetReason,
+    watchdog: Watchdog,
 }
 
 impl idl::InOrderJefeImpl for ServerImpl<'_> {
@@ -179,6 +224,122 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         Ok(())
     }
 
+    fn read_kernel_event(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<Option<KernelEventRecord>, RequestError<Infallible>> {
+        Ok(kipc::read_kernel_event())
+    }
+
+    fn get_restart_status(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+    ) -> Result<RestartStatus, RequestError<RestartError>> {
+        let history = self
+            .restarts
+            .get(task as usize)
+            .ok_or(RestartError::BadTask)?;
+        Ok(history.status())
+    }
+
+    fn clear_restart_status(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+    ) -> Result<(), RequestError<RestartError>> {
+        let i = task as usize;
+        let history = self.restarts.get_mut(i).ok_or(RestartError::BadTask)?;
+        if history.clear() && self.disposition[i] == Disposition::Hold {
+            // We were holding this task because of its restart policy; give
+            // it another go.
+            self.disposition[i] = Disposition::Restart;
+            if let abi::TaskState::Faulted { .. } = kipc::read_task_status(i) {
+                self.restart(i);
+            }
+        }
+        Ok(())
+    }
+
+    fn get_fault_snapshot(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+    ) -> Result<FaultSnapshot, RequestError<SnapshotError>> {
+        Ok(*self.snapshots.get(task as usize)?)
+    }
+
+    fn read_fault_snapshot_stack(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+        data: Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<SnapshotError>> {
+        let stack = self.snapshots.stack(task as usize)?;
+        let len = stack.len().min(data.len());
+        data.write_range(0..len, &stack[..len])
+            .map_err(|_| RequestError::went_away())?;
+        Ok(len as u32)
+    }
+
+    fn read_fault_snapshot_panic_message(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+        data: Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<SnapshotError>> {
+        let message = self.snapshots.panic_message(task as usize)?;
+        let len = message.len().min(data.len());
+        data.write_range(0..len, &message[..len])
+            .map_err(|_| RequestError::went_away())?;
+        Ok(len as u32)
+    }
+
+    fn get_stack_usage(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+    ) -> Result<StackUsage, RequestError<StackError>> {
+        let i = task as usize;
+        if i >= NUM_TASKS {
+            return Err(StackError::BadTask.into());
+        }
+        Ok(kipc::read_stack_usage(i))
+    }
+
+    fn get_irq_stats(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        irq: u32,
+    ) -> Result<IrqStats, RequestError<IrqError>> {
+        kipc::read_irq_stats(irq).ok_or_else(|| IrqError::NoSuchIrq.into())
+    }
+
+    fn enable_watchdog(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<WatchdogError>> {
+        self.watchdog.enable()?;
+        Ok(())
+    }
+
+    fn disable_watchdog(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<WatchdogError>> {
+        self.watchdog.disable()?;
+        Ok(())
+    }
+
+    fn set_watchdog_timeout(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        timeout_ms: u32,
+    ) -> Result<(), RequestError<WatchdogError>> {
+        self.watchdog.set_timeout(timeout_ms)?;
+        Ok(())
+    }
+
     fn get_state(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -214,10 +375,23 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
         // Check to see if we have any external requests
         let changed = external::check(self.disposition);
 
-        // If our timer went off, we need to reestablish it
+        // If our periodic timer went off, we need to reestablish it. (The
+        // timer may also have gone off early, to end a restart back-off.)
+        let now = sys_get_timer().now;
+        let mut periodic = false;
         if bits & TIMER_MASK != 0 {
-            self.deadline += TIMER_INTERVAL;
-            sys_set_timer(Some(self.deadline), TIMER_MASK);
+            if now >= self.deadline {
+                self.deadline += TIMER_INTERVAL;
+                periodic = true;
+            }
+
+            for i in 0..NUM_TASKS {
+                if self.restarts[i].take_due(now)
+                    && self.disposition[i] == Disposition::Restart
+                {
+                    self.restart(i);
+                }
+            }
         }
 
         // If our disposition has changed or if we have been notified of
@@ -227,20 +401,21 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
                 match kipc::read_task_status(i) {
                     abi::TaskState::Faulted { fault, .. } => {
                         if !self.logged[i] {
+                            self.snapshots.capture(i, fault);
                             log_fault(i, &fault);
                             self.logged[i] = true;
                         }
 
-                        if self.disposition[i] == Disposition::Restart {
-                            // Stand it back up
-                            kipc::restart_task(i, true);
-                            self.logged[i] = false;
+                        if self.disposition[i] == Disposition::Restart
+                            && !self.restarts[i].is_backing_off()
+                        {
+                            self.handle_fault(i, now);
                         }
                     }
 
                     abi::TaskState::Healthy(abi::SchedState::Stopped) => {
                         if self.disposition[i] == Disposition::Start {
-                            kipc::restart_task(i, true);
+                            self.restart(i);
                         }
                     }
 
@@ -252,6 +427,80 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
                 }
             }
         }
+
+        // Wake up for whichever comes first: our next periodic check, or the
+        // end of a back-off.
+        let wake = self
+            .restarts
+            .iter()
+            .filter_map(restart::History::next_restart)
+            .fold(self.deadline, u64::min);
+        sys_set_timer(Some(wake), TIMER_MASK);
+
+        // Only consider kicking the watchdog once we've had a chance to
+        // restart anything that faulted.
+        if periodic {
+            self.watchdog.tick();
+        }
+    }
+}
+
+impl ServerImpl<'_> {
+    /// Applies task `i`'s restart policy to a fault noticed at time `now`.
+    fn handle_fault(&mut self, i: usize, now: u64) {
+        match self.restarts[i].on_fault(restart::policy(i), now) {
+            restart::Action::Restart => self.restart(i),
+            restart::Action::Wait => (),
+            restart::Action::Escalate(restart::Escalation::Hold) => {
+                sys_log!("Task #{} is crash-looping; holding", i);
+                ringbuf_entry!(Trace::Holding(i));
+                self.disposition[i] = Disposition::Hold;
+            }
+            restart::Action::Escalate(restart::Escalation::Reset) => {
+                sys_log!("Task #{} is crash-looping; resetting", i);
+                kipc::system_restart();
+            }
+        }
+    }
+
+    /// Restarts task `i`, along with any tasks configured to restart with it
+    /// (and any configured to restart with those, and so on). Every restart
+    /// jefe performs, whether after a fault or by request, goes through here.
+    fn restart(&mut self, i: usize) {
+        let mut pending = [false; NUM_TASKS];
+        let mut visited = [false; NUM_TASKS];
+        pending[i] = true;
+        visited[i] = true;
+
+        while let Some(j) = pending.iter().position(|&p| p) {
+            pending[j] = false;
+
+            // Stand it back up
+            kipc::restart_task(j, true);
+            self.logged[j] = false;
+            self.restarts[j].restarted();
+
+            for (dependency, dependent) in generated::RESTART_DEPENDENCIES {
+                let k = dependent as usize;
+                if dependency as usize != j || visited[k] {
+                    continue;
+                }
+                visited[k] = true;
+
+                // Only restart dependents that are up and running. One that
+                // has faulted will be handled like any other fault, and one
+                // that's stopped or held stays that way.
+                let running = !matches!(
+                    kipc::read_task_status(k),
+                    abi::TaskState::Faulted { .. }
+                        | abi::TaskState::Healthy(abi::SchedState::Stopped)
+                );
+                if running && self.disposition[k] == Disposition::Restart {
+                    sys_log!("Task #{} restarting with task #{}", k, j);
+                    pending[k] = true;
+                }
+            }
+        }
     }
 }
 
@@ -262,6 +511,10 @@ mod generated {
 
 // And the Idol bits
 mod idl {
-    use task_jefe_api::ResetReason;
+    use task_jefe_api::{
+        FaultSnapshot, IrqError, ResetReason, RestartError, RestartStatus,
+        SnapshotError, StackError, WatchdogError,
+    };
+    use userlib::{IrqStats, KernelEventRecord, StackUsage};
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -0,0 +1,157 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Restart policies, for tasks that fault over and over.
+//!
+//! By default, a faulted task with the `Restart` disposition is restarted
+//! immediately, every time. A task can instead be given a policy in the
+//! application config, which counts its faults within a window of time:
+//! successive restarts within the window are delayed by an exponentially
+//! growing back-off, and once the task has used up its restarts, we either
+//! hold it (leave it faulted) or reset the whole system.
+
+use task_jefe_api::RestartStatus;
+
+/// What to do with a task that has exceeded its restart limit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Escalation {
+    Hold,
+    Reset,
+}
+
+/// Restart policy for a single task. These are generated from the config by
+/// our build script.
+pub struct Policy {
+    pub max_restarts: u32,
+    pub window_ms: u64,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub escalate: Escalation,
+}
+
+/// Policy for tasks that don't have one configured: restart immediately,
+/// forever.
+const DEFAULT: Policy = Policy {
+    max_restarts: u32::MAX,
+    window_ms: 0,
+    backoff_ms: 0,
+    max_backoff_ms: 0,
+    escalate: Escalation::Hold,
+};
+
+/// Returns the restart policy for task index `i`.
+pub fn policy(i: usize) -> &'static Policy {
+    super::generated::RESTART_POLICIES
+        .iter()
+        .find(|(task, _)| *task as usize == i)
+        .map(|(_, policy)| policy)
+        .unwrap_or(&DEFAULT)
+}
+
+/// What the supervisor should do about a fault.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Restart the task now.
+    Restart,
+    /// Restart the task later, at the time `History::next_restart` reports.
+    Wait,
+    /// Give up on the task.
+    Escalate(Escalation),
+}
+
+/// Restart bookkeeping for a single task.
+#[derive(Copy, Clone, Default)]
+pub struct History {
+    restarts: u32,
+    window_start: u64,
+    recent_faults: u32,
+    restart_at: Option<u64>,
+    held: bool,
+}
+
+impl History {
+    /// Records a fault at time `now`, and decides what to do about it.
+    pub fn on_fault(&mut self, policy: &Policy, now: u64) -> Action {
+        if self.recent_faults == 0
+            || now.saturating_sub(self.window_start) >= policy.window_ms
+        {
+            self.window_start = now;
+            self.recent_faults = 0;
+        }
+        self.recent_faults = self.recent_faults.saturating_add(1);
+
+        if self.recent_faults > policy.max_restarts {
+            if policy.escalate == Escalation::Hold {
+                self.held = true;
+            }
+            return Action::Escalate(policy.escalate);
+        }
+
+        // The first restart in a window waits `backoff_ms`, and each one
+        // after that waits twice as long as the last.
+        let doublings = (self.recent_faults - 1).min(63);
+        let delay = policy
+            .backoff_ms
+            .checked_mul(1 << doublings)
+            .unwrap_or(u64::MAX)
+            .min(policy.max_backoff_ms);
+        if delay == 0 {
+            Action::Restart
+        } else {
+            self.restart_at = Some(now.saturating_add(delay));
+            Action::Wait
+        }
+    }
+
+    /// Returns the time at which this task is due to be restarted, if it's
+    /// waiting out a back-off.
+    pub fn next_restart(&self) -> Option<u64> {
+        self.restart_at
+    }
+
+    /// Checks whether a back-off has expired by time `now`, and if so, clears
+    /// it. Returns `true` if the task should be restarted.
+    pub fn take_due(&mut self, now: u64) -> bool {
+        match self.restart_at {
+            Some(t) if t <= now => {
+                self.restart_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks whether this task is waiting out a back-off, i.e. whether its
+    /// current fault has already been dealt with.
+    pub fn is_backing_off(&self) -> bool {
+        self.restart_at.is_some()
+    }
+
+    /// Records that the task has been restarted. (If it was being held,
+    /// someone has changed their mind about that.)
+    pub fn restarted(&mut self) {
+        self.restarts = self.restarts.saturating_add(1);
+        self.held = false;
+    }
+
+    /// Forgets about recent faults, returning whether the task was being
+    /// held. The lifetime restart count is kept.
+    pub fn clear(&mut self) -> bool {
+        let held = self.held;
+        *self = History {
+            restarts: self.restarts,
+            ..History::default()
+        };
+        held
+    }
+
+    pub fn status(&self) -> RestartStatus {
+        RestartStatus {
+            restarts: self.restarts,
+            recent_faults: self.recent_faults,
+            restart_at: self.restart_at,
+            held: self.held,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fault snapshots.
+//!
+//! Restarting a faulted task wipes out its registers and stack, which are
+//! exactly what you'd want for figuring out what went wrong. So, if the
+//! application asks for it, we copy them out of the kernel when we first
+//! notice a fault -- before restarting anything -- and keep the most recent
+//! snapshot of each task around until its next fault. For a panic, this
+//! includes the start of the panic message, which the kernel keeps for us.
+//!
+//! The snapshots live in the `FAULT_SNAPSHOTS` static, so Humility can find
+//! them in a dump as well as asking for them over IPC.
+
+use hubris_num_tasks::NUM_TASKS;
+use mutable_statics::mutable_statics;
+use task_jefe_api::{FaultSnapshot, SnapshotError};
+use userlib::*;
+
+use crate::generated::{FAULT_SNAPSHOTS, SNAPSHOT_STACK_BYTES};
+
+/// Number of snapshots we have room for: one per task, or none at all if
+/// snapshots are turned off.
+const SLOTS: usize = if FAULT_SNAPSHOTS { NUM_TASKS } else { 0 };
+
+pub struct Slot {
+    snapshot: Option<FaultSnapshot>,
+    stack: [u8; SNAPSHOT_STACK_BYTES],
+    panic_message: [u8; abi::PANIC_MESSAGE_MAX],
+}
+
+pub struct Snapshots {
+    slots: &'static mut [Slot; SLOTS],
+}
+
+impl Snapshots {
+    /// Claims the snapshot storage. This can only be called once.
+    pub fn claim() -> Self {
+        let slots = mutable_statics! {
+            static mut FAULT_SNAPSHOTS: [Slot; SLOTS] = [Slot {
+                snapshot: None,
+                stack: [0; SNAPSHOT_STACK_BYTES],
+                panic_message: [0; abi::PANIC_MESSAGE_MAX],
+            }; _];
+        };
+        Self { slots }
+    }
+
+    /// Records a snapshot of task `i`, which has faulted with `fault`.
+    pub fn capture(&mut self, i: usize, fault: abi::FaultInfo) {
+        let slot = match self.slots.get_mut(i) {
+            Some(slot) => slot,
+            None => return,
+        };
+        // If the task has stopped being faulted since we looked -- which
+        // shouldn't happen, since we're the ones who restart things -- the
+        // kernel will give us nothing, and we'll keep the old snapshot.
+        if let Some(registers) = kipc::read_task_registers(i) {
+            let stack_len = kipc::read_task_stack(i, &mut slot.stack);
+            let panic_message_len =
+                kipc::read_panic_message(i, &mut slot.panic_message).len();
+            slot.snapshot = Some(FaultSnapshot {
+                timestamp: sys_get_timer().now,
+                fault,
+                registers,
+                stack_len: stack_len as u32,
+                panic_message_len: panic_message_len as u32,
+            });
+        }
+    }
+
+    /// Returns the most recent snapshot of task `i`.
+    pub fn get(&self, i: usize) -> Result<&FaultSnapshot, SnapshotError> {
+        self.slot(i).map(|(_, snapshot)| snapshot)
+    }
+
+    /// Returns the stack bytes from the most recent snapshot of task `i`.
+    pub fn stack(&self, i: usize) -> Result<&[u8], SnapshotError> {
+        let (slot, snapshot) = self.slot(i)?;
+        Ok(&slot.stack[..snapshot.stack_len as usize])
+    }
+
+    /// Returns the panic message from the most recent snapshot of task `i`.
+    /// This is empty unless the task panicked.
+    pub fn panic_message(&self, i: usize) -> Result<&[u8], SnapshotError> {
+        let (slot, snapshot) = self.slot(i)?;
+        Ok(&slot.panic_message[..snapshot.panic_message_len as usize])
+    }
+
+    fn slot(&self, i: usize) -> Result<(&Slot, &FaultSnapshot), SnapshotError> {
+        if i >= NUM_TASKS {
+            return Err(SnapshotError::BadTask);
+        }
+        let slot = self.slots.get(i).ok_or(SnapshotError::NoSnapshot)?;
+        let snapshot =
+            slot.snapshot.as_ref().ok_or(SnapshotError::NoSnapshot)?;
+        Ok((slot, snapshot))
+    }
+}
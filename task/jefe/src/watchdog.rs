@@ -0,0 +1,264 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hardware watchdog management.
+//!
+//! While the watchdog is enabled, jefe kicks it from its periodic timer, but
+//! only if every task listed as critical in the application config is
+//! healthy. If a critical task is left faulted (or stopped), or jefe itself
+//! stops running, the watchdog expires and resets the system; the driver that
+//! reads the reset cause at the next boot reports it to us as
+//! `ResetReason::IndependentWatchdog` on the STM32H7, or
+//! `ResetReason::SystemWatchdog` on the LPC55 (whose WWDT is its only
+//! watchdog).
+//!
+//! We use the chip's independent watchdog: IWDG1 on the STM32H7, and the WWDT
+//! on the LPC55. Neither can be stopped once started, so "disabling" the
+//! watchdog really means that jefe goes back to kicking it unconditionally.
+//!
+//! The hardware is only touched from the timer handler (and when changing the
+//! timeout of a watchdog that's already running). Among other things, this
+//! gives the clock driver a chance to get going before we start the watchdog
+//! at boot.
+
+use task_jefe_api::WatchdogError;
+use userlib::*;
+
+/// Shortest timeout we'll accept, in milliseconds. We kick the watchdog from
+/// our periodic timer, so leave room for that to be late a couple of times.
+const MIN_TIMEOUT_MS: u32 = 3 * super::TIMER_INTERVAL as u32;
+
+pub struct Watchdog {
+    /// Whether the watchdog should be running and enforcing task health.
+    enabled: bool,
+    /// Whether we have started the hardware. Once set, this never clears.
+    running: bool,
+    timeout_ms: u32,
+    /// Whether we're currently withholding kicks, so that we only log the
+    /// transition.
+    starving: bool,
+}
+
+impl Watchdog {
+    pub fn new(enabled: bool, timeout_ms: u32) -> Self {
+        Self {
+            enabled,
+            running: false,
+            timeout_ms,
+            starving: false,
+        }
+    }
+
+    /// Requests that the watchdog be started (if it isn't already) and kicked
+    /// only while all critical tasks are healthy.
+    pub fn enable(&mut self) -> Result<(), WatchdogError> {
+        hw::check_timeout(self.timeout_ms)?;
+        self.enabled = true;
+        Ok(())
+    }
+
+    /// Stops making kicks conditional on task health.
+    pub fn disable(&mut self) -> Result<(), WatchdogError> {
+        if !hw::SUPPORTED {
+            return Err(WatchdogError::Unsupported);
+        }
+        self.enabled = false;
+        self.starving = false;
+        Ok(())
+    }
+
+    /// Changes the watchdog timeout, reprogramming the hardware if it's
+    /// already running.
+    pub fn set_timeout(
+        &mut self,
+        timeout_ms: u32,
+    ) -> Result<(), WatchdogError> {
+        if timeout_ms < MIN_TIMEOUT_MS {
+            return Err(WatchdogError::BadTimeout);
+        }
+        hw::check_timeout(timeout_ms)?;
+        if self.running {
+            hw::reconfigure(timeout_ms);
+        }
+        self.timeout_ms = timeout_ms;
+        Ok(())
+    }
+
+    /// Called from jefe's periodic timer. Starts the hardware if it's been
+    /// enabled, and kicks it if everything we care about is healthy.
+    pub fn tick(&mut self) {
+        if !self.running {
+            if !self.enabled {
+                return;
+            }
+            hw::start(self.timeout_ms);
+            self.running = true;
+        }
+
+        if self.enabled {
+            if let Some(task) = first_unhealthy_critical_task() {
+                if !self.starving {
+                    sys_log!("Task #{} unhealthy; starving watchdog", task);
+                    self.starving = true;
+                }
+                return;
+            }
+            if self.starving {
+                sys_log!("Critical tasks healthy; feeding watchdog");
+                self.starving = false;
+            }
+        }
+
+        hw::kick();
+    }
+}
+
+fn first_unhealthy_critical_task() -> Option<usize> {
+    super::generated::CRITICAL
+        .iter()
+        .map(|&task| task as usize)
+        .find(|&i| {
+            !matches!(
+                kipc::read_task_status(i),
+                abi::TaskState::Healthy(
+                    abi::SchedState::Runnable
+                        | abi::SchedState::InSend(_)
+                        | abi::SchedState::InReply(_)
+                        | abi::SchedState::InRecv(_)
+                )
+            )
+        })
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(feature = "h743", feature = "h753"))] {
+        mod hw {
+            #[cfg(feature = "h743")]
+            use stm32h7::stm32h743 as device;
+            #[cfg(feature = "h753")]
+            use stm32h7::stm32h753 as device;
+            use task_jefe_api::WatchdogError;
+
+            pub const SUPPORTED: bool = true;
+
+            /// The IWDG runs from the LSI oscillator, at nominally 32 kHz.
+            const LSI_KHZ: u32 = 32;
+
+            const KEY_RELOAD: u32 = 0xAAAA;
+            const KEY_UNLOCK: u32 = 0x5555;
+            const KEY_START: u32 = 0xCCCC;
+
+            fn iwdg() -> &'static device::iwdg::RegisterBlock {
+                unsafe { &*device::IWDG::ptr() }
+            }
+
+            /// Finds the smallest prescaler (as its register encoding, where
+            /// `n` divides by `4 << n`) for which the reload value fits in
+            /// the 12-bit reload register, and returns it with the reload
+            /// value.
+            fn divisors(timeout_ms: u32) -> Option<(u32, u32)> {
+                let ticks = timeout_ms.checked_mul(LSI_KHZ)?;
+                (0..=6).find_map(|pr| {
+                    let reload = ticks / (4 << pr);
+                    (1..=0x1000).contains(&reload).then(|| (pr, reload - 1))
+                })
+            }
+
+            pub fn check_timeout(timeout_ms: u32) -> Result<(), WatchdogError> {
+                divisors(timeout_ms)
+                    .map(|_| ())
+                    .ok_or(WatchdogError::BadTimeout)
+            }
+
+            pub fn start(timeout_ms: u32) {
+                // Starting the IWDG also starts the LSI, if it isn't running.
+                iwdg().kr.write(|w| unsafe { w.bits(KEY_START) });
+                reconfigure(timeout_ms);
+            }
+
+            pub fn reconfigure(timeout_ms: u32) {
+                // Our callers have checked this already.
+                let (pr, rl) = divisors(timeout_ms).unwrap_or((6, 0xfff));
+                let iwdg = iwdg();
+                iwdg.kr.write(|w| unsafe { w.bits(KEY_UNLOCK) });
+                iwdg.pr.write(|w| unsafe { w.bits(pr) });
+                iwdg.rlr.write(|w| unsafe { w.bits(rl) });
+                // The new values take a few LSI cycles to land, and the
+                // reload below would use the old ones if we didn't wait.
+                while iwdg.sr.read().bits() != 0 {}
+                kick();
+            }
+
+            pub fn kick() {
+                iwdg().kr.write(|w| unsafe { w.bits(KEY_RELOAD) });
+            }
+        }
+    } else if #[cfg(feature = "lpc55")] {
+        mod hw {
+            use lpc55_pac as device;
+            use task_jefe_api::WatchdogError;
+
+            pub const SUPPORTED: bool = true;
+
+            /// The WWDT runs from the 1 MHz FRO, through a fixed divide by
+            /// four. (The syscon driver turns on that clock, and the WWDT's
+            /// bus clock, at boot.)
+            const WDT_KHZ: u32 = 250;
+
+            fn wwdt() -> &'static device::wwdt::RegisterBlock {
+                unsafe { &*device::WWDT::ptr() }
+            }
+
+            fn ticks(timeout_ms: u32) -> Option<u32> {
+                timeout_ms
+                    .checked_mul(WDT_KHZ)
+                    .filter(|t| (0xff..=0xff_ffff).contains(t))
+            }
+
+            pub fn check_timeout(timeout_ms: u32) -> Result<(), WatchdogError> {
+                ticks(timeout_ms)
+                    .map(|_| ())
+                    .ok_or(WatchdogError::BadTimeout)
+            }
+
+            pub fn start(timeout_ms: u32) {
+                let wwdt = wwdt();
+                wwdt.mod_.modify(|_, w| w.wden().set_bit().wdreset().set_bit());
+                // The watchdog doesn't actually start counting until the
+                // first feed, which `reconfigure` does for us.
+                reconfigure(timeout_ms);
+            }
+
+            pub fn reconfigure(timeout_ms: u32) {
+                // Our callers have checked this already.
+                let ticks = ticks(timeout_ms).unwrap_or(0xff_ffff);
+                wwdt().tc.write(|w| unsafe { w.bits(ticks) });
+                // A new TC value only takes effect on a feed.
+                kick();
+            }
+
+            pub fn kick() {
+                let wwdt = wwdt();
+                wwdt.feed.write(|w| unsafe { w.bits(0xaa) });
+                wwdt.feed.write(|w| unsafe { w.bits(0x55) });
+            }
+        }
+    } else {
+        mod hw {
+            use task_jefe_api::WatchdogError;
+
+            pub const SUPPORTED: bool = false;
+
+            pub fn check_timeout(_: u32) -> Result<(), WatchdogError> {
+                Err(WatchdogError::Unsupported)
+            }
+
+            pub fn start(_: u32) {}
+
+            pub fn reconfigure(_: u32) {}
+
+            pub fn kick() {}
+        }
+    }
+}
@@ -17,6 +17,8 @@ use task_net_api::{
 use userlib::{sys_recv_closed, task_slot, TaskId, UnwrapLite};
 
 mod mgs_common;
+#[cfg(feature = "sensor-telemetry")]
+mod sensor_telemetry;
 
 // If the build system enables multiple of the gimlet/sidecar/psc features, this
 // sequence of `cfg_attr`s will trigger an unused_attributes warning. We can
@@ -34,6 +36,8 @@ task_slot!(JEFE, jefe);
 task_slot!(NET, net);
 task_slot!(SYS, sys);
 task_slot!(UPDATE_SERVER, update_server);
+#[cfg(feature = "sensor-telemetry")]
+task_slot!(SENSOR, sensor);
 
 #[allow(dead_code)] // Not all cases are used by all variants
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -52,6 +56,8 @@ enum Log {
     SerialConsoleSend { buffered: usize },
     UpdatePartial { bytes_written: usize },
     UpdateComplete,
+    TelemetryBadRequest,
+    TelemetryResponse { start: u32, count: u32 },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -96,6 +102,9 @@ const SOCKET: SocketName = SocketName::mgmt_gateway;
 fn main() {
     let mut mgs_handler = MgsHandler::claim_static_resources();
     let mut net_handler = NetHandler::claim_static_resources();
+    #[cfg(feature = "sensor-telemetry")]
+    let mut telemetry =
+        sensor_telemetry::TelemetryHandler::claim_static_resources();
 
     loop {
         let note = sys_recv_closed(
@@ -114,6 +123,11 @@ fn main() {
         if (note & NET_IRQ) != 0 || mgs_handler.wants_to_send_packet_to_mgs() {
             net_handler.run_until_blocked(&mut mgs_handler);
         }
+
+        #[cfg(feature = "sensor-telemetry")]
+        if (note & NET_IRQ) != 0 {
+            telemetry.run_until_blocked(&net_handler.net);
+        }
     }
 }
 
@@ -0,0 +1,214 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Sensor telemetry over the management network.
+//!
+//! The message set MGS speaks is defined by `gateway-messages`, which we don't
+//! control, so sensor readings are served by a small protocol of our own, on
+//! its own socket. All messages are `ssmarshal`-encoded.
+//!
+//! A request is a `Request`, naming the first sensor ID wanted. The response
+//! is a `ResponseHeader`, followed by `count` `Reading`s for consecutive
+//! sensor IDs starting at `start`: as many as fit in one packet. If `start +
+//! count` is less than `total`, the poller asks again from there to get the
+//! rest.
+
+use crate::{Log, __RINGBUF};
+use mutable_statics::mutable_statics;
+use ringbuf::ringbuf_entry;
+use serde::{Deserialize, Serialize};
+use task_net_api::{LargePayloadBehavior, Net, RecvError, SocketName};
+use task_sensor_api::{LastPost, Sensor, SensorError, SensorId};
+use userlib::{sys_get_timer, UnwrapLite};
+
+const SOCKET: SocketName = SocketName::mgmt_telemetry;
+
+/// Version of the telemetry protocol. Requests for any other version are
+/// ignored.
+const VERSION: u8 = 1;
+
+/// Size of our response buffer, which bounds how many readings fit in each
+/// response. A reading is 13 bytes encoded, so this holds the header and 77
+/// readings, enough for every sensor on a Gimlet in one go.
+const TX_BUF_SIZE: usize = 1024;
+
+/// Size of our request buffer. `ssmarshal` doesn't pad, so a `Request` encodes
+/// to no more than its in-memory size; anything bigger isn't one of ours.
+const RX_BUF_SIZE: usize = core::mem::size_of::<Request>();
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+struct Request {
+    version: u8,
+    /// ID of the first sensor to report.
+    start: u32,
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+struct ResponseHeader {
+    version: u8,
+    /// Current time on the SP, in milliseconds since boot, for working out
+    /// how old each reading is.
+    now: u64,
+    /// Number of sensors on the board.
+    total: u32,
+    /// ID of the first sensor reported.
+    start: u32,
+    /// Number of readings that follow.
+    count: u32,
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+struct Reading {
+    status: Status,
+    /// The value, if `status` is `Ok`; otherwise zero.
+    value: f32,
+    /// When the sensor's value (or lack of one) was last posted, in
+    /// milliseconds since boot; zero if it never has been.
+    timestamp: u64,
+}
+
+/// State of a sensor's most recent reading.
+#[derive(Copy, Clone, Debug, Serialize)]
+enum Status {
+    Ok,
+    /// Nothing has been posted for the sensor yet.
+    Absent,
+    /// The device is powered off.
+    DeviceOff,
+    /// The device reported an error.
+    DeviceError,
+    /// The device isn't there.
+    NotPresent,
+    /// The device can't be reached right now.
+    DeviceUnavailable,
+    /// The device didn't respond in time.
+    DeviceTimeout,
+}
+
+impl From<SensorError> for Status {
+    fn from(err: SensorError) -> Self {
+        match err {
+            SensorError::InvalidSensor | SensorError::NoReading => {
+                Status::Absent
+            }
+            SensorError::DeviceOff => Status::DeviceOff,
+            SensorError::DeviceError => Status::DeviceError,
+            SensorError::NotPresent => Status::NotPresent,
+            SensorError::DeviceUnavailable => Status::DeviceUnavailable,
+            SensorError::DeviceTimeout => Status::DeviceTimeout,
+        }
+    }
+}
+
+pub(crate) struct TelemetryHandler {
+    sensor: Sensor,
+    tx_buf: &'static mut [u8; TX_BUF_SIZE],
+    rx_buf: &'static mut [u8; RX_BUF_SIZE],
+}
+
+impl TelemetryHandler {
+    /// Instantiate a `TelemetryHandler` that claims static buffers. Can only be
+    /// called once; will panic if called multiple times!
+    pub(crate) fn claim_static_resources() -> Self {
+        let (tx_buf, rx_buf) = mutable_statics! {
+            static mut TELEMETRY_TX_BUF: [u8; TX_BUF_SIZE] = [0; _];
+            static mut TELEMETRY_RX_BUF: [u8; RX_BUF_SIZE] = [0; _];
+        };
+        Self {
+            sensor: Sensor::from(crate::SENSOR.get_task_id()),
+            tx_buf,
+            rx_buf,
+        }
+    }
+
+    /// Answers every request waiting on our socket.
+    pub(crate) fn run_until_blocked(&mut self, net: &Net) {
+        loop {
+            let mut meta = match net.recv_packet(
+                SOCKET,
+                LargePayloadBehavior::Discard,
+                self.rx_buf,
+            ) {
+                Ok(meta) => meta,
+                Err(RecvError::QueueEmpty) => return,
+                Err(RecvError::NotYours | RecvError::Other) => panic!(),
+            };
+
+            let request = match ssmarshal::deserialize::<Request>(
+                &self.rx_buf[..meta.size as usize],
+            ) {
+                Ok((request, _)) if request.version == VERSION => request,
+                _ => {
+                    ringbuf_entry!(Log::TelemetryBadRequest);
+                    continue;
+                }
+            };
+
+            meta.size = self.respond(request) as u32;
+
+            // Telemetry is polled, so if there's no room to send this, the
+            // poller will just ask again.
+            if let Err(err) = net.send_packet(
+                SOCKET,
+                meta,
+                &self.tx_buf[..meta.size as usize],
+            ) {
+                ringbuf_entry!(Log::SendError(err));
+            }
+        }
+    }
+
+    /// Builds the response to `request` in `tx_buf`, returning its length.
+    fn respond(&mut self, request: Request) -> usize {
+        let mut header = ResponseHeader {
+            version: VERSION,
+            now: sys_get_timer().now,
+            total: self.sensor.get_count(),
+            start: request.start,
+            count: 0,
+        };
+
+        // The header is the same size whatever it says, so write the readings
+        // after a placeholder, and fill in the count at the end.
+        let mut len =
+            ssmarshal::serialize(&mut self.tx_buf[..], &header).unwrap_lite();
+        for i in request.start..header.total {
+            let reading = self.reading(SensorId(i as usize));
+            match ssmarshal::serialize(&mut self.tx_buf[len..], &reading) {
+                Ok(n) => len += n,
+                Err(_) => break,
+            }
+            header.count += 1;
+        }
+        ringbuf_entry!(Log::TelemetryResponse {
+            start: header.start,
+            count: header.count,
+        });
+
+        ssmarshal::serialize(&mut self.tx_buf[..], &header).unwrap_lite();
+        len
+    }
+
+    fn reading(&self, id: SensorId) -> Reading {
+        let post =
+            self.sensor
+                .get_last_post(id)
+                .unwrap_or_else(|err| LastPost {
+                    value: Err(err),
+                    timestamp: 0,
+                });
+        match post.value {
+            Ok(value) => Reading {
+                status: Status::Ok,
+                value,
+                timestamp: post.timestamp,
+            },
+            Err(err) => Reading {
+                status: err.into(),
+                value: 0.0,
+                timestamp: post.timestamp,
+            },
+        }
+    }
+}
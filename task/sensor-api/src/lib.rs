@@ -8,8 +8,12 @@
 
 use derive_idol_err::IdolError;
 use drv_i2c_api::ResponseCode;
+use serde::{Deserialize, Serialize};
 use userlib::*;
 
+/// Number of recent values the sensor task keeps for each sensor.
+pub const HISTORY_LEN: usize = 4;
+
 #[derive(zerocopy::AsBytes, Copy, Clone, Debug, PartialEq)]
 #[repr(C)]
 pub struct SensorId(pub usize);
@@ -33,6 +37,134 @@ pub enum Reading {
     NoData(NoData),
 }
 
+/// A value posted for a sensor, and when it was posted, in the kernel time
+/// returned by `sys_get_timer`. Subtract `timestamp` from the current time to
+/// find out how stale the value is.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+/// The value or error most recently posted for a sensor, and when it was
+/// posted, in the kernel time returned by `sys_get_timer`. If nothing has been
+/// posted, `value` is `Err(SensorError::NoReading)` and `timestamp` is zero.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LastPost {
+    pub value: Result<f32, SensorError>,
+    pub timestamp: u64,
+}
+
+/// Extremes of the values posted for a sensor.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MinMax {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// What a sensor measures.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensorKind {
+    Temperature,
+    Power,
+    Current,
+    Voltage,
+    Speed,
+}
+
+impl SensorKind {
+    /// Returns the units that values of this kind are posted in.
+    pub fn units(&self) -> &'static str {
+        match self {
+            SensorKind::Temperature => "degrees C",
+            SensorKind::Power => "W",
+            SensorKind::Current => "A",
+            SensorKind::Voltage => "V",
+            SensorKind::Speed => "RPM",
+        }
+    }
+}
+
+/// Description of a sensor, generated from the application's I2C config.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorInfo {
+    pub kind: SensorKind,
+    /// The sensor's name: the rail it measures, its entry in the device's
+    /// sensor `names`, or failing those, the device's name.
+    pub name: Option<&'static str>,
+    /// The part name of the device the sensor is on.
+    pub device: &'static str,
+    /// The reference designator of that device.
+    pub refdes: Option<&'static str>,
+}
+
+/// Description of a sensor, as returned by the `describe` operation. The
+/// strings in `SensorInfo` are written into leases; these are their full
+/// lengths, which may be more than the leases could hold.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SensorDescription {
+    pub kind: SensorKind,
+    pub name_len: u32,
+    pub device_len: u32,
+    pub refdes_len: u32,
+}
+
+/// Alarm thresholds for a sensor, from the `thresholds` in its device's
+/// `sensors` config.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Thresholds {
+    pub critical_low: Option<f32>,
+    pub warning_low: Option<f32>,
+    pub warning_high: Option<f32>,
+    pub critical_high: Option<f32>,
+}
+
+impl Thresholds {
+    /// Returns the most severe threshold that `value` is at or beyond, if
+    /// any.
+    pub fn check(&self, value: f32) -> Option<Threshold> {
+        let beyond = |t: Option<f32>, high: bool| match t {
+            Some(t) if high => value >= t,
+            Some(t) => value <= t,
+            None => false,
+        };
+
+        if beyond(self.critical_high, true) {
+            Some(Threshold::CriticalHigh)
+        } else if beyond(self.critical_low, false) {
+            Some(Threshold::CriticalLow)
+        } else if beyond(self.warning_high, true) {
+            Some(Threshold::WarningHigh)
+        } else if beyond(self.warning_low, false) {
+            Some(Threshold::WarningLow)
+        } else {
+            None
+        }
+    }
+}
+
+/// One of the thresholds in `Thresholds`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Threshold {
+    CriticalLow,
+    WarningLow,
+    WarningHigh,
+    CriticalHigh,
+}
+
+/// A sensor whose most recent value is at or beyond one of its thresholds.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Alarm {
+    pub id: u32,
+    /// The most severe threshold crossed.
+    pub threshold: Threshold,
+    /// The most recent value.
+    pub value: f32,
+    /// When the sensor crossed into this alarm, in the kernel time returned by
+    /// `sys_get_timer`.
+    pub since: u64,
+}
+
 #[derive(zerocopy::AsBytes, Copy, Clone, Debug, FromPrimitive, PartialEq)]
 #[repr(u8)]
 pub enum NoData {
@@ -56,7 +188,16 @@ impl From<ResponseCode> for NoData {
     }
 }
 
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    FromPrimitive,
+    PartialEq,
+    IdolError,
+    Serialize,
+    Deserialize,
+)]
 pub enum SensorError {
     InvalidSensor = 1,
     NoReading = 2,
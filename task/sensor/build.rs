@@ -2,6 +2,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
     build_i2c::codegen(build_i2c::Disposition::Sensors)?;
@@ -12,5 +16,41 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         idol::server::ServerStyle::InOrder,
     )?;
 
+    let cfg = build_util::task_maybe_config::<Config>()?.unwrap_or_default();
+
+    let out_dir = std::env::var("OUT_DIR")?;
+    let dest_path = std::path::Path::new(&out_dir).join("sensor_config.rs");
+    let mut out = std::fs::File::create(&dest_path)?;
+
+    let task = "hubris_num_tasks::Task";
+    writeln!(
+        out,
+        "pub(crate) const ALARM_SUBSCRIBERS: [({}, u32); {}] = [",
+        task,
+        cfg.on_alarm.len()
+    )?;
+    for (name, rec) in cfg.on_alarm {
+        writeln!(out, "    ({}::{}, 1 << {}),", task, name, rec.bit_number)?;
+    }
+    writeln!(out, "];")?;
+
     Ok(())
 }
+
+/// Sensor task-level configuration.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    /// Tasks to be notified when a sensor's alarm state changes, as a map
+    /// from task name to `Subscriber` record.
+    #[serde(default)]
+    on_alarm: BTreeMap<String, Subscriber>,
+}
+
+/// Description of how to notify a task of alarms.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Subscriber {
+    /// Number of notification bit to signal (_not_ mask).
+    bit_number: u8,
+}
@@ -3,12 +3,28 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Sensor management
+//!
+//! Tasks that read sensors post their values (or the reasons they don't have
+//! one) here, and anyone can read them back. Along with the most recent
+//! reading, we keep when it was posted, so that readers can tell how fresh it
+//! is, and for each sensor, the extremes and last few values posted since its
+//! history was last cleared. We can also describe each sensor -- what it
+//! measures, its name, and the device it's on -- from the tables generated
+//! from the I2C config, so that clients don't need a copy of that config.
+//!
+//! Sensors can also have alarm thresholds, configured with their devices. We
+//! check each value posted against them, and notify the tasks in our config
+//! whenever a sensor enters, changes, or leaves an alarm.
 
 #![no_std]
 #![no_main]
 
-use idol_runtime::{NotificationHandler, RequestError};
-use task_sensor_api::{NoData, Reading, SensorError, SensorId};
+use idol_runtime::{Leased, NotificationHandler, RequestError, W};
+use mutable_statics::mutable_statics;
+use task_sensor_api::{
+    Alarm, LastPost, MinMax, NoData, Reading, Sample, SensorDescription,
+    SensorError, SensorId, Threshold, Thresholds, HISTORY_LEN,
+};
 use userlib::*;
 
 // This is only included to determine the number of sensors
@@ -17,11 +33,170 @@ include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 use i2c_config::sensors;
 use sensors::NUM_SENSORS;
 
+/// Everything we know about a single sensor.
+#[derive(Copy, Clone)]
+struct SensorData {
+    /// The most recent value or error posted.
+    last: Reading,
+    /// When `last` was posted.
+    last_time: u64,
+    /// Extremes of the values posted since the history was cleared, if any
+    /// have been.
+    min_max: Option<MinMax>,
+    /// Recent values, as a ring: `history[next]` is the oldest once the ring
+    /// has filled.
+    history: [Sample; HISTORY_LEN],
+    /// Number of valid entries in `history`.
+    len: usize,
+    /// Where the next value goes in `history`.
+    next: usize,
+    /// The alarm this sensor is in, if it's crossed one of its thresholds.
+    alarm: Option<Alarm>,
+}
+
+impl SensorData {
+    const EMPTY: Self = Self {
+        last: Reading::Absent,
+        last_time: 0,
+        min_max: None,
+        history: [Sample {
+            value: 0.0,
+            timestamp: 0,
+        }; HISTORY_LEN],
+        len: 0,
+        next: 0,
+        alarm: None,
+    };
+
+    fn post(&mut self, value: f32, now: u64) {
+        self.last = Reading::Value(value);
+        self.last_time = now;
+
+        self.min_max = Some(match self.min_max {
+            Some(m) => MinMax {
+                min: m.min.min(value),
+                max: m.max.max(value),
+            },
+            None => MinMax {
+                min: value,
+                max: value,
+            },
+        });
+
+        self.history[self.next] = Sample {
+            value,
+            timestamp: now,
+        };
+        self.next = (self.next + 1) % HISTORY_LEN;
+        self.len = (self.len + 1).min(HISTORY_LEN);
+    }
+
+    /// Updates the alarm state of sensor `id`, given that `value` was posted
+    /// at time `now`, and is at or beyond `threshold`. Returns `true` if the
+    /// sensor has entered, changed, or left an alarm.
+    fn update_alarm(
+        &mut self,
+        id: SensorId,
+        threshold: Option<Threshold>,
+        value: f32,
+        now: u64,
+    ) -> bool {
+        match (&mut self.alarm, threshold) {
+            (Some(alarm), Some(t)) if alarm.threshold == t => {
+                alarm.value = value;
+                false
+            }
+            (None, None) => false,
+            (_, threshold) => {
+                self.alarm = threshold.map(|threshold| Alarm {
+                    id: id.0 as u32,
+                    threshold,
+                    value,
+                    since: now,
+                });
+                true
+            }
+        }
+    }
+
+    fn nodata(&mut self, nodata: NoData, now: u64) {
+        self.last = Reading::NoData(nodata);
+        self.last_time = now;
+    }
+
+    /// Returns the `index`th most recent value, counting from 0.
+    fn history(&self, index: usize) -> Option<Sample> {
+        if index < self.len {
+            let i = (self.next + HISTORY_LEN - 1 - index) % HISTORY_LEN;
+            Some(self.history[i])
+        } else {
+            None
+        }
+    }
+
+    fn clear_history(&mut self) {
+        self.min_max = None;
+        self.len = 0;
+        self.next = 0;
+    }
+}
+
 struct ServerImpl {
-    data: [Reading; NUM_SENSORS],
+    data: &'static mut [SensorData; NUM_SENSORS],
     deadline: u64,
 }
 
+impl ServerImpl {
+    fn sensor(&mut self, id: SensorId) -> Result<&mut SensorData, SensorError> {
+        self.data.get_mut(id.0).ok_or(SensorError::InvalidSensor)
+    }
+
+    /// Returns the most recent value posted for sensor `id`, or the error
+    /// that describes why there isn't one.
+    fn reading(&mut self, id: SensorId) -> Result<Sample, SensorError> {
+        let sensor = self.sensor(id)?;
+        match sensor.last {
+            Reading::Absent => Err(SensorError::NoReading),
+            Reading::NoData(nodata) => Err(nodata.into()),
+            Reading::Value(value) => Ok(Sample {
+                value,
+                timestamp: sensor.last_time,
+            }),
+        }
+    }
+}
+
+/// Writes as much of `s` as fits into `lease`, returning the full length of
+/// `s`.
+fn write_str(
+    lease: &Leased<W, [u8]>,
+    s: &str,
+) -> Result<u32, RequestError<SensorError>> {
+    let len = s.len().min(lease.len());
+    lease
+        .write_range(0..len, &s.as_bytes()[..len])
+        .map_err(|_| RequestError::went_away())?;
+    Ok(s.len() as u32)
+}
+
+/// Returns the alarm thresholds configured for sensor `id`, if any.
+fn thresholds(id: SensorId) -> Option<&'static Thresholds> {
+    sensors::THRESHOLDS
+        .iter()
+        .find(|(sensor, _)| *sensor == id)
+        .map(|(_, thresholds)| thresholds)
+}
+
+/// Lets the tasks in our config know that some sensor's alarm state has
+/// changed. It's up to them to find out which, with `next_alarm`.
+fn notify_subscribers() {
+    for (task, mask) in generated::ALARM_SUBSCRIBERS {
+        let taskid = TaskId::for_index_and_gen(task as usize, Generation::ZERO);
+        let taskid = sys_refresh_task_id(taskid);
+        sys_post(taskid, mask);
+    }
+}
+
 const TIMER_MASK: u32 = 1 << 0;
 const TIMER_INTERVAL: u64 = 1000;
 
@@ -31,20 +206,7 @@ impl idl::InOrderSensorImpl for ServerImpl {
         _: &RecvMessage,
         id: SensorId,
     ) -> Result<f32, RequestError<SensorError>> {
-        let index = id.0;
-
-        if index < NUM_SENSORS {
-            match self.data[index] {
-                Reading::Absent => Err(SensorError::NoReading.into()),
-                Reading::NoData(nodata) => {
-                    let err: SensorError = nodata.into();
-                    Err(err.into())
-                }
-                Reading::Value(reading) => Ok(reading),
-            }
-        } else {
-            Err(SensorError::InvalidSensor.into())
-        }
+        Ok(self.reading(id)?.value)
     }
 
     fn post(
@@ -53,14 +215,15 @@ impl idl::InOrderSensorImpl for ServerImpl {
         id: SensorId,
         value: f32,
     ) -> Result<(), RequestError<SensorError>> {
-        let index = id.0;
+        let now = sys_get_timer().now;
+        let sensor = self.sensor(id)?;
+        sensor.post(value, now);
 
-        if index < NUM_SENSORS {
-            self.data[index] = Reading::Value(value);
-            Ok(())
-        } else {
-            Err(SensorError::InvalidSensor.into())
+        let threshold = thresholds(id).and_then(|t| t.check(value));
+        if sensor.update_alarm(id, threshold, value, now) {
+            notify_subscribers();
         }
+        Ok(())
     }
 
     fn nodata(
@@ -69,15 +232,113 @@ impl idl::InOrderSensorImpl for ServerImpl {
         id: SensorId,
         nodata: NoData,
     ) -> Result<(), RequestError<SensorError>> {
-        let index = id.0;
+        let now = sys_get_timer().now;
+        self.sensor(id)?.nodata(nodata, now);
+        Ok(())
+    }
 
-        if index < NUM_SENSORS {
-            self.data[index] = Reading::NoData(nodata);
-            Ok(())
-        } else {
-            Err(SensorError::InvalidSensor.into())
+    fn get_reading(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<Sample, RequestError<SensorError>> {
+        Ok(self.reading(id)?)
+    }
+
+    fn get_last_update(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<u64, RequestError<SensorError>> {
+        let sensor = self.sensor(id)?;
+        match sensor.last {
+            Reading::Absent => Err(SensorError::NoReading.into()),
+            _ => Ok(sensor.last_time),
         }
     }
+
+    fn get_last_post(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<LastPost, RequestError<SensorError>> {
+        let sensor = self.sensor(id)?;
+        let value = match sensor.last {
+            Reading::Absent => Err(SensorError::NoReading),
+            Reading::NoData(nodata) => Err(nodata.into()),
+            Reading::Value(value) => Ok(value),
+        };
+        Ok(LastPost {
+            value,
+            timestamp: sensor.last_time,
+        })
+    }
+
+    fn get_min_max(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<MinMax, RequestError<SensorError>> {
+        let min_max = self.sensor(id)?.min_max;
+        min_max.ok_or_else(|| SensorError::NoReading.into())
+    }
+
+    fn get_history(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        index: u32,
+    ) -> Result<Sample, RequestError<SensorError>> {
+        let sample = self.sensor(id)?.history(index as usize);
+        sample.ok_or_else(|| SensorError::NoReading.into())
+    }
+
+    fn clear_history(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<(), RequestError<SensorError>> {
+        self.sensor(id)?.clear_history();
+        Ok(())
+    }
+
+    fn get_count(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<core::convert::Infallible>> {
+        Ok(NUM_SENSORS as u32)
+    }
+
+    fn describe(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        name: Leased<W, [u8]>,
+        device: Leased<W, [u8]>,
+        refdes: Leased<W, [u8]>,
+    ) -> Result<SensorDescription, RequestError<SensorError>> {
+        let info = sensors::SENSOR_INFO
+            .get(id.0)
+            .ok_or(SensorError::InvalidSensor)?;
+        Ok(SensorDescription {
+            kind: info.kind,
+            name_len: write_str(&name, info.name.unwrap_or(""))?,
+            device_len: write_str(&device, info.device)?,
+            refdes_len: write_str(&refdes, info.refdes.unwrap_or(""))?,
+        })
+    }
+
+    fn next_alarm(
+        &mut self,
+        _: &RecvMessage,
+        start: u32,
+    ) -> Result<Option<Alarm>, RequestError<core::convert::Infallible>> {
+        Ok(self
+            .data
+            .iter()
+            .skip(start as usize)
+            .find_map(|sensor| sensor.alarm))
+    }
 }
 
 impl NotificationHandler for ServerImpl {
@@ -100,11 +361,14 @@ fn main() -> ! {
     //
     sys_set_timer(Some(deadline), TIMER_MASK);
 
-    let mut server = ServerImpl {
-        data: [Reading::Absent; NUM_SENSORS],
-        deadline,
+    // The sensor table is too big to keep on the stack.
+    let data = mutable_statics! {
+        static mut SENSOR_DATA: [SensorData; NUM_SENSORS] =
+            [SensorData::EMPTY; _];
     };
 
+    let mut server = ServerImpl { data, deadline };
+
     let mut buffer = [0; idl::INCOMING_SIZE];
 
     loop {
@@ -112,8 +376,16 @@ fn main() -> ! {
     }
 }
 
+// Place to namespace all the bits generated by our config processor.
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/sensor_config.rs"));
+}
+
 mod idl {
-    use super::{NoData, SensorError, SensorId};
+    use super::{
+        Alarm, LastPost, MinMax, NoData, Sample, SensorDescription,
+        SensorError, SensorId,
+    };
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -14,6 +14,11 @@ const _: () = {
 };
 
 pub(crate) trait BspT {
+    /// Tuning for the fan control loop. The controller's input is degrees C
+    /// above target and its output is fan PWM duty cycle (0-100), updated
+    /// once per control period.
+    const PID_CONFIG: pid::PidConfig;
+
     fn new(i2c_task: userlib::TaskId) -> Self;
 
     /// Sensors which are monitored as part of the control loop
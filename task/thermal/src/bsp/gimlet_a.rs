@@ -18,6 +18,7 @@ use drv_i2c_devices::sbtsi::*;
 use drv_i2c_devices::tmp117::*;
 use drv_i2c_devices::tmp451::*;
 use drv_i2c_devices::tse2004av::*;
+use pid::PidConfig;
 use task_sensor_api::SensorId;
 use userlib::{task_slot, units::Celsius, TaskId};
 
@@ -53,6 +54,16 @@ const POWER_STATE_A2: u32 = 0b001;
 const POWER_STATE_A0: u32 = 0b010;
 
 impl BspT for Bsp {
+    // Same tuning as rev B
+    const PID_CONFIG: PidConfig = PidConfig {
+        gain_p: 8.0,
+        gain_i: 1.0,
+        gain_d: 10.0,
+        min_output: 0.0,
+        max_output: 100.0,
+        max_slew: 20.0,
+    };
+
     fn inputs(&self) -> &[InputChannel] {
         &self.inputs
     }
@@ -110,6 +121,11 @@ impl BspT for Bsp {
         const MAX_CPU_TEMP: Celsius = Celsius(55f32);
         const MAX_T6_TEMP: Celsius = Celsius(55f32);
 
+        // How far below their max temperatures we aim to keep each part
+        const DIMM_TARGET_MARGIN: Celsius = Celsius(2f32);
+        const CPU_TARGET_MARGIN: Celsius = Celsius(2f32);
+        const T6_TARGET_MARGIN: Celsius = Celsius(2f32);
+
         Self {
             seq,
             fans,
@@ -122,6 +138,7 @@ impl BspT for Bsp {
                         sensors::SBTSI_TEMPERATURE_SENSOR,
                     ),
                     MAX_CPU_TEMP,
+                    CPU_TARGET_MARGIN,
                     POWER_STATE_A0,
                     false,
                 ),
@@ -134,6 +151,7 @@ impl BspT for Bsp {
                         sensors::TMP451_TEMPERATURE_SENSOR,
                     ),
                     MAX_T6_TEMP,
+                    T6_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2, // <- different from rev B
                     false,
                 ),
@@ -145,6 +163,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[0],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -156,6 +175,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[1],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -167,6 +187,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[2],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -178,6 +199,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[3],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -189,6 +211,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[4],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -200,6 +223,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[5],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -211,6 +235,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[6],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -222,6 +247,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[7],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -233,6 +259,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[8],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -244,6 +271,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[9],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -255,6 +283,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[10],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -266,6 +295,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[11],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -277,6 +307,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[12],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -288,6 +319,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[13],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -299,6 +331,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[14],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -310,6 +343,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[15],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
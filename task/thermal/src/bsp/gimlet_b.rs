@@ -18,6 +18,7 @@ use drv_i2c_devices::sbtsi::*;
 use drv_i2c_devices::tmp117::*;
 use drv_i2c_devices::tmp451::*;
 use drv_i2c_devices::tse2004av::*;
+use pid::PidConfig;
 use task_sensor_api::SensorId;
 use userlib::{task_slot, units::Celsius, TaskId};
 
@@ -53,6 +54,17 @@ const POWER_STATE_A2: u32 = 0b001;
 const POWER_STATE_A0: u32 = 0b010;
 
 impl BspT for Bsp {
+    // Tuned against the simulated plant in the `pid` crate's tests, which
+    // has the same control period as we do.
+    const PID_CONFIG: PidConfig = PidConfig {
+        gain_p: 8.0,
+        gain_i: 1.0,
+        gain_d: 10.0,
+        min_output: 0.0,
+        max_output: 100.0,
+        max_slew: 20.0,
+    };
+
     fn inputs(&self) -> &[InputChannel] {
         &self.inputs
     }
@@ -111,6 +123,11 @@ impl BspT for Bsp {
         const MAX_CPU_TEMP: Celsius = Celsius(60f32);
         const MAX_T6_TEMP: Celsius = Celsius(60f32);
 
+        // How far below their max temperatures we aim to keep each part
+        const DIMM_TARGET_MARGIN: Celsius = Celsius(2f32);
+        const CPU_TARGET_MARGIN: Celsius = Celsius(2f32);
+        const T6_TARGET_MARGIN: Celsius = Celsius(2f32);
+
         Self {
             seq,
             fans,
@@ -123,6 +140,7 @@ impl BspT for Bsp {
                         sensors::SBTSI_TEMPERATURE_SENSOR,
                     ),
                     MAX_CPU_TEMP,
+                    CPU_TARGET_MARGIN,
                     POWER_STATE_A0,
                     false,
                 ),
@@ -135,6 +153,7 @@ impl BspT for Bsp {
                         sensors::TMP451_TEMPERATURE_SENSOR,
                     ),
                     MAX_T6_TEMP,
+                    T6_TARGET_MARGIN,
                     POWER_STATE_A0, // <-- this is different from rev A
                     false,
                 ),
@@ -146,6 +165,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[0],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -157,6 +177,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[1],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -168,6 +189,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[2],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -179,6 +201,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[3],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -190,6 +213,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[4],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -201,6 +225,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[5],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -212,6 +237,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[6],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -223,6 +249,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[7],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -234,6 +261,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[8],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -245,6 +273,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[9],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -256,6 +285,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[10],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -267,6 +297,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[11],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -278,6 +309,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[12],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -289,6 +321,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[13],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -300,6 +333,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[14],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
@@ -311,6 +345,7 @@ impl BspT for Bsp {
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[15],
                     ),
                     MAX_DIMM_TEMP,
+                    DIMM_TARGET_MARGIN,
                     POWER_STATE_A0 | POWER_STATE_A2,
                     true,
                 ),
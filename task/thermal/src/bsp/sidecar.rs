@@ -13,6 +13,7 @@ use drv_i2c_devices::max31790::Max31790;
 use drv_i2c_devices::tmp117::*;
 use drv_i2c_devices::tmp451::*;
 use drv_sidecar_seq_api::Sequencer;
+use pid::PidConfig;
 use task_sensor_api::SensorId;
 use userlib::{task_slot, units::Celsius, TaskId};
 
@@ -44,6 +45,16 @@ pub(crate) struct Bsp {
 }
 
 impl BspT for Bsp {
+    // Borrowed from Gimlet until Sidecar's thermals have been characterized
+    const PID_CONFIG: PidConfig = PidConfig {
+        gain_p: 8.0,
+        gain_i: 1.0,
+        gain_d: 10.0,
+        min_output: 0.0,
+        max_output: 100.0,
+        max_slew: 20.0,
+    };
+
     fn inputs(&self) -> &[InputChannel] {
         &self.inputs
     }
@@ -127,6 +138,10 @@ impl BspT for Bsp {
         const MAX_TF2_TEMP: Celsius = Celsius(60f32);
         const MAX_VSC7448_TEMP: Celsius = Celsius(60f32);
 
+        // How far below their max temperatures we aim to keep each part
+        const TF2_TARGET_MARGIN: Celsius = Celsius(2f32);
+        const VSC7448_TARGET_MARGIN: Celsius = Celsius(2f32);
+
         Self {
             seq,
             fans,
@@ -143,6 +158,7 @@ impl BspT for Bsp {
                         sensors::TMP451_TF2_TEMPERATURE_SENSOR,
                     ),
                     MAX_TF2_TEMP,
+                    TF2_TARGET_MARGIN,
                     0,
                     false,
                 ),
@@ -155,6 +171,7 @@ impl BspT for Bsp {
                         sensors::TMP451_VSC7448_TEMPERATURE_SENSOR,
                     ),
                     MAX_VSC7448_TEMP,
+                    VSC7448_TARGET_MARGIN,
                     0,
                     false,
                 ),
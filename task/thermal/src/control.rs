@@ -9,6 +9,7 @@ use drv_i2c_devices::TempSensor;
 use drv_i2c_devices::{
     sbtsi::Sbtsi, tmp117::Tmp117, tmp451::Tmp451, tse2004av::Tse2004Av,
 };
+use pid::PidControl;
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
 use task_sensor_api::{Sensor as SensorApi, SensorId};
 use userlib::units::{Celsius, PWMDuty, Rpm};
@@ -86,6 +87,10 @@ pub(crate) struct InputChannel {
     /// Maximum temperature for this part
     max_temp: Celsius,
 
+    /// Target temperature margin. This must be >= 0; as it increases, the
+    /// part is kept cooler than its max temperature rating.
+    target_margin: Celsius,
+
     /// Mask with bits set based on the Bsp's `power_mode` bits
     power_mode_mask: u32,
 
@@ -97,12 +102,15 @@ impl InputChannel {
     pub fn new(
         sensor: TemperatureSensor,
         max_temp: Celsius,
+        target_margin: Celsius,
         power_mode_mask: u32,
         removable: bool,
     ) -> Self {
+        assert!(target_margin.0 >= 0.0);
         Self {
             sensor,
             max_temp,
+            target_margin,
             power_mode_mask,
             removable,
         }
@@ -117,23 +125,10 @@ impl InputChannel {
 /// elsewhere; the standard pattern is to create static arrays in a
 /// `struct Bsp` which is conditionally included based on board name.
 ///
-/// ```
-///         |
-///   HOT   |   Fan speed increases quickly
-///         |
-///         0------ Component margin + target_margin --
-///         |
-///         |   Fan speed increases slowly
-///         |
-///         ------- slow_band -------------------------
-///         |
-///         |   Fan speed remains constant
-///         |
-///         ------- dead_band -------------------------
-///         |
-///   COLD  |   Fan speed decreases slowly
-///         |
-/// ```
+/// Fan speed is set by a PID controller, tuned by the BSP's `PID_CONFIG`.
+/// Its input is the error of the hottest part, relative to that part's
+/// target: how far it is above `max_temp - target_margin`.  Parts which
+/// aren't powered in the current power mode are left out.
 ///
 pub(crate) struct ThermalControl<'a, B> {
     /// Reference to board-specific parameters
@@ -142,15 +137,8 @@ pub(crate) struct ThermalControl<'a, B> {
     /// Task to which we should post sensor data updates
     sensor_api: SensorApi,
 
-    /// Dead band between increasing and decreasing fan speed
-    dead_band: Celsius,
-
-    /// Band where fan speed increases slowly
-    slow_band: Celsius,
-
-    /// Target temperature margin. This must be >= 0; as it increases, parts
-    /// are kept cooler than their max temperature ratings.
-    target_margin: Celsius,
+    /// Controller which turns temperature error into fan PWM
+    pid: PidControl,
 
     /// Commanded PWM value (0-100) for every output channel
     target_pwm: u8,
@@ -163,15 +151,14 @@ impl<'a, B: BspT> ThermalControl<'a, B> {
     /// Constructs a new `ThermalControl` based on a `struct Bsp`. This
     /// requires that every BSP has the same internal structure,
     pub fn new(bsp: &'a B, sensor_api: SensorApi) -> Self {
-        let dead_band = Celsius(2.0f32);
-        let slow_band = Celsius(1.0f32);
-        assert!(dead_band.0 > slow_band.0);
+        let cfg = B::PID_CONFIG;
+        assert!(cfg.min_output >= 0.0 && cfg.max_output <= 100.0);
+        let mut pid = PidControl::new(cfg);
+        pid.reset(100.0);
         Self {
             bsp,
             sensor_api,
-            dead_band,
-            slow_band,
-            target_margin: Celsius(2.0f32),
+            pid,
             target_pwm: 100,
             read_failed_count: 0,
             post_failed_count: 0,
@@ -179,9 +166,9 @@ impl<'a, B: BspT> ThermalControl<'a, B> {
     }
 
     /// Reads all temperature and fan RPM sensors, posting their results
-    /// to the sensors task API. Returns the worst margin; positive means
-    /// all parts are happily below their max temperatures, while negative
-    /// means someone is overheating.
+    /// to the sensors task API. Returns the worst margin, relative to each
+    /// part's target margin; positive means all parts are happily below
+    /// their targets, while negative means someone is running hot.
     ///
     /// Records failed reads to non-controlled sensors and failed posts to the
     /// sensors task in `self.read_failed_count` and `self.post_failed_count`
@@ -226,7 +213,7 @@ impl<'a, B: BspT> ThermalControl<'a, B> {
         }
 
         // Remember, positive margin means that all parts are happily below
-        // their max temperature; negative means someone is overheating.
+        // their target temperature; negative means someone is running hot.
         let mut worst_margin = None;
         let mut last_err = Ok(());
         let power_mode = self.bsp.power_mode();
@@ -234,7 +221,7 @@ impl<'a, B: BspT> ThermalControl<'a, B> {
             let post_result = match s.sensor.read_temp() {
                 Ok(v) => {
                     if (s.power_mode_mask & power_mode) != 0 {
-                        let margin = s.max_temp.0 - v.0;
+                        let margin = s.max_temp.0 - s.target_margin.0 - v.0;
                         worst_margin = Some(match worst_margin {
                             Some(m) => margin.min(m),
                             None => margin,
@@ -267,27 +254,25 @@ impl<'a, B: BspT> ThermalControl<'a, B> {
         Ok(worst_margin)
     }
 
-    /// An extremely simple thermal control loop.
+    /// Runs one step of the thermal control loop.
     ///
     /// Returns an error if the control loop failed to read critical sensors;
     /// the caller should set us to some kind of fail-safe mode if this
     /// occurs.
     pub fn run_control(&mut self) -> Result<(), ThermalError> {
-        let mut r = self
+        let margin = self
             .read_sensors()
             .map_err(|_| ThermalError::DeviceError)?
             .ok_or(ThermalError::NoReading)?;
 
-        r -= self.target_margin.0;
-        if r < 0.0f32 {
-            self.target_pwm = (self.target_pwm + 5).min(100);
-        } else if r < self.slow_band.0 {
-            self.target_pwm = (self.target_pwm + 1).min(100);
-        } else if r < self.dead_band.0 {
-            // No change
-        } else {
-            self.target_pwm = self.target_pwm.saturating_sub(1);
-        }
+        // The controller wants an error that's positive when the fans need
+        // to speed up, i.e. when we're short of our margin.
+        ringbuf_entry!(Trace::ControlError(-margin));
+        let pwm = self.pid.run(-margin);
+
+        // We checked in `new` that the output range is within 0-100, so
+        // this just rounds to the nearest percent.
+        self.target_pwm = (pwm + 0.5) as u8;
 
         // Send the new RPM to all of our fans
         ringbuf_entry!(Trace::ControlPwm(self.target_pwm));
@@ -303,6 +288,7 @@ impl<'a, B: BspT> ThermalControl<'a, B> {
         if initial_pwm.0 > 100 {
             return Err(ThermalError::InvalidPWM);
         }
+        self.pid.reset(initial_pwm.0.into());
         self.target_pwm = initial_pwm.0;
         Ok(())
     }
@@ -4,9 +4,10 @@
 
 //! Thermal loop
 //!
-//! This is a primordial thermal loop, which will ultimately reading temperature
-//! sensors and control fan duty cycles to actively manage thermals.  Right now,
-//! though it is merely reading every fan and temp sensor that it can find...
+//! This reads every fan and temp sensor that it can find, posting readings to
+//! the `sensor` task.  In automatic mode, it also drives fan duty cycles from
+//! the temperatures of the parts listed as inputs in the BSP, using a PID
+//! controller (see the `pid` crate) tuned by the BSP.
 //!
 
 #![no_std]
@@ -54,6 +55,7 @@ enum Trace {
     FanReadFailed(usize, ResponseCode),
     MiscReadFailed(usize, ResponseCode),
     SensorReadFailed(usize, ResponseCode),
+    ControlError(f32),
     ControlPwm(u8),
 }
 ringbuf!(Trace, 32, Trace::None);
@@ -36,6 +36,10 @@ pub enum AssistOp {
     RefreshTaskIdOffByOne = 21,
     RefreshTaskIdOffByMany = 22,
     ReadNotifications = 23,
+    BadTimer = 24,
+    SendTo = 25,
+    ReadShared = 26,
+    WriteShared = 27,
 }
 
 /// Operations that are performed by the test-suite
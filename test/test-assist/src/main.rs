@@ -13,6 +13,9 @@ use test_api::*;
 use userlib::*;
 use zerocopy::AsBytes;
 
+// Memory the test suite can write, and we can only read.
+shared_region!(SHARED, test_shared);
+
 #[inline(never)]
 fn badread(arg: u32) {
     unsafe {
@@ -45,9 +48,11 @@ fn execdata(_arg: u32) {
     }
 }
 
+#[cfg(not(hubris_sim))]
 static BXLR: [u16; 1] = [0x4770u16];
 
 #[inline(never)]
+#[cfg(not(hubris_sim))]
 fn illop(_arg: u32) {
     unsafe {
         // This should attempt to execute with the Thumb bit clear, so
@@ -108,10 +113,31 @@ fn busfault(_arg: u32) {
 fn illinst(_arg: u32) {
     unsafe {
         // an illegal instruction
+        #[cfg(not(hubris_sim))]
         asm!("udf 0xde");
+        // (not ud2, which is how the simulator makes syscalls)
+        #[cfg(hubris_sim)]
+        asm!("ud1 eax, eax");
     }
 }
 
+#[inline(never)]
+fn badtimer(arg: u32) {
+    sys_set_timer_n(arg as u16, None, 0);
+}
+
+#[inline(never)]
+fn writeshared(arg: u32) {
+    unsafe {
+        (SHARED.as_ptr() as *mut u32).write_volatile(arg);
+    }
+}
+
+#[inline(never)]
+fn sendto(arg: u32) {
+    sys_send(TaskId(arg as u16), 0, &[], &mut [], &[]);
+}
+
 #[inline(never)]
 #[cfg(any(armv7m, armv8m))]
 fn divzero(_arg: u32) {
@@ -158,12 +184,16 @@ fn main() -> ! {
         (AssistOp::DivZero, divzero),
         (AssistOp::StackOverflow, stackblow),
         (AssistOp::ExecData, execdata),
+        #[cfg(not(hubris_sim))]
         (AssistOp::IllegalOperation, illop),
         (AssistOp::BadExec, badexec),
         (AssistOp::TextOutOfBounds, textoob),
         (AssistOp::StackOutOfBounds, stackoob),
         (AssistOp::BusError, busfault),
         (AssistOp::IllegalInstruction, illinst),
+        (AssistOp::BadTimer, badtimer),
+        (AssistOp::SendTo, sendto),
+        (AssistOp::WriteShared, writeshared),
     ];
 
     const ALL_NOTIFICATIONS: u32 = !0;
@@ -205,6 +235,12 @@ fn main() -> ! {
                     AssistOp::LastReply => {
                         caller.reply(last_reply);
                     }
+                    AssistOp::ReadShared => {
+                        let value = unsafe {
+                            (SHARED.as_ptr() as *const u32).read_volatile()
+                        };
+                        caller.reply(value);
+                    }
                     AssistOp::Store => {
                         caller.reply(stored_value);
                         stored_value = *msg;
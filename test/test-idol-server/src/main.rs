@@ -5,7 +5,7 @@
 #![no_std]
 #![no_main]
 
-use idol_runtime::RequestError;
+use idol_runtime::{Leased, RequestError, R};
 use test_idol_api::{FancyTestType, IdolTestError, SocketName, UdpMetadata};
 use userlib::*;
 
@@ -79,6 +79,18 @@ impl idl::InOrderIdolTestImpl for ServerImpl {
     ) -> Result<u16, RequestError<IdolTestError>> {
         Ok(b.vid)
     }
+    fn sum_bytes(
+        &mut self,
+        _: &RecvMessage,
+        data: Leased<R, [u8]>,
+    ) -> Result<u32, RequestError<IdolTestError>> {
+        let mut sum = 0u32;
+        for i in 0..data.len() {
+            let b = data.read_at(i).ok_or_else(RequestError::went_away)?;
+            sum += u32::from(b);
+        }
+        Ok(sum)
+    }
 }
 
 #[export_name = "main"]
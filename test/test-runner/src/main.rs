@@ -61,7 +61,8 @@
 //!
 //! # Output
 //!
-//! Output is produced on ITM stimulus port 8. Output is in a line-oriented
+//! Output is produced on ITM stimulus port 8 (or, on the simulator, on the
+//! host's standard error). Output is in a line-oriented
 //! human-readable format modeled after report formats like TAP, but avoiding
 //! some issues.
 //!
@@ -93,7 +94,23 @@ use zerocopy::AsBytes;
 use armv6m_atomic_hack::*;
 
 cfg_if::cfg_if! {
-    if #[cfg(armv6m)] {
+    if #[cfg(hubris_sim)] {
+        /// Helper macro for producing output on the simulator's console.
+        macro_rules! test_output {
+            ($s:expr) => {
+                {
+                    use core::fmt::Write;
+                    let _ = writeln!(userlib::HostConsole, $s);
+                }
+            };
+            ($s:expr, $($tt:tt)*) => {
+                {
+                    use core::fmt::Write;
+                    let _ = writeln!(userlib::HostConsole, $s, $($tt)*);
+                }
+            };
+        }
+    } else if #[cfg(armv6m)] {
         /// Helper macro for producing output by semihosting :-(
         macro_rules! test_output {
             ($s:expr) => {
@@ -51,6 +51,9 @@ task_config::task_config! {
 // Actual list of functions with their names.
 test_cases! {
     test_send,
+    test_send_timeout,
+    test_send_async,
+    test_send_async_queued,
     test_recv_reply,
     test_recv_reply_fault,
     #[cfg(any(armv7m, armv8m))]
@@ -60,13 +63,23 @@ test_cases! {
     #[cfg(any(armv7m, armv8m))]
     test_floating_point_fault,
     test_fault_badmem,
+    // The simulator has no MPU, so it can't detect stack overflow as such,
+    // can't stop execution of data, and has no bus to fault; nor does x86 have
+    // ARM's "invalid operation" fault.
+    #[cfg(not(hubris_sim))]
     test_fault_stackoverflow,
+    #[cfg(not(hubris_sim))]
     test_fault_execdata,
+    #[cfg(not(hubris_sim))]
     test_fault_illop,
+    #[cfg(not(hubris_sim))]
     test_fault_nullexec,
     test_fault_textoob,
     test_fault_stackoob,
+    #[cfg(not(hubris_sim))]
     test_fault_buserror,
+    #[cfg(not(hubris_sim))]
+    test_fault_shared_readonly,
     test_fault_illinst,
     #[cfg(any(armv7m, armv8m))]
     test_fault_divzero,
@@ -78,19 +91,26 @@ test_cases! {
     test_fault_badinjection,
     test_fault_superinjection,
     test_fault_selfinjection,
+    test_fault_badtimer,
+    test_fault_caller_not_allowed,
     test_panic,
     test_restart,
     test_restart_taskgen,
     test_borrow_info,
     test_borrow_read,
     test_borrow_write,
+    test_borrow_forwarded,
     test_borrow_without_peer_waiting,
+    test_shared_region,
     test_supervisor_fault_notification,
     test_timer_advance,
     test_timer_notify,
     test_timer_notify_past,
+    test_timer_multiple,
     test_task_config,
     test_task_status,
+    test_stack_usage,
+    test_irq_stats_unknown_irq,
     test_task_fault_injection,
     test_refresh_task_id_basic,
     test_refresh_task_id_off_by_one,
@@ -127,6 +147,137 @@ fn test_send() {
     assert_eq!(response, !0xDEADBEEF);
 }
 
+/// Tests that a send with a timeout gives up on a peer that isn't receiving,
+/// and otherwise behaves like a normal send.
+fn test_send_timeout() {
+    let assist = assist_task_id();
+
+    // Get the assistant blocked sending to us, so it can't receive.
+    let challenge = 0xCAFE_F00Du32;
+    let mut response = 0_u32;
+    let (rc, _len) = sys_send(
+        assist,
+        AssistOp::SendBack as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+
+    let start = sys_get_timer().now;
+    let (rc, len) = sys_send_timeout(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+        10,
+    );
+    assert_eq!(rc, TIMED_OUT);
+    assert_eq!(len, 0);
+    assert!(sys_get_timer().now >= start + 10);
+
+    // Let the assistant go again.
+    let rm = sys_recv_open(response.as_bytes_mut(), 0);
+    assert_eq!(rm.sender, assist);
+    sys_reply(assist, 0, &[]);
+
+    // Now that it's listening, the timeout shouldn't get in the way.
+    let (rc, len) = sys_send_timeout(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+        10,
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, !challenge);
+}
+
+/// Tests that an asynchronous send gets its reply, and notifies us when it
+/// does.
+fn test_send_async() {
+    const REPLY_NOTIFICATION: u32 = 1 << 16;
+
+    let assist = assist_task_id();
+    let challenge = 0xDEADBEEF_u32.to_le_bytes();
+    let mut response = 0_u32;
+    // Safety: we don't touch `response` until we've collected the reply.
+    unsafe {
+        sys_send_async(
+            assist,
+            AssistOp::JustReply as u16,
+            &challenge,
+            response.as_bytes_mut(),
+            16,
+        )
+    }
+    .unwrap();
+
+    let rm =
+        sys_recv_closed(&mut [], REPLY_NOTIFICATION, TaskId::KERNEL).unwrap();
+    assert_eq!(rm.operation, REPLY_NOTIFICATION);
+    assert_eq!(
+        kipc::collect_async_reply(),
+        AsyncSendStatus::Done { code: 0, len: 4 }
+    );
+    assert_eq!(response, !0xDEADBEEF);
+
+    // Collecting the reply frees us up for another send.
+    assert_eq!(kipc::collect_async_reply(), AsyncSendStatus::Idle);
+}
+
+/// Tests that an asynchronous send to a task that isn't receiving waits for
+/// it, without blocking us.
+fn test_send_async_queued() {
+    const REPLY_NOTIFICATION: u32 = 1 << 16;
+
+    let assist = assist_task_id();
+
+    // Get the assistant blocked sending to us, so it can't receive.
+    let challenge = 0xCAFE_F00Du32.to_le_bytes();
+    let mut response = 0_u32;
+    let (rc, _len) = sys_send(
+        assist,
+        AssistOp::SendBack as u16,
+        &challenge,
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+
+    let mut async_response = 0_u32;
+    // Safety: we don't touch `async_response` until we've collected the
+    // reply.
+    unsafe {
+        sys_send_async(
+            assist,
+            AssistOp::JustReply as u16,
+            &challenge,
+            async_response.as_bytes_mut(),
+            16,
+        )
+    }
+    .unwrap();
+    assert_eq!(kipc::collect_async_reply(), AsyncSendStatus::Pending);
+
+    // Let the assistant go again, at which point it can pick up our message.
+    let rm = sys_recv_open(response.as_bytes_mut(), 0);
+    assert_eq!(rm.sender, assist);
+    sys_reply(assist, 0, &[]);
+
+    let rm =
+        sys_recv_closed(&mut [], REPLY_NOTIFICATION, TaskId::KERNEL).unwrap();
+    assert_eq!(rm.operation, REPLY_NOTIFICATION);
+    assert_eq!(
+        kipc::collect_async_reply(),
+        AsyncSendStatus::Done { code: 0, len: 4 }
+    );
+    assert_eq!(async_response, !0xCAFE_F00D);
+}
+
 /// Tests that we can receive a message from the assistant and reply.
 fn test_recv_reply() {
     let assist = assist_task_id();
@@ -286,6 +437,7 @@ fn test_fault_badmem() {
     );
 }
 
+#[cfg(not(hubris_sim))]
 fn test_fault_stackoverflow() {
     let fault = test_fault(AssistOp::StackOverflow, 0);
 
@@ -299,10 +451,12 @@ fn test_fault_stackoverflow() {
     }
 }
 
+#[cfg(not(hubris_sim))]
 fn test_fault_execdata() {
     assert_fault_eq!(test_fault(AssistOp::ExecData, 0), FaultInfo::IllegalText);
 }
 
+#[cfg(not(hubris_sim))]
 fn test_fault_illop() {
     let fault = test_fault(AssistOp::IllegalOperation, 0);
 
@@ -314,6 +468,7 @@ fn test_fault_illop() {
     }
 }
 
+#[cfg(not(hubris_sim))]
 fn test_fault_nullexec() {
     assert_fault_eq!(
         test_fault(AssistOp::BadExec, BAD_ADDRESS),
@@ -346,6 +501,7 @@ fn test_fault_stackoob() {
     }
 }
 
+#[cfg(not(hubris_sim))]
 fn test_fault_buserror() {
     let fault = test_fault(AssistOp::BusError, 0);
 
@@ -426,6 +582,37 @@ fn test_fault_selfinjection() {
     );
 }
 
+fn test_fault_badtimer() {
+    assert_eq!(
+        test_fault(AssistOp::BadTimer, u32::from(u16::MAX)),
+        FaultInfo::SyscallUsage(UsageError::TimerOutOfRange)
+    );
+}
+
+/// Tests that a task can't send to a server that doesn't list it as an
+/// allowed caller. The test apps only let us talk to the Idol server.
+fn test_fault_caller_not_allowed() {
+    assert_eq!(
+        test_fault(AssistOp::SendTo, u32::from(IDOL.get_task_id().0)),
+        FaultInfo::SyscallUsage(UsageError::CallerNotAllowed)
+    );
+}
+
+/// Tests that a task can't write to a shared region it's only allowed to
+/// read.
+#[cfg(not(hubris_sim))]
+fn test_fault_shared_readonly() {
+    let fault = test_fault(AssistOp::WriteShared, 0);
+
+    assert_fault_eq!(
+        fault,
+        FaultInfo::MemoryAccess {
+            address: Some(SHARED.as_ptr() as u32),
+            source: FaultSource::User,
+        }
+    );
+}
+
 /// Tests that a `panic!` in a task is recorded as a fault.
 fn test_panic() {
     let assist = assist_task_id();
@@ -1072,6 +1259,72 @@ fn test_borrow_write() {
     );
 }
 
+/// Tests that a lease can be forwarded: the assistant lends us `b"hello"`, and
+/// we pass it on to the Idol server, which reads it directly.
+fn test_borrow_forwarded() {
+    // Op numbers are assigned in the order ops appear in `api.idol`, starting
+    // at 1; we can't use the generated client because it won't forward.
+    const IDOL_SUM_BYTES: u16 = 9;
+
+    let assist = assist_task_id();
+
+    // Ask the assistant to call us back with two particularly shaped loans
+    // (which are hardcoded in the assistant, not encoded here).
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::SendBackWithLoans as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+            let client = caller.task_id();
+            let idol = IDOL.get_task_id();
+
+            // Forward all of borrow #1. Asking for write access doesn't get
+            // the Idol server any more than the read access we were given.
+            let mut sum = 0_u32;
+            let (rc, len) = sys_send(
+                idol,
+                IDOL_SUM_BYTES,
+                &[],
+                sum.as_bytes_mut(),
+                &[Lease::forward(
+                    client,
+                    1,
+                    LeaseAttributes::READ | LeaseAttributes::WRITE,
+                    usize::MAX,
+                )],
+            );
+            assert_eq!(rc, 0);
+            assert_eq!(len, 4);
+            assert_eq!(sum, b"hello".iter().map(|&b| u32::from(b)).sum());
+
+            // Forward just the first three bytes.
+            let (rc, len) = sys_send(
+                idol,
+                IDOL_SUM_BYTES,
+                &[],
+                sum.as_bytes_mut(),
+                &[Lease::forward(client, 1, LeaseAttributes::READ, 3)],
+            );
+            assert_eq!(rc, 0);
+            assert_eq!(len, 4);
+            assert_eq!(sum, b"hel".iter().map(|&b| u32::from(b)).sum());
+
+            caller.reply(0);
+            Ok(())
+        },
+    );
+}
+
 /// Tests the three borrow syscalls on a task that is not waiting in reply,
 /// which should return `DEFECT` but not cause either task to fault.
 fn test_borrow_without_peer_waiting() {
@@ -1097,6 +1350,30 @@ fn test_borrow_without_peer_waiting() {
     assert_eq!(initial_id, new_id, "id should not change");
 }
 
+/// Tests that a write to a shared region is visible to the other task it's
+/// mapped into.
+fn test_shared_region() {
+    let assist = assist_task_id();
+    assert!(SHARED.len() >= 4);
+
+    let value = 0x5AFE_F00D_u32;
+    unsafe {
+        (SHARED.as_ptr() as *mut u32).write_volatile(value);
+    }
+
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::ReadShared as u16,
+        &0_u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, value);
+}
+
 /// Tests that faults in tasks are reported to the supervisor.
 ///
 /// NOTE: this test depends on the supervisor fault mask, set in the test's
@@ -1182,6 +1459,42 @@ fn test_timer_notify_past() {
     assert_eq!(rm.lease_count, 0);
 }
 
+/// Tests that a task's timers are independent of each other. This relies on
+/// the test apps configuring at least two timers per task.
+fn test_timer_multiple() {
+    const FIRST_NOTIFICATION: u32 = 1 << 16;
+    const SECOND_NOTIFICATION: u32 = 1 << 17;
+
+    let start_time = sys_get_timer().now;
+    // Leave plenty of room between the two, so that the first can't fire
+    // while we're checking on the second.
+    let first = start_time + 50;
+    let second = start_time + 2;
+    sys_set_timer_n(0, Some(first), FIRST_NOTIFICATION);
+    sys_set_timer_n(1, Some(second), SECOND_NOTIFICATION);
+
+    // Each timer reports its own settings.
+    let state = sys_get_timer_n(0);
+    assert_eq!(state.deadline, Some(first));
+    assert_eq!(state.on_dl, FIRST_NOTIFICATION);
+    let state = sys_get_timer_n(1);
+    assert_eq!(state.deadline, Some(second));
+    assert_eq!(state.on_dl, SECOND_NOTIFICATION);
+
+    // The second timer is due first, and firing it leaves the first alone.
+    let rm =
+        sys_recv_closed(&mut [], SECOND_NOTIFICATION, TaskId::KERNEL).unwrap();
+    assert_eq!(rm.operation, SECOND_NOTIFICATION);
+    assert_eq!(sys_get_timer_n(1).deadline, None);
+    assert_eq!(sys_get_timer_n(0).deadline, Some(first));
+
+    let rm =
+        sys_recv_closed(&mut [], FIRST_NOTIFICATION, TaskId::KERNEL).unwrap();
+    assert_eq!(rm.operation, FIRST_NOTIFICATION);
+    assert!(sys_get_timer().now >= first);
+    assert_eq!(sys_get_timer().deadline, None);
+}
+
 /// Tests that floating point registers are properly saved and restored
 #[cfg(any(armv7m, armv8m))]
 fn test_floating_point(highregs: bool) {
@@ -1292,6 +1605,21 @@ fn test_task_status() {
     }
 }
 
+/// Tests that the kernel can tell us how much of the assistant's stack it has
+/// used. The assistant has certainly run by now, so it's used some.
+fn test_stack_usage() {
+    let usage = kipc::read_stack_usage(ASSIST.get_task_index().into());
+    assert!(usage.size > 0);
+    assert!(usage.max_used > 0);
+    assert!(usage.max_used <= usage.size);
+}
+
+/// Tests that the kernel has no statistics for an interrupt that no task
+/// owns. (The test image has no interrupts we can fire at will.)
+fn test_irq_stats_unknown_irq() {
+    assert_eq!(kipc::read_irq_stats(u32::MAX), None);
+}
+
 fn test_task_fault_injection() {
     // Assistant should be fine
     let status = kipc::read_task_status(ASSIST.get_task_index().into());
@@ -1402,6 +1730,9 @@ task_slot!(IDOL, idol);
 task_slot!(SUITE, suite);
 task_slot!(RUNNER, runner);
 
+// Memory shared with the assistant, which can only read it.
+shared_region!(SHARED, test_shared);
+
 /// Gets the current expected `TaskId` for the assistant.
 fn assist_task_id() -> TaskId {
     ASSIST.get_task_id()